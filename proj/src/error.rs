@@ -45,6 +45,12 @@ pub enum ErrorKind {
 	/// Errors from the Bincode crate
 	#[fail(display = "Bincode Error: {}", _0)]
 	BincodeError(bincode::Error),
+	/// Project file couldn't be decoded
+	#[fail(
+		display = "Unable to read project file, which may have been created by an older version of Aloevera and need recreating: {}",
+		_0
+	)]
+	IncompatibleProject(String),
 	/// Other
 	#[fail(display = "Generic error: {}", _0)]
 	GenericError(String),
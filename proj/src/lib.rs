@@ -29,9 +29,11 @@ extern crate aloevera_vera as vera;
 
 mod error;
 mod project;
+mod usage;
 pub use error::{Error, ErrorKind};
 
 pub use project::AloeVeraProject;
+pub use usage::{PaletteEntryUsage, PaletteEntryUser, PaletteUsageReport};
 
 /// And around the binary version
 pub trait Binable {
@@ -14,9 +14,9 @@
 //! Top Level Project file definition
 
 use crate::Binable;
-use crate::Error;
+use crate::{Error, ErrorKind};
 use std::collections::BTreeMap;
-use vera::{VeraBitmap, VeraImageSet, VeraPalette, VeraSprite, VeraTileMap};
+use vera::{VeraBitmap, VeraImageSet, VeraPalette, VeraPaletteFade, VeraSprite, VeraTileMap};
 
 /// Top level project file definition
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -33,6 +33,8 @@ pub struct AloeVeraProject<'a> {
 	pub sprites: BTreeMap<String, VeraSprite<'a>>,
 	/// Bitmaps, which are bounds-checking wrappers around Imagesets
 	pub bitmaps: BTreeMap<String, VeraBitmap<'a>>,
+	/// Palette fades, precomputed from palettes
+	pub palette_fades: BTreeMap<String, VeraPaletteFade>,
 }
 
 impl<'a> Binable for AloeVeraProject<'a> {
@@ -42,7 +44,11 @@ impl<'a> Binable for AloeVeraProject<'a> {
 	}
 
 	fn from_bin(encoded: &Vec<u8>) -> Result<Box<Self>, Error> {
-		let decoded = bincode::deserialize(&encoded[..])?;
+		// The layout changes between versions, so older files may not decode
+		let decoded = match bincode::deserialize(&encoded[..]) {
+			Ok(d) => d,
+			Err(e) => return Err(ErrorKind::IncompatibleProject(format!("{}", e)).into()),
+		};
 		Ok(Box::new(decoded))
 	}
 }
@@ -57,6 +63,21 @@ impl<'a> AloeVeraProject<'a> {
 			tilemaps: BTreeMap::new(),
 			sprites: BTreeMap::new(),
 			bitmaps: BTreeMap::new(),
+			palette_fades: BTreeMap::new(),
 		}
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_project_from_bin() {
+		let encoded = AloeVeraProject::new("project").to_bin().unwrap();
+		assert_eq!(AloeVeraProject::from_bin(&encoded).unwrap().id, "project");
+		let truncated = encoded[..encoded.len() - 1].to_vec();
+		let err = AloeVeraProject::from_bin(&truncated).unwrap_err();
+		assert!(format!("{}", err).contains("older version of Aloevera"));
+	}
+}
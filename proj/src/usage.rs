@@ -0,0 +1,329 @@
+// Copyright 2020 Revcore Technologies Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Report on which assets in a project reference each palette entry
+
+use crate::AloeVeraProject;
+use crate::{Error, ErrorKind};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use vera::{VeraImage, VeraImageSet, VeraPixelDepth, VeraTileMapEntry};
+
+/// An asset using a palette entry, and which of its frames
+/// (or tiles, for tilemaps) do so
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PaletteEntryUser {
+	/// Asset type, e.g. `imageset`
+	pub kind: String,
+	/// Asset id
+	pub id: String,
+	/// Frame or tile indices within the asset
+	pub frames: Vec<usize>,
+}
+
+/// Usage of a single palette entry
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PaletteEntryUsage {
+	/// Palette index
+	pub index: usize,
+	/// Colour as VERA will display it, e.g. `#f0a`, or `None`
+	/// for an empty slot
+	pub color: Option<String>,
+	/// Assets using this entry
+	pub users: Vec<PaletteEntryUser>,
+}
+
+/// Usage of every entry in a palette
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PaletteUsageReport {
+	/// Palette id
+	pub palette_id: String,
+	/// Per entry usage
+	pub entries: Vec<PaletteEntryUsage>,
+	/// Occupied entries no asset uses
+	pub unused: Vec<usize>,
+	/// Groups of indices holding the same colour
+	pub duplicates: Vec<Vec<usize>>,
+}
+
+/// index -> (kind, id) -> frames
+type UsageMap = BTreeMap<usize, BTreeMap<(String, String), BTreeSet<usize>>>;
+
+fn record(usage: &mut UsageMap, index: usize, kind: &str, id: &str, frame: usize) {
+	usage
+		.entry(index)
+		.or_default()
+		.entry((kind.to_owned(), id.to_owned()))
+		.or_default()
+		.insert(frame);
+}
+
+fn record_frame(
+	usage: &mut UsageMap,
+	frame: &VeraImage,
+	offset: usize,
+	kind: &str,
+	id: &str,
+	frame_index: usize,
+) {
+	for p in frame.data.iter().filter(|p| !p.is_transparent) {
+		if let Some(i) = p.pal_index {
+			record(usage, offset + i as usize, kind, id, frame_index);
+		}
+	}
+}
+
+fn uses_palette(imageset: &VeraImageSet, palette_id: &str) -> bool {
+	imageset.formatted
+		&& imageset.depth != Some(VeraPixelDepth::BPP1)
+		&& imageset.palette_id.as_deref() == Some(palette_id)
+}
+
+impl PaletteUsageReport {
+	/// Walk all imagesets formatted against the palette, along with the
+	/// sprites, bitmaps and tilemaps built on them. Text mode tilemaps index
+	/// the palette directly, so their colours are counted against any palette
+	pub fn from_project(project: &AloeVeraProject, palette_id: &str) -> Result<Self, Error> {
+		let palette = match project.palettes.get(palette_id) {
+			Some(p) => p,
+			None => {
+				let msg = format!("Palette with id `{}` not found", palette_id);
+				return Err(ErrorKind::ArgumentError(msg).into());
+			}
+		};
+		let mut usage = UsageMap::new();
+		for imageset in project.imagesets.values() {
+			if !uses_palette(imageset, palette_id) {
+				continue;
+			}
+			for (i, f) in imageset.frame_data.iter().enumerate() {
+				record_frame(
+					&mut usage,
+					f,
+					f.pal_offset as usize,
+					"imageset",
+					&imageset.id,
+					i,
+				);
+			}
+		}
+		let wrappers = project
+			.sprites
+			.values()
+			.map(|s| ("sprite", &s.id, &s.imageset_id))
+			.chain(
+				project
+					.bitmaps
+					.values()
+					.map(|b| ("bitmap", &b.id, &b.imageset_id)),
+			);
+		for (kind, id, imageset_id) in wrappers {
+			let imageset = match project.imagesets.get(imageset_id) {
+				Some(i) if uses_palette(i, palette_id) => i,
+				_ => continue,
+			};
+			for (i, f) in imageset.frame_data.iter().enumerate() {
+				record_frame(&mut usage, f, f.pal_offset as usize, kind, id, i);
+			}
+		}
+		for tilemap in project.tilemaps.values() {
+			let imageset = project
+				.imagesets
+				.get(&tilemap.imageset_id)
+				.filter(|i| uses_palette(i, palette_id));
+			for (i, t) in tilemap.get_tiles().iter().enumerate() {
+				match t {
+					VeraTileMapEntry::Text0(_, fg, bg) => {
+						record(&mut usage, *fg as usize, "tilemap", &tilemap.id, i);
+						record(&mut usage, *bg as usize, "tilemap", &tilemap.id, i);
+					}
+					VeraTileMapEntry::Text1(_, fg) => {
+						record(&mut usage, *fg as usize, "tilemap", &tilemap.id, i);
+						record(&mut usage, 0, "tilemap", &tilemap.id, i);
+					}
+					VeraTileMapEntry::Tile234(index, offset, _, _) => {
+						let frame = match imageset.map(|s| s.frame_at(*index as usize)) {
+							Some(Ok(f)) => f,
+							_ => continue,
+						};
+						record_frame(
+							&mut usage,
+							frame,
+							*offset as usize,
+							"tilemap",
+							&tilemap.id,
+							i,
+						);
+					}
+				}
+			}
+		}
+
+		let mut entries = vec![];
+		let mut unused = vec![];
+		let mut colors: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+		for (index, (r, g, b)) in palette.to_rgb8().into_iter().enumerate() {
+			let color = match palette.is_slot_occupied(index) {
+				true => Some(format!("#{:x}{:x}{:x}", r >> 4, g >> 4, b >> 4)),
+				false => None,
+			};
+			let users: Vec<PaletteEntryUser> = usage
+				.remove(&index)
+				.unwrap_or_default()
+				.into_iter()
+				.map(|((kind, id), frames)| PaletteEntryUser {
+					kind,
+					id,
+					frames: frames.into_iter().collect(),
+				})
+				.collect();
+			if let Some(c) = color.as_ref() {
+				colors.entry(c.clone()).or_default().push(index);
+				if users.is_empty() {
+					unused.push(index);
+				}
+			}
+			entries.push(PaletteEntryUsage {
+				index,
+				color,
+				users,
+			});
+		}
+		let mut duplicates: Vec<Vec<usize>> =
+			colors.into_values().filter(|v| v.len() > 1).collect();
+		duplicates.sort();
+		Ok(PaletteUsageReport {
+			palette_id: palette_id.to_owned(),
+			entries,
+			unused,
+			duplicates,
+		})
+	}
+
+	/// Output the report as JSON
+	pub fn to_json(&self) -> Result<String, Error> {
+		serde_json::to_string_pretty(self).map_err(|e| ErrorKind::JSONError(e.to_string()).into())
+	}
+}
+
+/// Sorted indices as a list, with consecutive runs shown as ranges
+fn fmt_indices(indices: &[usize]) -> String {
+	let mut runs: Vec<(usize, usize)> = vec![];
+	for i in indices.iter() {
+		match runs.last_mut() {
+			Some(r) if r.1 + 1 == *i => r.1 = *i,
+			_ => runs.push((*i, *i)),
+		}
+	}
+	let strs: Vec<String> = runs
+		.iter()
+		.map(|(s, e)| match e - s {
+			0 => format!("{}", s),
+			1 => format!("{}, {}", s, e),
+			_ => format!("{}-{}", s, e),
+		})
+		.collect();
+	strs.join(", ")
+}
+
+impl fmt::Display for PaletteUsageReport {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		writeln!(f, "Palette {} usage:", self.palette_id)?;
+		writeln!(f, "{:>5}  {:<7}  Used by", "Index", "Color")?;
+		for e in self.entries.iter() {
+			let users = match (&e.color, e.users.is_empty()) {
+				(None, _) => "".to_owned(),
+				(Some(_), true) => "(unused)".to_owned(),
+				(Some(_), false) => {
+					let strs: Vec<String> = e
+						.users
+						.iter()
+						.map(|u| format!("{} {} ({})", u.kind, u.id, fmt_indices(&u.frames)))
+						.collect();
+					strs.join(", ")
+				}
+			};
+			let color = e.color.as_deref().unwrap_or("(empty)");
+			writeln!(f, "{:>5}  {:<7}  {}", e.index, color, users)?;
+		}
+		writeln!(f, "Unused entries: {}", fmt_indices(&self.unused))?;
+		let dups: Vec<String> = self
+			.duplicates
+			.iter()
+			.map(|d| format!("[{}]", fmt_indices(d)))
+			.collect();
+		writeln!(f, "Duplicate entries: {}", dups.join(", "))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use vera::{VeraPalette, VeraPixelDepth};
+
+	#[test]
+	fn test_palette_usage_report() {
+		let (black, red, green, blue) = ((0, 0, 0), (0xff, 0, 0), (0, 0xff, 0), (0, 0, 0xff));
+		let mut palette = VeraPalette::blank("pal");
+		for (r, g, b) in [black, red, green, blue, red].iter() {
+			palette.add_entry(true, *r, *g, *b).unwrap();
+		}
+		// a red and green frame, then an all green one
+		let mut imageset = VeraImageSet::new("set", 1, 2);
+		for (i, colors) in [[red, green], [green, green]].iter().enumerate() {
+			let mut frame = VeraImage::new(&format!("set_{}", i), 1, 2);
+			for (r, g, b) in colors.iter() {
+				frame.push_pixel(*r, *g, *b, None);
+			}
+			imageset.frame_data.push(frame);
+		}
+		imageset
+			.format_indices(&palette, VeraPixelDepth::BPP8)
+			.unwrap();
+		let mut project = AloeVeraProject::new("proj");
+		project.palettes.insert("pal".to_owned(), palette.clone());
+		project.imagesets.insert("set".to_owned(), imageset.clone());
+
+		let users = |report: &PaletteUsageReport, index: usize| -> Vec<(String, Vec<usize>)> {
+			report.entries[index]
+				.users
+				.iter()
+				.map(|u| (u.id.clone(), u.frames.clone()))
+				.collect()
+		};
+		let used_by_set = |frames: Vec<usize>| vec![("set".to_owned(), frames)];
+
+		let report = PaletteUsageReport::from_project(&project, "pal").unwrap();
+		assert_eq!(report.entries.len(), 5);
+		assert_eq!(users(&report, 1), used_by_set(vec![0]));
+		assert_eq!(users(&report, 2), used_by_set(vec![0, 1]));
+		assert!(users(&report, 4).is_empty());
+		assert_eq!(report.unused, vec![0, 3, 4]);
+		assert_eq!(report.duplicates, vec![vec![1, 4]]);
+
+		// usage follows the entries once the imageset is reindexed
+		let index_map = palette.swap_entries(1, 3).unwrap();
+		assert!(imageset.reindex(&index_map));
+		project.palettes.insert("pal".to_owned(), palette);
+		project.imagesets.insert("set".to_owned(), imageset);
+		let report = PaletteUsageReport::from_project(&project, "pal").unwrap();
+		assert!(users(&report, 1).is_empty());
+		assert_eq!(users(&report, 3), used_by_set(vec![0]));
+		assert_eq!(users(&report, 2), used_by_set(vec![0, 1]));
+		assert_eq!(report.unused, vec![0, 1, 4]);
+		assert_eq!(report.duplicates, vec![vec![3, 4]]);
+
+		assert!(PaletteUsageReport::from_project(&project, "nope").is_err());
+	}
+}
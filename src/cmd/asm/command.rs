@@ -16,7 +16,11 @@ use std::iter::Iterator;
 
 use crate::cmd::common::{self, GlobalArgs};
 use crate::{Error, ErrorKind};
-use vera::{AsmFormat, Assemblable, VeraBitmap, VeraSprite};
+use proj::AloeVeraProject;
+use vera::{
+	AsmFormat, Assemblable, VeraAnimationTable, VeraBitmap, VeraPalette, VeraPaletteCycleTable,
+	VeraPalettePatch, VeraSprite,
+};
 
 const LOW_RAM_SIZE: usize = 38655;
 const LOW_RAM_WARN_THRESHOLD: f64 = 0.9;
@@ -27,6 +31,7 @@ pub struct AsmArgs {
 	pub format: AsmFormat,
 	pub sd_image: Option<String>,
 	pub conflate_tilemaps: bool,
+	pub palette_base: Option<String>,
 }
 
 fn perform_assemble<T>(
@@ -99,6 +104,24 @@ where
 	Ok(assembled_size)
 }
 
+/// Palette to diff palettes against, if outputting them as patches
+fn palette_base(proj: &AloeVeraProject, args: &AsmArgs) -> Result<Option<VeraPalette>, Error> {
+	match args.palette_base.as_deref() {
+		None => Ok(None),
+		Some("default") => Ok(Some(VeraPalette::default())),
+		Some(id) => match proj.palettes.get(id) {
+			Some(p) => Ok(Some(p.clone())),
+			None => {
+				let msg = format!(
+					"Base palette with id {} does not exist in project file.",
+					id
+				);
+				Err(ErrorKind::ArgumentError(msg).into())
+			}
+		},
+	}
+}
+
 /// Assemble
 pub fn asm_all(g_args: &GlobalArgs, mut args: AsmArgs) -> Result<(), Error> {
 	let proj = common::load_project(g_args.project_file.clone())?;
@@ -111,12 +134,50 @@ pub fn asm_all(g_args: &GlobalArgs, mut args: AsmArgs) -> Result<(), Error> {
 	if !proj.palettes.is_empty() {
 		args.out_dir = format!("{}/palettes", start_dir);
 		common::create_dir(&args.out_dir)?;
-		tot_size += perform_assemble(&mut proj.palettes.values(), &args, None, &mut line_start)?;
+		match palette_base(&proj, &args)? {
+			Some(base) => {
+				// The base palette itself is still output in full
+				let (full, patched): (Vec<&VeraPalette>, Vec<&VeraPalette>) =
+					proj.palettes.values().partition(|p| p.id == base.id);
+				let patches = patched
+					.iter()
+					.map(|p| VeraPalettePatch::from_palettes(p, &base))
+					.collect::<Result<Vec<_>, _>>()?;
+				tot_size += perform_assemble(&mut full.into_iter(), &args, None, &mut line_start)?;
+				tot_size += perform_assemble(&mut patches.iter(), &args, None, &mut line_start)?;
+			}
+			None => {
+				tot_size +=
+					perform_assemble(&mut proj.palettes.values(), &args, None, &mut line_start)?;
+			}
+		}
+		let cycle_tables: Vec<VeraPaletteCycleTable> = proj
+			.palettes
+			.values()
+			.filter_map(VeraPaletteCycleTable::from_palette)
+			.collect();
+		tot_size += perform_assemble(&mut cycle_tables.iter(), &args, None, &mut line_start)?;
+	}
+	if !proj.palette_fades.is_empty() {
+		args.out_dir = format!("{}/palette_fades", start_dir);
+		common::create_dir(&args.out_dir)?;
+		tot_size += perform_assemble(
+			&mut proj.palette_fades.values(),
+			&args,
+			None,
+			&mut line_start,
+		)?;
 	}
 	if !proj.imagesets.is_empty() {
 		args.out_dir = format!("{}/imagesets", start_dir);
 		common::create_dir(&args.out_dir)?;
 		tot_size += perform_assemble(&mut proj.imagesets.values(), &args, None, &mut line_start)?;
+		let animation_tables: Vec<VeraAnimationTable> = proj
+			.imagesets
+			.values()
+			.filter_map(VeraAnimationTable::from_imageset)
+			.collect();
+		tot_size += perform_assemble(&mut animation_tables.iter(), &args, None, &mut line_start)?;
 	}
 	if !proj.tilemaps.is_empty() {
 		args.out_dir = format!("{}/tilemaps", start_dir);
@@ -193,7 +254,14 @@ pub fn asm_select(
 	let mut line_start = 10000;
 	common::create_dir(&asm_args.out_dir)?;
 	// now look for the ID
-	if proj.palettes.contains_key(&args.asset_id) {
+	if let Some(palette) = proj.palettes.get(&args.asset_id) {
+		if let Some(base) = palette_base(&proj, &asm_args)? {
+			if base.id != palette.id {
+				let patch = VeraPalettePatch::from_palettes(palette, &base)?;
+				perform_assemble(&mut [patch].iter(), &asm_args, Some(args), &mut line_start)?;
+				return Ok(());
+			}
+		}
 		perform_assemble(
 			&mut proj.palettes.values().filter(|v| v.id == args.asset_id),
 			&asm_args,
@@ -202,6 +270,27 @@ pub fn asm_select(
 		)?;
 		return Ok(());
 	}
+	let cycle_table = proj
+		.palettes
+		.values()
+		.filter_map(VeraPaletteCycleTable::from_palette)
+		.find(|t| t.id == args.asset_id);
+	if let Some(t) = cycle_table {
+		perform_assemble(&mut [t].iter(), &asm_args, Some(args), &mut line_start)?;
+		return Ok(());
+	}
+	if proj.palette_fades.contains_key(&args.asset_id) {
+		perform_assemble(
+			&mut proj
+				.palette_fades
+				.values()
+				.filter(|v| v.id == args.asset_id),
+			&asm_args,
+			Some(args),
+			&mut line_start,
+		)?;
+		return Ok(());
+	}
 	if proj.imagesets.contains_key(&args.asset_id) {
 		perform_assemble(
 			&mut proj.imagesets.values().filter(|v| v.id == args.asset_id),
@@ -211,6 +300,15 @@ pub fn asm_select(
 		)?;
 		return Ok(());
 	}
+	let animation_table = proj
+		.imagesets
+		.values()
+		.filter_map(VeraAnimationTable::from_imageset)
+		.find(|t| t.id == args.asset_id);
+	if let Some(t) = animation_table {
+		perform_assemble(&mut [t].iter(), &asm_args, Some(args), &mut line_start)?;
+		return Ok(());
+	}
 	if proj.tilemaps.contains_key(&args.asset_id) {
 		perform_assemble(
 			&mut proj.tilemaps.values().filter(|v| v.id == args.asset_id),
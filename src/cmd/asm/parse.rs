@@ -34,11 +34,13 @@ pub fn parse_asm_args(g_args: &GlobalArgs, args: &ArgMatches) -> Result<AsmArgs,
 		None => None,
 	};
 	let conflate_tilemaps = args.is_present("conflate_tilemaps");
+	let palette_base = args.value_of("palette_base").map(|s| s.to_owned());
 	Ok(AsmArgs {
 		out_dir: out_dir.into(),
 		format: AsmFormat::from_str(asm_format)?,
 		sd_image,
 		conflate_tilemaps,
+		palette_base,
 	})
 }
 
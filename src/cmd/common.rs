@@ -15,11 +15,13 @@
 use crate::{Error, ErrorKind};
 use std::fs::{self, File};
 use std::io::{Read, Write};
+use std::path::Path;
 use util::fat;
 
 use proj::{AloeVeraProject, Binable};
 
 use clap::ArgMatches;
+use vera::{VeraImageSetLoadConfig, VeraTransparency};
 // define what to do on argument error
 
 #[macro_export]
@@ -58,6 +60,46 @@ pub fn parse_u64(arg: &str, name: &str) -> Result<u64, Error> {
 	}
 }
 
+pub fn parse_f64(arg: &str, name: &str) -> Result<f64, Error> {
+	let val = arg.parse::<f64>();
+	match val {
+		Ok(v) => Ok(v),
+		Err(e) => {
+			let msg = format!("Could not parse {} as a number. e={}", name, e);
+			Err(ErrorKind::ArgumentError(msg).into())
+		}
+	}
+}
+
+/// Parse a colour given as #rgb (as VERA stores it) or #rrggbb
+pub fn parse_rgb(v: &str) -> Result<(u8, u8, u8), Error> {
+	let hex = v.trim_start_matches('#');
+	let err = || {
+		let msg = format!("Invalid color `{}`, expected #rgb or #rrggbb", v);
+		ErrorKind::ArgumentError(msg)
+	};
+	if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+		return Err(err().into());
+	}
+	let channel = |s: &str| u8::from_str_radix(s, 16).map_err(|_| err());
+	match hex.len() {
+		3 => {
+			let mut rgb = [0u8; 3];
+			for (i, c) in rgb.iter_mut().enumerate() {
+				let n = channel(&hex[i..i + 1])?;
+				*c = n << 4 | n;
+			}
+			Ok((rgb[0], rgb[1], rgb[2]))
+		}
+		6 => Ok((
+			channel(&hex[0..2])?,
+			channel(&hex[2..4])?,
+			channel(&hex[4..6])?,
+		)),
+		_ => Err(err().into()),
+	}
+}
+
 // As above, but optional
 pub fn parse_u64_or_none(arg: Option<&str>) -> Option<u64> {
 	let val = match arg {
@@ -70,6 +112,42 @@ pub fn parse_u64_or_none(arg: Option<&str>) -> Option<u64> {
 	}
 }
 
+/// Sheet margin, spacing, offset and frame limit, on top of the
+/// default load configuration
+pub fn parse_sheet_layout(args: &ArgMatches) -> Result<VeraImageSetLoadConfig, Error> {
+	let parse = |name: &str| -> Result<u32, Error> {
+		let v = parse_required(args, name)?;
+		Ok(parse_u64(v, name)? as u32)
+	};
+	let (margin, spacing) = (parse("margin")?, parse("spacing")?);
+	let (offset_x, offset_y) = (parse("offset_x")?, parse("offset_y")?);
+	let limit = match args.value_of("limit") {
+		Some(v) => Some(parse_u64(v, "limit")? as usize),
+		None => None,
+	};
+	Ok(VeraImageSetLoadConfig {
+		margin,
+		spacing,
+		offset_x,
+		offset_y,
+		limit,
+		..VeraImageSetLoadConfig::default()
+	})
+}
+
+/// Transparency from the `transparent` argument, either `alpha` or an
+/// RGB key colour
+pub fn parse_transparency(args: &ArgMatches) -> Result<VeraTransparency, Error> {
+	match args.value_of("transparent") {
+		None => Ok(VeraTransparency::None),
+		Some("alpha") => Ok(VeraTransparency::Alpha),
+		Some(c) => {
+			let (r, g, b) = parse_rgb(c)?;
+			Ok(VeraTransparency::Key(r, g, b))
+		}
+	}
+}
+
 /// Arguments common to all commands
 pub struct GlobalArgs {
 	/// project file on which the command should operate
@@ -102,6 +180,43 @@ pub fn read_file_bin(path: &str) -> Result<Vec<u8>, Error> {
 	Ok(content)
 }
 
+/// Whether the file name has an Aseprite extension (.ase or .aseprite)
+pub fn is_aseprite_file(path: &str) -> bool {
+	let extension = Path::new(path)
+		.extension()
+		.and_then(|e| e.to_str())
+		.map(|e| e.to_lowercase());
+	matches!(extension.as_deref(), Some("ase") | Some("aseprite"))
+}
+
+/// Expand any glob patterns among the given files, in the order given, with
+/// each pattern's matches sorted by path. Patterns matching nothing are an error
+pub fn expand_file_patterns(patterns: &[&str]) -> Result<Vec<String>, Error> {
+	let mut files = vec![];
+	for p in patterns {
+		if !p.contains(&['*', '?', '['][..]) {
+			files.push(p.to_string());
+			continue;
+		}
+		let paths = glob::glob(p).map_err(|e| {
+			let msg = format!("Invalid file pattern `{}`: {}", p, e);
+			ErrorKind::ArgumentError(msg)
+		})?;
+		let mut matched = vec![];
+		for path in paths {
+			let path = path.map_err(|e| ErrorKind::GenericError(format!("{}", e)))?;
+			matched.push(path.to_string_lossy().into_owned());
+		}
+		if matched.is_empty() {
+			let msg = format!("No files match `{}`", p);
+			return Err(ErrorKind::ArgumentError(msg).into());
+		}
+		matched.sort();
+		files.append(&mut matched);
+	}
+	Ok(files)
+}
+
 pub fn read_file_string(path: &str) -> Result<String, Error> {
 	let mut file = File::open(&path)?;
 	let mut content = String::new();
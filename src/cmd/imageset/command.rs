@@ -16,7 +16,11 @@ use crate::{Error, ErrorKind};
 use proj::Binable;
 
 use crate::cmd::common::{self, GlobalArgs};
-use vera::{VeraImageSet, VeraImageSetLoadConfig, VeraPixelDepth};
+use vera::{
+	VeraAnimation, VeraAnimationFrame, VeraAnimationLoop, VeraColorConversion, VeraFrameRef,
+	VeraImageSet, VeraImageSetFormatConfig, VeraImageSetLoadConfig, VeraPixelDepth,
+	VeraTransparency,
+};
 
 fn insert_imageset(
 	project_file: Option<String>,
@@ -36,25 +40,183 @@ pub struct ImageSetImportArgs {
 	pub input_file: String,
 	pub frame_width: u32,
 	pub frame_height: u32,
+	pub conversion: VeraColorConversion,
+	pub transparency: VeraTransparency,
+	pub preserve_indices: bool,
+	pub layer: Option<String>,
+	pub cull_flipped: bool,
+	pub layout: VeraImageSetLoadConfig,
+	pub atlas_file: Option<String>,
 }
 
 /// Imageset import command
 pub fn imageset_import(g_args: &GlobalArgs, args: &ImageSetImportArgs) -> Result<(), Error> {
-	let png_bytes = common::read_file_bin(&args.input_file)?;
-	let config = VeraImageSetLoadConfig::default();
+	let image_bytes = common::read_file_bin(&args.input_file)?;
+	let config = VeraImageSetLoadConfig {
+		conversion: args.conversion,
+		transparency: args.transparency,
+		cull_flipped: args.cull_flipped,
+		..args.layout
+	};
 	println!("{}, {}", args.frame_width, args.frame_height);
 	let mut imageset = VeraImageSet::new(&args.id, args.frame_width, args.frame_height);
-	imageset.load_from_png(png_bytes, &config)?;
+	if let Some(f) = &args.atlas_file {
+		let atlas_json = common::read_file_bin(f)?;
+		imageset.load_from_atlas(image_bytes, &atlas_json, &config)?;
+	} else if common::is_aseprite_file(&args.input_file) {
+		if args.preserve_indices {
+			warn!("Palette indices can't be preserved from Aseprite input, ignoring");
+		}
+		imageset.load_from_aseprite(image_bytes, args.layer.as_deref(), &config)?;
+	} else {
+		if args.layer.is_some() {
+			warn!("Layers only apply to Aseprite input, ignoring");
+		}
+		match args.preserve_indices {
+			true => imageset.load_from_indexed_png(image_bytes, &config)?,
+			false => imageset.load_from_image(image_bytes, &config)?,
+		}
+	}
 	insert_imageset(g_args.project_file.clone(), &args.id, &imageset)?;
 
 	Ok(())
 }
 
+/// Arguments for imageset append command
+pub struct ImageSetAppendArgs {
+	pub imageset_id: String,
+	pub input_files: Vec<String>,
+	pub conversion: VeraColorConversion,
+	pub transparency: VeraTransparency,
+	pub cull_flipped: bool,
+	pub layout: VeraImageSetLoadConfig,
+}
+
+/// Imageset append command
+pub fn imageset_append(g_args: &GlobalArgs, args: &ImageSetAppendArgs) -> Result<(), Error> {
+	let proj = common::load_project(g_args.project_file.clone())?;
+	let mut imageset = match proj.imagesets.get(&args.imageset_id) {
+		Some(i) => i.clone(),
+		None => {
+			let msg = format!("Imageset with id `{}` not found", args.imageset_id);
+			return Err(ErrorKind::ArgumentError(msg).into());
+		}
+	};
+	let config = VeraImageSetLoadConfig {
+		conversion: args.conversion,
+		transparency: args.transparency,
+		cull_flipped: args.cull_flipped,
+		..args.layout
+	};
+	for f in args.input_files.iter() {
+		info!("Appending {} to imageset {}", f, args.imageset_id);
+		let image_bytes = common::read_file_bin(f)?;
+		imageset.append_from_image(image_bytes, &config)?;
+	}
+	insert_imageset(g_args.project_file.clone(), &args.imageset_id, &imageset)?;
+
+	Ok(())
+}
+
+/// Index of a frame given by index or name
+fn resolve_frame(imageset: &VeraImageSet, frame: &str) -> Result<usize, Error> {
+	if let Ok(i) = frame.parse::<usize>() {
+		return Ok(i);
+	}
+	match imageset.frame_index(frame) {
+		Some(i) => Ok(i),
+		None => {
+			let msg = format!("Imageset `{}` has no frame `{}`", imageset.id, frame);
+			Err(ErrorKind::ArgumentError(msg).into())
+		}
+	}
+}
+
+/// Arguments for imageset name command
+pub struct ImageSetNameArgs {
+	pub imageset_id: String,
+	pub frame: String,
+	/// New name, or None to remove
+	pub name: Option<String>,
+}
+
+/// Imageset name command, names or unnames a frame
+pub fn imageset_name(g_args: &GlobalArgs, args: &ImageSetNameArgs) -> Result<(), Error> {
+	let proj = common::load_project(g_args.project_file.clone())?;
+	let mut imageset = match proj.imagesets.get(&args.imageset_id) {
+		Some(i) => i.clone(),
+		None => {
+			let msg = format!("Imageset with id `{}` not found", args.imageset_id);
+			return Err(ErrorKind::ArgumentError(msg).into());
+		}
+	};
+	let index = resolve_frame(&imageset, &args.frame)?;
+	imageset.set_frame_name(index, args.name.as_deref())?;
+	insert_imageset(g_args.project_file.clone(), &args.imageset_id, &imageset)?;
+
+	Ok(())
+}
+
+/// An animation frame by index or name, with its h and v flips and duration
+pub type AnimationFrameArg = (String, bool, bool, u8);
+
+/// Arguments for imageset animate command
+pub struct ImageSetAnimateArgs {
+	pub imageset_id: String,
+	pub name: String,
+	/// frames by index or name with their h and v flips and durations,
+	/// and loop mode, or None to remove
+	pub sequence: Option<(Vec<AnimationFrameArg>, VeraAnimationLoop)>,
+}
+
+/// Imageset animate command, declares or removes an animation
+pub fn imageset_animate(g_args: &GlobalArgs, args: &ImageSetAnimateArgs) -> Result<(), Error> {
+	let proj = common::load_project(g_args.project_file.clone())?;
+	let mut imageset = match proj.imagesets.get(&args.imageset_id) {
+		Some(i) => i.clone(),
+		None => {
+			let msg = format!("Imageset with id `{}` not found", args.imageset_id);
+			return Err(ErrorKind::ArgumentError(msg).into());
+		}
+	};
+	match &args.sequence {
+		Some((frames, loop_mode)) => {
+			let frames = frames
+				.iter()
+				.map(|(f, h_flip, v_flip, duration)| {
+					Ok(VeraAnimationFrame {
+						frame: VeraFrameRef {
+							frame: resolve_frame(&imageset, f)?,
+							h_flip: *h_flip,
+							v_flip: *v_flip,
+							pal_offset: None,
+						},
+						duration: *duration,
+					})
+				})
+				.collect::<Result<Vec<_>, Error>>()?;
+			imageset.set_animation(VeraAnimation {
+				name: args.name.clone(),
+				frames,
+				loop_mode: *loop_mode,
+			})?
+		}
+		None => imageset.remove_animation(&args.name)?,
+	}
+	insert_imageset(g_args.project_file.clone(), &args.imageset_id, &imageset)?;
+
+	Ok(())
+}
+
 /// Arguments for imageset format command
 pub struct ImageSetFormatArgs {
 	pub imageset_id: String,
 	pub palette_id: String,
 	pub pixel_depth: VeraPixelDepth,
+	pub remap_max_distance: Option<f64>,
+	pub offset: Option<usize>,
+	pub cull_recoloured: bool,
+	pub cull_flipped: bool,
 }
 
 /// Imageset format
@@ -74,7 +236,32 @@ pub fn imageset_format(g_args: &GlobalArgs, args: &ImageSetFormatArgs) -> Result
 			return Err(ErrorKind::ArgumentError(msg).into());
 		}
 	};
-	imageset.format_indices(&palette, args.pixel_depth)?;
+	if imageset.source_indices {
+		if args.remap_max_distance.is_some() {
+			let msg = "--remap can't be used with preserved palette indices".to_owned();
+			return Err(ErrorKind::ArgumentError(msg).into());
+		}
+		let offset = args.offset.unwrap_or(0);
+		imageset.format_source_indices(palette, args.pixel_depth, offset)?;
+	} else {
+		if args.offset.is_some() {
+			let msg = format!(
+				"--offset needs imageset `{}` to be imported with --preserve_indices",
+				args.imageset_id
+			);
+			return Err(ErrorKind::ArgumentError(msg).into());
+		}
+		let config = VeraImageSetFormatConfig {
+			remap_max_distance: args.remap_max_distance,
+		};
+		let reports = imageset.format_indices_with_config(palette, args.pixel_depth, &config)?;
+		for r in reports.iter() {
+			warn!("{}", r);
+		}
+	}
+	if args.cull_recoloured {
+		imageset.remove_recoloured_frames(args.cull_flipped)?;
+	}
 	insert_imageset(g_args.project_file.clone(), &args.imageset_id, &imageset)?;
 
 	Ok(())
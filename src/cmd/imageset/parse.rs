@@ -14,10 +14,14 @@
 
 use clap::ArgMatches;
 
-use super::command::{self, ImageSetFormatArgs, ImageSetImportArgs};
+use super::command::{
+	self, AnimationFrameArg, ImageSetAnimateArgs, ImageSetAppendArgs, ImageSetFormatArgs,
+	ImageSetImportArgs, ImageSetNameArgs,
+};
 use crate::cmd::common::{self, GlobalArgs};
 use crate::{Error, ErrorKind};
-use vera::VeraPixelDepth;
+use std::str::FromStr;
+use vera::{VeraAnimationLoop, VeraColorConversion, VeraPixelDepth};
 
 pub fn parse_imageset_import_args(
 	g_args: &GlobalArgs,
@@ -33,11 +37,135 @@ pub fn parse_imageset_import_args(
 	let v = common::parse_required(args, "frame_height")?;
 	let frame_height = common::parse_u64(&v, "frame_height")?;
 	let id = common::parse_required(args, "id")?;
+	let conversion = common::parse_required(args, "conversion")?;
 	Ok(ImageSetImportArgs {
 		id: id.into(),
 		frame_height: frame_height as u32,
 		frame_width: frame_width as u32,
 		input_file: input_file.into(),
+		conversion: VeraColorConversion::from_str(conversion)?,
+		transparency: common::parse_transparency(args)?,
+		preserve_indices: args.is_present("preserve_indices"),
+		layer: args.value_of("layer").map(|l| l.to_owned()),
+		cull_flipped: args.is_present("cull_flipped"),
+		layout: common::parse_sheet_layout(args)?,
+		atlas_file: args.value_of("atlas").map(|a| a.to_owned()),
+	})
+}
+
+pub fn parse_imageset_append_args(
+	g_args: &GlobalArgs,
+	args: &ArgMatches,
+) -> Result<ImageSetAppendArgs, Error> {
+	if g_args.project_file.is_none() {
+		let msg = format!("--project_file is required in this context");
+		return Err(ErrorKind::ArgumentError(msg).into());
+	}
+	let imageset_id = common::parse_required(args, "imageset_id")?;
+	let input_files: Vec<&str> = match args.values_of("input_file") {
+		Some(v) => v.collect(),
+		None => {
+			let msg = format!("Value for argument 'input_file' is required in this context");
+			return Err(ErrorKind::ArgumentError(msg).into());
+		}
+	};
+	let conversion = common::parse_required(args, "conversion")?;
+	Ok(ImageSetAppendArgs {
+		imageset_id: imageset_id.into(),
+		input_files: common::expand_file_patterns(&input_files)?,
+		conversion: VeraColorConversion::from_str(conversion)?,
+		transparency: common::parse_transparency(args)?,
+		cull_flipped: args.is_present("cull_flipped"),
+		layout: common::parse_sheet_layout(args)?,
+	})
+}
+
+pub fn parse_imageset_name_args(
+	g_args: &GlobalArgs,
+	args: &ArgMatches,
+	remove: bool,
+) -> Result<ImageSetNameArgs, Error> {
+	if g_args.project_file.is_none() {
+		let msg = format!("--project_file is required in this context");
+		return Err(ErrorKind::ArgumentError(msg).into());
+	}
+	let imageset_id = common::parse_required(args, "imageset_id")?;
+	let frame = common::parse_required(args, "frame")?;
+	let name = match remove {
+		true => None,
+		false => Some(common::parse_required(args, "name")?.to_owned()),
+	};
+	Ok(ImageSetNameArgs {
+		imageset_id: imageset_id.into(),
+		frame: frame.into(),
+		name,
+	})
+}
+
+/// Parse a display frame count, which must fit a byte
+fn parse_duration(v: &str) -> Result<u8, Error> {
+	let duration = common::parse_u64(v, "duration")?;
+	if duration == 0 || duration > 255 {
+		let msg = format!("Duration must be between 1 and 255");
+		return Err(ErrorKind::ArgumentError(msg).into());
+	}
+	Ok(duration as u8)
+}
+
+/// Parse an animation frame, given as its index or name, optionally
+/// followed by `/` and its flips (h, v or hv) then `:` and its duration
+fn parse_animation_frame(v: &str, duration: u8) -> Result<AnimationFrameArg, Error> {
+	let (frame, duration) = match v.find(':') {
+		Some(i) => (&v[..i], parse_duration(&v[i + 1..])?),
+		None => (v, duration),
+	};
+	let (frame, h_flip, v_flip) = match frame.find('/') {
+		Some(i) => match &frame[i + 1..] {
+			"h" => (&frame[..i], true, false),
+			"v" => (&frame[..i], false, true),
+			"hv" | "vh" => (&frame[..i], true, true),
+			f => {
+				let msg = format!("Invalid flip `{}`, expected h, v or hv", f);
+				return Err(ErrorKind::ArgumentError(msg).into());
+			}
+		},
+		None => (frame, false, false),
+	};
+	Ok((frame.to_owned(), h_flip, v_flip, duration))
+}
+
+pub fn parse_imageset_animate_args(
+	g_args: &GlobalArgs,
+	args: &ArgMatches,
+	remove: bool,
+) -> Result<ImageSetAnimateArgs, Error> {
+	if g_args.project_file.is_none() {
+		let msg = format!("--project_file is required in this context");
+		return Err(ErrorKind::ArgumentError(msg).into());
+	}
+	let imageset_id = common::parse_required(args, "imageset_id")?;
+	let name = common::parse_required(args, "name")?;
+	let sequence = match remove {
+		true => None,
+		false => {
+			let duration = parse_duration(common::parse_required(args, "duration")?)?;
+			let loop_mode = common::parse_required(args, "loop")?;
+			let frames = match args.values_of("frames") {
+				Some(v) => v
+					.map(|f| parse_animation_frame(f, duration))
+					.collect::<Result<Vec<_>, Error>>()?,
+				None => {
+					let msg = format!("Value for argument 'frames' is required in this context");
+					return Err(ErrorKind::ArgumentError(msg).into());
+				}
+			};
+			Some((frames, VeraAnimationLoop::from_str(loop_mode)?))
+		}
+	};
+	Ok(ImageSetAnimateArgs {
+		imageset_id: imageset_id.into(),
+		name: name.into(),
+		sequence,
 	})
 }
 
@@ -63,11 +191,29 @@ pub fn parse_imageset_format_args(
 			return Err(ErrorKind::ArgumentError(msg).into());
 		}
 	};
+	let remap_max_distance = match args.value_of("remap") {
+		Some(v) => Some(common::parse_f64(v, "remap")?),
+		None => None,
+	};
+	let offset = match args.value_of("offset") {
+		Some(v) => Some(common::parse_u64(v, "offset")? as usize),
+		None => None,
+	};
+	let cull_recoloured = args.is_present("cull_recoloured");
+	let cull_flipped = args.is_present("cull_flipped");
+	if cull_flipped && !cull_recoloured {
+		let msg = format!("--cull_flipped needs --cull_recoloured when formatting");
+		return Err(ErrorKind::ArgumentError(msg).into());
+	}
 
 	Ok(ImageSetFormatArgs {
 		imageset_id: imageset_id.into(),
 		palette_id: palette_id.into(),
 		pixel_depth,
+		remap_max_distance,
+		offset,
+		cull_recoloured,
+		cull_flipped,
 	})
 }
 
@@ -77,6 +223,26 @@ pub fn execute_imageset_command(g_args: &GlobalArgs, args: &ArgMatches) -> Resul
 			let a = arg_parse!(parse_imageset_import_args(g_args, args));
 			command::imageset_import(g_args, &a)
 		}
+		("append", Some(args)) => {
+			let a = arg_parse!(parse_imageset_append_args(g_args, args));
+			command::imageset_append(g_args, &a)
+		}
+		("name", Some(args)) => {
+			let a = arg_parse!(parse_imageset_name_args(g_args, args, false));
+			command::imageset_name(g_args, &a)
+		}
+		("unname", Some(args)) => {
+			let a = arg_parse!(parse_imageset_name_args(g_args, args, true));
+			command::imageset_name(g_args, &a)
+		}
+		("animate", Some(args)) => {
+			let a = arg_parse!(parse_imageset_animate_args(g_args, args, false));
+			command::imageset_animate(g_args, &a)
+		}
+		("unanimate", Some(args)) => {
+			let a = arg_parse!(parse_imageset_animate_args(g_args, args, true));
+			command::imageset_animate(g_args, &a)
+		}
 		("format", Some(args)) => {
 			let a = arg_parse!(parse_imageset_format_args(g_args, args));
 			command::imageset_format(g_args, &a)
@@ -33,6 +33,7 @@ pub enum ListObjectType {
 	Tilemaps,
 	Sprites,
 	Bitmaps,
+	PaletteFades,
 }
 
 impl TryFrom<&str> for ListObjectType {
@@ -45,6 +46,7 @@ impl TryFrom<&str> for ListObjectType {
 			"tilemaps" => ListObjectType::Tilemaps,
 			"sprites" => ListObjectType::Sprites,
 			"bitmaps" => ListObjectType::Bitmaps,
+			"palette_fades" => ListObjectType::PaletteFades,
 			n => {
 				return Err(ErrorKind::ArgumentError(format!("Invalid object type: {}", n)).into())
 			}
@@ -74,6 +76,7 @@ pub fn list(g_args: &GlobalArgs, args: &ListArgs) -> Result<(), Error> {
 			list_tilemaps(&proj)?;
 			list_sprites(&proj)?;
 			list_bitmaps(&proj)?;
+			list_palette_fades(&proj)?;
 		}
 		ListObjectType::Palettes => {
 			list_palettes(&proj)?;
@@ -90,6 +93,9 @@ pub fn list(g_args: &GlobalArgs, args: &ListArgs) -> Result<(), Error> {
 		ListObjectType::Bitmaps => {
 			list_bitmaps(&proj)?;
 		}
+		ListObjectType::PaletteFades => {
+			list_palette_fades(&proj)?;
+		}
 	}
 	Ok(())
 }
@@ -100,6 +106,12 @@ fn list_palettes(proj: &AloeVeraProject) -> Result<(), Error> {
 	for (id, palette) in proj.palettes.iter() {
 		println!("   {}:", id);
 		println!("      Color Count: {}", palette.len());
+		for c in palette.cycles() {
+			println!(
+				"      Cycle {}: {} entries from {}, {}, {} frames per step",
+				c.name, c.len, c.start, c.direction, c.frames_per_step
+			);
+		}
 	}
 	Ok(())
 }
@@ -119,6 +131,47 @@ pub fn list_imagesets(proj: &AloeVeraProject) -> Result<(), Error> {
 			imageset.frame_width, imageset.frame_height,
 		);
 		println!("      Pixel Depth: {}", depth,);
+		for t in imageset.tags.iter() {
+			println!(
+				"      Tag {}: source frames {} to {}, {}",
+				t.name, t.from, t.to, t.direction
+			);
+		}
+		for (i, f) in imageset.frame_data.iter().enumerate() {
+			if let Some(n) = &f.name {
+				println!("      Frame {}: {}", i, n);
+			}
+		}
+		for (i, r) in imageset.source_frames.iter().enumerate() {
+			if r.h_flip || r.v_flip || r.pal_offset.is_some() {
+				println!("      Source Frame {}: {}", i, r);
+			}
+		}
+		for a in imageset.animations() {
+			let frames: Vec<String> = a
+				.frames
+				.iter()
+				.map(|f| {
+					let flip = match (f.frame.h_flip, f.frame.v_flip) {
+						(true, true) => "/hv",
+						(true, false) => "/h",
+						(false, true) => "/v",
+						(false, false) => "",
+					};
+					let pal_offset = match f.frame.pal_offset {
+						Some(o) => format!("@{}", o),
+						None => "".to_owned(),
+					};
+					format!("{}{}{}:{}", f.frame.frame, flip, pal_offset, f.duration)
+				})
+				.collect();
+			println!(
+				"      Animation {}: frames {}, {}",
+				a.name,
+				frames.join(" "),
+				a.loop_mode
+			);
+		}
 	}
 	Ok(())
 }
@@ -182,3 +235,15 @@ pub fn list_bitmaps(proj: &AloeVeraProject) -> Result<(), Error> {
 	}
 	Ok(())
 }
+
+/// Palette fade list
+pub fn list_palette_fades(proj: &AloeVeraProject) -> Result<(), Error> {
+	println!("Palette Fades:");
+	for (id, fade) in proj.palette_fades.iter() {
+		println!("   {}:", id);
+		println!("      From Palette: {}", fade.palette_id,);
+		println!("      Steps: {}", fade.step_count(),);
+		println!("      Color Count: {}", fade.palette_len(),);
+	}
+	Ok(())
+}
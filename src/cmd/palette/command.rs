@@ -13,15 +13,24 @@
 // limitations under the License.
 
 use crate::{Error, ErrorKind};
-use proj::{AloeVeraProject, Binable};
+use proj::{AloeVeraProject, Binable, PaletteUsageReport};
+use std::path::Path;
 
 use crate::cmd::common::{self, GlobalArgs};
-use vera::{VeraPalette, VeraPaletteLoadConfig};
+use vera::{
+	pack_sub_palettes, VeraColorConversion, VeraPalette, VeraPaletteCycle,
+	VeraPaletteCycleDirection, VeraPaletteEntry, VeraPaletteFade, VeraPaletteFadeTarget,
+	VeraPaletteLoadConfig, VeraPixelDepth,
+};
 
 /// Arguments for palette command
 pub struct PaletteImportArgs {
 	pub id: String,
 	pub input_file: String,
+	pub quantize: Option<VeraPixelDepth>,
+	pub conversion: VeraColorConversion,
+	pub offset: Option<usize>,
+	pub overwrite: bool,
 }
 
 #[derive(Debug)]
@@ -29,17 +38,24 @@ pub struct PaletteImportArgs {
 enum PaletteFileType {
 	PNG,
 	GPL,
+	JASC,
+	ACT,
+	ACO,
+	HEX,
+	ASE,
 }
 
 fn vec_compare(va: &[u8], vb: &[u8]) -> bool {
 	(va.len() >= vb.len()) && va.iter().zip(vb).all(|(a, b)| a == b)
 }
 
-fn determine_file_type(data: &Vec<u8>) -> Result<PaletteFileType, Error> {
+fn determine_file_type(data: &Vec<u8>, file_name: &str) -> Result<PaletteFileType, Error> {
 	// 8-byte PNG file header as described here: https://en.wikipedia.org/wiki/Portable_Network_Graphics#File_header
 	const PNG_BYTES: [u8; 8] = [0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a];
 	// First line of Gimp gpl file excluding line ending: "GIMP Palette"
 	const GPL_BYTES: [u8; 12] = [71, 73, 77, 80, 32, 80, 97, 108, 101, 116, 116, 101];
+	// First line of JASC pal file excluding line ending: "JASC-PAL"
+	const JASC_BYTES: [u8; 8] = [74, 65, 83, 67, 45, 80, 65, 76];
 
 	if vec_compare(data, &PNG_BYTES) {
 		return Ok(PaletteFileType::PNG);
@@ -47,6 +63,23 @@ fn determine_file_type(data: &Vec<u8>) -> Result<PaletteFileType, Error> {
 	if vec_compare(data, &GPL_BYTES) {
 		return Ok(PaletteFileType::GPL);
 	}
+	if vec_compare(data, &JASC_BYTES) {
+		return Ok(PaletteFileType::JASC);
+	}
+	if common::is_aseprite_file(file_name) {
+		return Ok(PaletteFileType::ASE);
+	}
+	// Remaining formats have no identifying header, so go by extension
+	let extension = Path::new(file_name)
+		.extension()
+		.and_then(|e| e.to_str())
+		.map(|e| e.to_lowercase());
+	match extension.as_deref() {
+		Some("act") => return Ok(PaletteFileType::ACT),
+		Some("aco") => return Ok(PaletteFileType::ACO),
+		Some("hex") => return Ok(PaletteFileType::HEX),
+		_ => {}
+	}
 	return Err(ErrorKind::ArgumentError("Invalid palette file".to_string()).into());
 }
 
@@ -57,14 +90,31 @@ pub fn palette_import(g_args: &GlobalArgs, args: &PaletteImportArgs) -> Result<(
 		direct_load: true,
 		include_defaults: false,
 		sort: false,
+		quantize: args.quantize,
+		conversion: args.conversion,
 	};
-	let file_type = determine_file_type(&pal_bytes);
+	let file_type = determine_file_type(&pal_bytes, &args.input_file)?;
+	match file_type {
+		PaletteFileType::PNG => {}
+		_ => {
+			if args.quantize.is_some() {
+				warn!("Quantization only applies to PNG input, ignoring");
+			}
+			if args.conversion != VeraColorConversion::Truncate {
+				warn!("Color conversion only applies to PNG input, ignoring");
+			}
+		}
+	}
 	let palette = match file_type {
-		Ok(PaletteFileType::GPL) => VeraPalette::derive_from_gpl(&args.id, pal_bytes, &pal_config)?,
-		Ok(PaletteFileType::PNG) => VeraPalette::derive_from_png(&args.id, pal_bytes, &pal_config)?,
-		Err(s) => {
-			return Err(s);
+		PaletteFileType::GPL => VeraPalette::derive_from_gpl(&args.id, pal_bytes, &pal_config)?,
+		PaletteFileType::JASC => VeraPalette::derive_from_jasc(&args.id, pal_bytes, &pal_config)?,
+		PaletteFileType::ACT => VeraPalette::derive_from_act(&args.id, pal_bytes, &pal_config)?,
+		PaletteFileType::ACO => VeraPalette::derive_from_aco(&args.id, pal_bytes, &pal_config)?,
+		PaletteFileType::HEX => VeraPalette::derive_from_hex(&args.id, pal_bytes, &pal_config)?,
+		PaletteFileType::ASE => {
+			VeraPalette::derive_from_aseprite(&args.id, pal_bytes, &pal_config)?
 		}
+		PaletteFileType::PNG => VeraPalette::derive_from_png(&args.id, pal_bytes, &pal_config)?,
 	};
 	// load up the project json
 	let project_file = match &g_args.project_file {
@@ -76,8 +126,321 @@ pub fn palette_import(g_args: &GlobalArgs, args: &PaletteImportArgs) -> Result<(
 	info!("Inserting palette into project: {}", project_file);
 	let encoded = common::read_file_bin(&project_file)?;
 	let mut proj = *AloeVeraProject::from_bin(&encoded)?;
+	let palette = match args.offset {
+		Some(offset) => {
+			let mut target = match proj.palettes.get(&args.id) {
+				Some(p) => p.clone(),
+				None => VeraPalette::blank(&args.id),
+			};
+			info!("Importing {} entries at index {}", palette.len(), offset);
+			target.import_at(offset, &palette, args.overwrite)?;
+			target
+		}
+		None => palette,
+	};
 	proj.palettes.insert(palette.id.clone(), palette);
 	common::output_to_file(&project_file, &proj.to_bin()?, &None)?;
 
 	Ok(())
 }
+
+/// Arguments for palette pack command
+pub struct PalettePackArgs {
+	pub id: String,
+	pub pixel_depth: VeraPixelDepth,
+	pub imageset_ids: Vec<String>,
+}
+
+/// Palette pack command
+pub fn palette_pack(g_args: &GlobalArgs, args: &PalettePackArgs) -> Result<(), Error> {
+	let mut proj = common::load_project(g_args.project_file.clone())?;
+	let mut imagesets = vec![];
+	for id in args.imageset_ids.iter() {
+		match proj.imagesets.get(id) {
+			Some(i) => imagesets.push(i.clone()),
+			None => {
+				let msg = format!("Imageset with id `{}` not found", id);
+				return Err(ErrorKind::ArgumentError(msg).into());
+			}
+		}
+	}
+	let palette = pack_sub_palettes(&args.id, &mut imagesets, args.pixel_depth)?;
+	proj.palettes.insert(palette.id.clone(), palette);
+	for imageset in imagesets.into_iter() {
+		proj.imagesets.insert(imageset.id.clone(), imageset);
+	}
+	common::output_to_file(
+		&g_args.project_file.clone().unwrap(),
+		&proj.to_bin()?,
+		&None,
+	)?;
+
+	Ok(())
+}
+
+/// Arguments for palette slots command
+pub struct PaletteSlotsArgs {
+	pub id: String,
+}
+
+/// Palette slots command, shows a row per 16 color sub-palette
+pub fn palette_slots(g_args: &GlobalArgs, args: &PaletteSlotsArgs) -> Result<(), Error> {
+	let proj = common::load_project(g_args.project_file.clone())?;
+	let palette = match proj.palettes.get(&args.id) {
+		Some(p) => p,
+		None => {
+			let msg = format!("Palette with id `{}` not found", args.id);
+			return Err(ErrorKind::ArgumentError(msg).into());
+		}
+	};
+	println!("Palette {} slots (# = occupied, . = empty):", args.id);
+	for row in 0..16 {
+		let slots: String = (row * 16..row * 16 + 16)
+			.map(|i| match palette.is_slot_occupied(i) {
+				true => '#',
+				false => '.',
+			})
+			.collect();
+		println!("   {:>3}-{:>3}: {}", row * 16, row * 16 + 15, slots);
+	}
+	Ok(())
+}
+
+/// What a palette fade targets
+pub enum PaletteFadeTarget {
+	Color((u8, u8, u8)),
+	Palette(String),
+}
+
+/// Arguments for palette fade command
+pub struct PaletteFadeArgs {
+	pub id: String,
+	pub palette_id: String,
+	pub steps: usize,
+	pub target: PaletteFadeTarget,
+}
+
+/// Palette fade command
+pub fn palette_fade(g_args: &GlobalArgs, args: &PaletteFadeArgs) -> Result<(), Error> {
+	let mut proj = common::load_project(g_args.project_file.clone())?;
+	let find_palette = |id: &str| match proj.palettes.get(id) {
+		Some(p) => Ok(p),
+		None => {
+			let msg = format!("Palette with id `{}` not found", id);
+			Err(ErrorKind::ArgumentError(msg))
+		}
+	};
+	let palette = find_palette(&args.palette_id)?;
+	let target = match &args.target {
+		PaletteFadeTarget::Color((r, g, b)) => {
+			VeraPaletteFadeTarget::Color(VeraPaletteEntry::new(*r, *g, *b))
+		}
+		PaletteFadeTarget::Palette(id) => VeraPaletteFadeTarget::Palette(find_palette(id)?),
+	};
+	let fade = VeraPaletteFade::from_palette(&args.id, palette, target, args.steps)?;
+	info!(
+		"Inserting palette fade into project: {}",
+		g_args.project_file.as_ref().unwrap()
+	);
+	proj.palette_fades.insert(fade.id.clone(), fade);
+	common::output_to_file(
+		&g_args.project_file.clone().unwrap(),
+		&proj.to_bin()?,
+		&None,
+	)?;
+
+	Ok(())
+}
+
+/// Arguments for palette usage command
+pub struct PaletteUsageArgs {
+	pub id: String,
+	pub json: bool,
+}
+
+/// Palette usage command
+pub fn palette_usage(g_args: &GlobalArgs, args: &PaletteUsageArgs) -> Result<(), Error> {
+	let proj = common::load_project(g_args.project_file.clone())?;
+	let report = PaletteUsageReport::from_project(&proj, &args.id)?;
+	match args.json {
+		true => println!("{}", report.to_json()?),
+		false => print!("{}", report),
+	}
+	Ok(())
+}
+
+/// Size in pixels of each entry in an exported PNG swatch
+const SWATCH_SIZE: u32 = 16;
+
+/// Arguments for palette export command
+pub struct PaletteExportArgs {
+	pub id: String,
+	pub output_file: String,
+	pub format: Option<String>,
+	pub cycle: Option<String>,
+}
+
+/// Palette export command
+pub fn palette_export(g_args: &GlobalArgs, args: &PaletteExportArgs) -> Result<(), Error> {
+	let proj = common::load_project(g_args.project_file.clone())?;
+	let palette = match proj.palettes.get(&args.id) {
+		Some(p) => p,
+		None => {
+			let msg = format!("Palette with id `{}` not found", args.id);
+			return Err(ErrorKind::ArgumentError(msg).into());
+		}
+	};
+	let format = match &args.format {
+		Some(f) => Some(f.to_lowercase()),
+		None => Path::new(&args.output_file)
+			.extension()
+			.and_then(|e| e.to_str())
+			.map(|e| e.to_lowercase()),
+	};
+	let data = match (format.as_deref(), &args.cycle) {
+		(Some("png"), Some(c)) => palette.to_png_cycle_preview(c, SWATCH_SIZE)?,
+		(_, Some(_)) => {
+			let msg = "Cycles can only be previewed as png".to_owned();
+			return Err(ErrorKind::ArgumentError(msg).into());
+		}
+		(Some("gpl"), None) => palette.to_gpl(),
+		(Some("jasc"), None) | (Some("pal"), None) => palette.to_jasc(),
+		(Some("png"), None) => palette.to_png_swatch(SWATCH_SIZE)?,
+		_ => {
+			let msg = format!(
+				"Can't determine export format for `{}`, use --format to specify one",
+				args.output_file
+			);
+			return Err(ErrorKind::ArgumentError(msg).into());
+		}
+	};
+	info!("Exporting palette {} to {}", args.id, args.output_file);
+	common::output_to_file(&args.output_file, &data, &None)?;
+
+	Ok(())
+}
+
+/// Arguments for palette cycle command
+pub struct PaletteCycleArgs {
+	pub id: String,
+	pub name: String,
+	/// start, length, direction and frames per step, or None to remove
+	pub range: Option<(usize, usize, VeraPaletteCycleDirection, u8)>,
+}
+
+/// Palette cycle command, declares or removes a color cycle
+pub fn palette_cycle(g_args: &GlobalArgs, args: &PaletteCycleArgs) -> Result<(), Error> {
+	let mut proj = common::load_project(g_args.project_file.clone())?;
+	let palette = match proj.palettes.get_mut(&args.id) {
+		Some(p) => p,
+		None => {
+			let msg = format!("Palette with id `{}` not found", args.id);
+			return Err(ErrorKind::ArgumentError(msg).into());
+		}
+	};
+	match args.range {
+		Some((start, len, direction, frames_per_step)) => palette.set_cycle(VeraPaletteCycle {
+			name: args.name.clone(),
+			start,
+			len,
+			direction,
+			frames_per_step,
+		})?,
+		None => palette.remove_cycle(&args.name)?,
+	}
+	common::output_to_file(
+		&g_args.project_file.clone().unwrap(),
+		&proj.to_bin()?,
+		&None,
+	)?;
+
+	Ok(())
+}
+
+/// A single edit to make to a palette
+pub enum PaletteEdit {
+	Set(usize, (u8, u8, u8)),
+	Insert(usize, (u8, u8, u8)),
+	Swap(usize, usize),
+	Move(usize, usize),
+	Remove(usize),
+	Sort,
+}
+
+/// Arguments for palette edit commands
+pub struct PaletteEditArgs {
+	pub id: String,
+	pub edit: PaletteEdit,
+}
+
+/// Palette edit commands. Any imagesets formatted against the palette
+/// follow their colours to the new indices, or are marked as needing
+/// to be formatted again if that isn't possible. Tilemaps on those
+/// imagesets follow the new palette offsets in turn, or are marked
+/// stale until they're initialized and loaded again
+pub fn palette_edit(g_args: &GlobalArgs, args: &PaletteEditArgs) -> Result<(), Error> {
+	let mut proj = common::load_project(g_args.project_file.clone())?;
+	let palette = match proj.palettes.get_mut(&args.id) {
+		Some(p) => p,
+		None => {
+			let msg = format!("Palette with id `{}` not found", args.id);
+			return Err(ErrorKind::ArgumentError(msg).into());
+		}
+	};
+	let index_map = match args.edit {
+		PaletteEdit::Set(i, (r, g, b)) => {
+			palette.set_entry(i, r, g, b)?;
+			None
+		}
+		PaletteEdit::Insert(i, (r, g, b)) => Some(palette.insert_entry(i, r, g, b)?),
+		PaletteEdit::Swap(a, b) => Some(palette.swap_entries(a, b)?),
+		PaletteEdit::Move(from, to) => Some(palette.move_entry(from, to)?),
+		PaletteEdit::Remove(i) => Some(palette.remove_entry(i)?),
+		PaletteEdit::Sort => Some(palette.sort_entries()),
+	};
+	if let Some(index_map) = index_map {
+		let mut reindexed = vec![];
+		let mut unmatched = vec![];
+		for imageset in proj.imagesets.values_mut() {
+			if imageset.palette_id.as_deref() != Some(args.id.as_str()) {
+				continue;
+			}
+			if !imageset.reindex(&index_map) {
+				let again = match imageset.recoloured_frames.is_empty() {
+					true => "formatted",
+					false => "imported",
+				};
+				warn!(
+					"Imageset {} can no longer be matched to palette {} and must be {} again",
+					imageset.id, args.id, again
+				);
+				unmatched.push(imageset.id.clone());
+				continue;
+			}
+			reindexed.push(imageset.id.clone());
+		}
+		for tilemap in proj.tilemaps.values_mut() {
+			let followed = match proj.imagesets.get(&tilemap.imageset_id) {
+				Some(i) if reindexed.contains(&i.id) => tilemap.update_pal_offsets(i),
+				Some(i) if unmatched.contains(&i.id) => {
+					tilemap.mark_stale();
+					false
+				}
+				_ => continue,
+			};
+			if !followed {
+				warn!(
+					"Palette offsets of imageset {} have changed, tilemap {} must be initialized and loaded again",
+					tilemap.imageset_id, tilemap.id
+				);
+			}
+		}
+	}
+	common::output_to_file(
+		&g_args.project_file.clone().unwrap(),
+		&proj.to_bin()?,
+		&None,
+	)?;
+
+	Ok(())
+}
@@ -13,9 +13,14 @@
 // limitations under the License.
 use clap::ArgMatches;
 
-use super::command::{self, PaletteImportArgs};
+use super::command::{
+	self, PaletteCycleArgs, PaletteEdit, PaletteEditArgs, PaletteExportArgs, PaletteFadeArgs,
+	PaletteFadeTarget, PaletteImportArgs, PalettePackArgs, PaletteSlotsArgs, PaletteUsageArgs,
+};
 use crate::cmd::common::{self, GlobalArgs};
 use crate::{Error, ErrorKind};
+use std::str::FromStr;
+use vera::{VeraColorConversion, VeraPaletteCycleDirection, VeraPixelDepth};
 
 pub fn parse_palette_import_args(
 	g_args: &GlobalArgs,
@@ -27,9 +32,202 @@ pub fn parse_palette_import_args(
 	}
 	let id = common::parse_required(args, "id")?;
 	let input_file = common::parse_required(args, "input_file")?;
+	let quantize = match args.value_of("quantize") {
+		Some("4") => Some(VeraPixelDepth::BPP2),
+		Some("16") => Some(VeraPixelDepth::BPP4),
+		Some("256") => Some(VeraPixelDepth::BPP8),
+		Some(_) => {
+			let msg = format!("Quantize color count must be 4, 16 or 256");
+			return Err(ErrorKind::ArgumentError(msg).into());
+		}
+		None => None,
+	};
+	let conversion = common::parse_required(args, "conversion")?;
+	let offset = match args.value_of("offset") {
+		Some(v) => Some(common::parse_u64(v, "offset")? as usize),
+		None => None,
+	};
 	Ok(PaletteImportArgs {
 		id: id.to_owned(),
 		input_file: input_file.into(),
+		quantize,
+		conversion: VeraColorConversion::from_str(conversion)?,
+		offset,
+		overwrite: args.is_present("overwrite"),
+	})
+}
+
+pub fn parse_palette_pack_args(
+	g_args: &GlobalArgs,
+	args: &ArgMatches,
+) -> Result<PalettePackArgs, Error> {
+	if g_args.project_file.is_none() {
+		let msg = "--project_file is required in this context".to_owned();
+		return Err(ErrorKind::ArgumentError(msg).into());
+	}
+	let id = common::parse_required(args, "id")?;
+	let v = common::parse_required(args, "pixel_depth")?;
+	let pixel_depth = match common::parse_u64(v, "pixel_depth")? {
+		4 => VeraPixelDepth::BPP4,
+		2 => VeraPixelDepth::BPP2,
+		_ => {
+			let msg = "Given pixel depth must be 2 or 4".to_owned();
+			return Err(ErrorKind::ArgumentError(msg).into());
+		}
+	};
+	let imageset_ids = match args.values_of("imageset_ids") {
+		Some(v) => v.map(|i| i.to_owned()).collect(),
+		None => {
+			let msg = "At least one imageset ID is required".to_owned();
+			return Err(ErrorKind::ArgumentError(msg).into());
+		}
+	};
+	Ok(PalettePackArgs {
+		id: id.to_owned(),
+		pixel_depth,
+		imageset_ids,
+	})
+}
+
+pub fn parse_palette_slots_args(
+	g_args: &GlobalArgs,
+	args: &ArgMatches,
+) -> Result<PaletteSlotsArgs, Error> {
+	if g_args.project_file.is_none() {
+		let msg = "--project_file is required in this context".to_owned();
+		return Err(ErrorKind::ArgumentError(msg).into());
+	}
+	let id = common::parse_required(args, "id")?;
+	Ok(PaletteSlotsArgs { id: id.to_owned() })
+}
+
+pub fn parse_palette_fade_args(
+	g_args: &GlobalArgs,
+	args: &ArgMatches,
+) -> Result<PaletteFadeArgs, Error> {
+	if g_args.project_file.is_none() {
+		let msg = "--project_file is required in this context".to_owned();
+		return Err(ErrorKind::ArgumentError(msg).into());
+	}
+	let id = common::parse_required(args, "id")?;
+	let palette_id = common::parse_required(args, "palette_id")?;
+	let steps = parse_index(args, "steps")?;
+	let target = match common::parse_required(args, "target")? {
+		"black" => PaletteFadeTarget::Color((0, 0, 0)),
+		"white" => PaletteFadeTarget::Color((0xff, 0xff, 0xff)),
+		t if t.starts_with('#') => PaletteFadeTarget::Color(common::parse_rgb(t)?),
+		t => PaletteFadeTarget::Palette(t.to_owned()),
+	};
+	Ok(PaletteFadeArgs {
+		id: id.to_owned(),
+		palette_id: palette_id.to_owned(),
+		steps,
+		target,
+	})
+}
+
+pub fn parse_palette_cycle_args(
+	g_args: &GlobalArgs,
+	args: &ArgMatches,
+	remove: bool,
+) -> Result<PaletteCycleArgs, Error> {
+	if g_args.project_file.is_none() {
+		let msg = "--project_file is required in this context".to_owned();
+		return Err(ErrorKind::ArgumentError(msg).into());
+	}
+	let id = common::parse_required(args, "id")?;
+	let name = common::parse_required(args, "name")?;
+	let range = match remove {
+		true => None,
+		false => {
+			let direction = common::parse_required(args, "direction")?;
+			let frames = common::parse_required(args, "frames_per_step")?;
+			let frames = common::parse_u64(frames, "frames_per_step")?;
+			if frames == 0 || frames > 255 {
+				let msg = "Frames per step must be between 1 and 255".to_owned();
+				return Err(ErrorKind::ArgumentError(msg).into());
+			}
+			Some((
+				parse_index(args, "start")?,
+				parse_index(args, "length")?,
+				VeraPaletteCycleDirection::from_str(direction)?,
+				frames as u8,
+			))
+		}
+	};
+	Ok(PaletteCycleArgs {
+		id: id.to_owned(),
+		name: name.to_owned(),
+		range,
+	})
+}
+
+pub fn parse_palette_usage_args(
+	g_args: &GlobalArgs,
+	args: &ArgMatches,
+) -> Result<PaletteUsageArgs, Error> {
+	if g_args.project_file.is_none() {
+		let msg = "--project_file is required in this context".to_owned();
+		return Err(ErrorKind::ArgumentError(msg).into());
+	}
+	let id = common::parse_required(args, "id")?;
+	let format = common::parse_required(args, "format")?;
+	Ok(PaletteUsageArgs {
+		id: id.to_owned(),
+		json: format == "json",
+	})
+}
+
+pub fn parse_palette_export_args(
+	g_args: &GlobalArgs,
+	args: &ArgMatches,
+) -> Result<PaletteExportArgs, Error> {
+	if g_args.project_file.is_none() {
+		let msg = "--project_file is required in this context".to_owned();
+		return Err(ErrorKind::ArgumentError(msg).into());
+	}
+	let id = common::parse_required(args, "id")?;
+	let output_file = common::parse_required(args, "output_file")?;
+	Ok(PaletteExportArgs {
+		id: id.to_owned(),
+		output_file: output_file.into(),
+		format: args.value_of("format").map(|f| f.to_owned()),
+		cycle: args.value_of("cycle").map(|c| c.to_owned()),
+	})
+}
+
+fn parse_index(args: &ArgMatches, name: &str) -> Result<usize, Error> {
+	let v = common::parse_required(args, name)?;
+	Ok(common::parse_u64(v, name)? as usize)
+}
+
+pub fn parse_palette_edit_args(
+	g_args: &GlobalArgs,
+	args: &ArgMatches,
+	edit_name: &str,
+) -> Result<PaletteEditArgs, Error> {
+	if g_args.project_file.is_none() {
+		let msg = "--project_file is required in this context".to_owned();
+		return Err(ErrorKind::ArgumentError(msg).into());
+	}
+	let id = common::parse_required(args, "id")?;
+	let edit = match edit_name {
+		"set" => PaletteEdit::Set(
+			parse_index(args, "index")?,
+			common::parse_rgb(common::parse_required(args, "color")?)?,
+		),
+		"insert" => PaletteEdit::Insert(
+			parse_index(args, "index")?,
+			common::parse_rgb(common::parse_required(args, "color")?)?,
+		),
+		"swap" => PaletteEdit::Swap(parse_index(args, "index_a")?, parse_index(args, "index_b")?),
+		"move" => PaletteEdit::Move(parse_index(args, "from")?, parse_index(args, "to")?),
+		"remove" => PaletteEdit::Remove(parse_index(args, "index")?),
+		_ => PaletteEdit::Sort,
+	};
+	Ok(PaletteEditArgs {
+		id: id.to_owned(),
+		edit,
 	})
 }
 
@@ -39,6 +237,43 @@ pub fn execute_palette_command(g_args: &GlobalArgs, args: &ArgMatches) -> Result
 			let a = arg_parse!(parse_palette_import_args(g_args, args));
 			command::palette_import(g_args, &a)
 		}
+		("pack", Some(args)) => {
+			let a = arg_parse!(parse_palette_pack_args(g_args, args));
+			command::palette_pack(g_args, &a)
+		}
+		("slots", Some(args)) => {
+			let a = arg_parse!(parse_palette_slots_args(g_args, args));
+			command::palette_slots(g_args, &a)
+		}
+		("fade", Some(args)) => {
+			let a = arg_parse!(parse_palette_fade_args(g_args, args));
+			command::palette_fade(g_args, &a)
+		}
+		("cycle", Some(args)) => {
+			let a = arg_parse!(parse_palette_cycle_args(g_args, args, false));
+			command::palette_cycle(g_args, &a)
+		}
+		("uncycle", Some(args)) => {
+			let a = arg_parse!(parse_palette_cycle_args(g_args, args, true));
+			command::palette_cycle(g_args, &a)
+		}
+		("usage", Some(args)) => {
+			let a = arg_parse!(parse_palette_usage_args(g_args, args));
+			command::palette_usage(g_args, &a)
+		}
+		("export", Some(args)) => {
+			let a = arg_parse!(parse_palette_export_args(g_args, args));
+			command::palette_export(g_args, &a)
+		}
+		(edit @ "set", Some(args))
+		| (edit @ "insert", Some(args))
+		| (edit @ "swap", Some(args))
+		| (edit @ "move", Some(args))
+		| (edit @ "remove", Some(args))
+		| (edit @ "sort", Some(args)) => {
+			let a = arg_parse!(parse_palette_edit_args(g_args, args, edit));
+			command::palette_edit(g_args, &a)
+		}
 		_ => {
 			let msg = format!("Unknown sub command, use 'aloevera palette --help' for details");
 			return Err(ErrorKind::ArgumentError(msg).into());
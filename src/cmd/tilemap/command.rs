@@ -15,7 +15,10 @@ use proj::Binable;
 
 use crate::cmd::common::{self, GlobalArgs};
 use crate::{Error, ErrorKind};
-use vera::{VeraTileMap, VeraTileMapDim, VeraTileMapMode};
+use vera::{
+	VeraColorConversion, VeraImageSetLoadConfig, VeraTileMap, VeraTileMapDim, VeraTileMapMode,
+	VeraTransparency,
+};
 
 fn insert_tilemap(
 	project_file: Option<String>,
@@ -67,6 +70,9 @@ pub struct LoadTileMapArgs {
 	pub start_x: u32,
 	pub start_y: u32,
 	pub clear_index: u32,
+	pub conversion: VeraColorConversion,
+	pub transparency: VeraTransparency,
+	pub layout: VeraImageSetLoadConfig,
 }
 
 /// Tilemap load command
@@ -90,12 +96,18 @@ pub fn tilemap_load(g_args: &GlobalArgs, args: &LoadTileMapArgs) -> Result<(), E
 		}
 	};
 	let png_bytes = common::read_file_bin(&args.input_file)?;
-	tilemap.load_from_png(
+	let config = VeraImageSetLoadConfig {
+		conversion: args.conversion,
+		transparency: args.transparency,
+		..args.layout
+	};
+	tilemap.load_from_png_with_config(
 		png_bytes.to_vec(),
 		palette,
 		args.start_x,
 		args.start_y,
 		args.clear_index as u8,
+		&config,
 	)?;
 	insert_tilemap(g_args.project_file.clone(), &args.id, &tilemap)?;
 
@@ -17,7 +17,8 @@ use clap::ArgMatches;
 use super::command::{self, InitTileMapArgs, LoadTileMapArgs};
 use crate::cmd::common::{self, GlobalArgs};
 use crate::{Error, ErrorKind};
-use vera::{VeraTileMapDim, VeraTileMapMode};
+use std::str::FromStr;
+use vera::{VeraColorConversion, VeraTileMapDim, VeraTileMapMode};
 
 pub fn parse_init_tilemap_args(
 	g_args: &GlobalArgs,
@@ -67,6 +68,7 @@ pub fn parse_load_tilemap_args(
 		Some(i) => Some(i.into()),
 		None => None,
 	};
+	let conversion = common::parse_required(args, "conversion")?;
 
 	Ok(LoadTileMapArgs {
 		id: id.into(),
@@ -75,6 +77,9 @@ pub fn parse_load_tilemap_args(
 		start_x: start_x as u32,
 		start_y: start_y as u32,
 		clear_index: clear_index as u32,
+		conversion: VeraColorConversion::from_str(conversion)?,
+		transparency: common::parse_transparency(args)?,
+		layout: common::parse_sheet_layout(args)?,
 	})
 }
 pub fn execute_tilemap_command(g_args: &GlobalArgs, args: &ArgMatches) -> Result<(), Error> {
@@ -107,6 +107,154 @@ fn cmd_line_palette() -> Result<(), Error> {
 		ref_file!("imageset-4bpp-pal.cc65"),
 	)?;
 
+	// Export palette back out for editors
+	for ext in ["gpl", "pal", "png"].iter() {
+		let out_file = format!("{}/imageset-4bpp-pal.{}", test_dir, ext);
+		let arg_vec = vec![
+			"aloevera",
+			"-p",
+			&project_file,
+			"palette",
+			"export",
+			"imageset-4bpp-pal",
+			&out_file,
+		];
+		execute_command(&app, arg_vec)?;
+		compare_results(&out_file, ref_file!(format!("imageset-4bpp-pal.{}", ext)))?;
+	}
+
+	// Edit and report on a palette in use by an imageset
+	let arg_vec = vec![
+		"aloevera",
+		"-p",
+		&project_file,
+		"imageset",
+		"import",
+		"imageset-4bpp",
+		"8",
+		"8",
+		input_file,
+	];
+	execute_command(&app, arg_vec)?;
+	let arg_vec = vec![
+		"aloevera",
+		"-p",
+		&project_file,
+		"imageset",
+		"format",
+		"imageset-4bpp",
+		"imageset-4bpp-pal",
+		"4",
+	];
+	execute_command(&app, arg_vec)?;
+	let arg_vec = vec![
+		"aloevera",
+		"-p",
+		&project_file,
+		"palette",
+		"swap",
+		"imageset-4bpp-pal",
+		"1",
+		"2",
+	];
+	execute_command(&app, arg_vec)?;
+	for format in ["table", "json"].iter() {
+		let arg_vec = vec![
+			"aloevera",
+			"-p",
+			&project_file,
+			"palette",
+			"usage",
+			"imageset-4bpp-pal",
+			"-f",
+			format,
+		];
+		execute_command(&app, arg_vec)?;
+	}
+
+	// Tilemaps on an imageset that can no longer follow the palette
+	// can't be assembled until they're initialized and loaded again
+	let init_and_load_map = [
+		vec![
+			"aloevera",
+			"-p",
+			&project_file,
+			"tilemap",
+			"init",
+			"imageset-4bpp-map",
+			"imageset-4bpp",
+			"32",
+			"32",
+			"tile_4bpp",
+		],
+		vec![
+			"aloevera",
+			"-p",
+			&project_file,
+			"tilemap",
+			"load",
+			"imageset-4bpp-map",
+			input_file,
+		],
+	];
+	let asm_map = || {
+		let arg_vec = vec![
+			"aloevera",
+			"-p",
+			&project_file,
+			"asm",
+			&test_dir,
+			"select",
+			"imageset-4bpp-map",
+			"imageset-4bpp-map.ca65",
+		];
+		execute_command(&app, arg_vec).is_ok()
+	};
+	for arg_vec in init_and_load_map.iter() {
+		execute_command(&app, arg_vec.clone())?;
+	}
+	assert!(asm_map());
+	for _ in 0..2 {
+		let arg_vec = vec![
+			"aloevera",
+			"-p",
+			&project_file,
+			"palette",
+			"insert",
+			"imageset-4bpp-pal",
+			"0",
+			"#123",
+		];
+		execute_command(&app, arg_vec)?;
+	}
+	assert!(!asm_map());
+	let arg_vec = vec![
+		"aloevera",
+		"-p",
+		&project_file,
+		"palette",
+		"remove",
+		"imageset-4bpp-pal",
+		"0",
+	];
+	execute_command(&app, arg_vec)?;
+	let arg_vec = vec![
+		"aloevera",
+		"-p",
+		&project_file,
+		"imageset",
+		"format",
+		"imageset-4bpp",
+		"imageset-4bpp-pal",
+		"4",
+	];
+	execute_command(&app, arg_vec)?;
+	assert!(!asm_map());
+	for arg_vec in init_and_load_map.iter() {
+		execute_command(&app, arg_vec.clone())?;
+	}
+	assert!(asm_map());
+
 	clean_output_dir(&test_dir);
 	Ok(())
 }
@@ -0,0 +1,145 @@
+// Copyright 2020 Revcore Technologies Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Implements basic support for reading Adobe .act (Color Table) and
+//! .aco (Color Swatch) palette files.
+
+/// Size of an .act file holding 256 RGB entries
+const ACT_SIZE: usize = 768;
+/// Size of an .act file with the trailing color count and transparency index
+const ACT_SIZE_EXTENDED: usize = 772;
+
+/// Parses an Adobe act file provided as a vector of bytes into a vector of (r,g,b) tuples.
+pub fn parse_act_from_bytes(act_data: Vec<u8>) -> Result<Vec<(u8, u8, u8)>, &'static str> {
+	let count = match act_data.len() {
+		ACT_SIZE => 256,
+		ACT_SIZE_EXTENDED => {
+			let count = u16::from_be_bytes([act_data[768], act_data[769]]) as usize;
+			// some writers leave the count at 0 to mean all 256
+			match count {
+				0 => 256,
+				n if n > 256 => {
+					return Err("Invalid act file color count");
+				}
+				n => n,
+			}
+		}
+		_ => {
+			return Err("Invalid act file size");
+		}
+	};
+
+	Ok(act_data[..count * 3]
+		.chunks(3)
+		.map(|c| (c[0], c[1], c[2]))
+		.collect())
+}
+
+/// Parses an Adobe aco file provided as a vector of bytes into a vector of (r,g,b) tuples.
+/// Only RGB and grayscale swatches are supported
+pub fn parse_aco_from_bytes(aco_data: Vec<u8>) -> Result<Vec<(u8, u8, u8)>, &'static str> {
+	let read_u16 = |pos: usize| -> Result<u16, &'static str> {
+		match aco_data.get(pos..pos + 2) {
+			Some(b) => Ok(u16::from_be_bytes([b[0], b[1]])),
+			None => Err("Unexpected end of aco file"),
+		}
+	};
+	let read_u32 = |pos: usize| -> Result<u32, &'static str> {
+		match aco_data.get(pos..pos + 4) {
+			Some(b) => Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]])),
+			None => Err("Unexpected end of aco file"),
+		}
+	};
+
+	// Version 1 section, optionally followed by a version 2 section holding
+	// the same colors along with their names. Only the first section is read
+	let version = read_u16(0)?;
+	if version != 1 && version != 2 {
+		return Err("Unsupported aco file version");
+	}
+	let count = read_u16(2)? as usize;
+	let mut pos = 4;
+	let mut colors = vec![];
+	for _ in 0..count {
+		let space = read_u16(pos)?;
+		let w = read_u16(pos + 2)?;
+		let x = read_u16(pos + 4)?;
+		let y = read_u16(pos + 6)?;
+		pos += 10;
+		let color = match space {
+			// RGB, 0-65535 per channel
+			0 => ((w >> 8) as u8, (x >> 8) as u8, (y >> 8) as u8),
+			// Grayscale, 0-10000 as ink coverage (10000 is black)
+			8 => {
+				if w > 10000 {
+					return Err("Invalid aco grayscale value");
+				}
+				let v = 255 - (w as u32 * 255 / 10000) as u8;
+				(v, v, v)
+			}
+			_ => {
+				return Err("Unsupported aco color space, only RGB and grayscale are supported");
+			}
+		};
+		colors.push(color);
+		if version == 2 {
+			// skip the name, a UTF-16 string with its length in code units
+			let len = read_u32(pos)? as usize;
+			pos += 4 + len * 2;
+		}
+	}
+
+	Ok(colors)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	#[test]
+	fn test_parse_act_from_bytes() {
+		let test_act = include_bytes!("data/palette-adobe.act");
+		let act = parse_act_from_bytes(test_act.to_vec()).unwrap();
+		assert_eq!(act[0], (0, 0, 0));
+		assert_eq!(act[2], (255, 255, 255));
+		assert_eq!(act[4], (136, 102, 17));
+		assert_eq!(act.len(), 16);
+
+		// without the trailing count, all 256 entries are read
+		let act = parse_act_from_bytes(test_act[..768].to_vec()).unwrap();
+		assert_eq!(act.len(), 256);
+
+		let invalid_act = include_bytes!("data/palette-gimp.gpl");
+		assert!(parse_act_from_bytes(invalid_act.to_vec()).is_err());
+	}
+
+	#[test]
+	fn test_parse_aco_from_bytes() {
+		let test_aco = include_bytes!("data/palette-adobe.aco");
+		let aco = parse_aco_from_bytes(test_aco.to_vec()).unwrap();
+		assert_eq!(aco[0], (0, 0, 0));
+		assert_eq!(aco[2], (255, 255, 255));
+		assert_eq!(aco[4], (136, 102, 17));
+		assert_eq!(aco.len(), 16);
+
+		// version 2 section on its own
+		let v2_start = 4 + 16 * 10;
+		let aco = parse_aco_from_bytes(test_aco[v2_start..].to_vec()).unwrap();
+		assert_eq!(aco[4], (136, 102, 17));
+		assert_eq!(aco.len(), 16);
+
+		assert!(parse_aco_from_bytes(test_aco[..20].to_vec()).is_err());
+		let cmyk = [0, 1, 0, 1, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0];
+		assert!(parse_aco_from_bytes(cmyk.to_vec()).is_err());
+	}
+}
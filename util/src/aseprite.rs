@@ -0,0 +1,540 @@
+// Copyright 2020 Revcore Technologies Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Implements basic support for reading Aseprite .ase/.aseprite files, as
+//! described in Aseprite's docs/ase-file-specs.md. Layers, cels, tags,
+//! frame durations and the palette are read, and visible layers can be
+//! flattened into RGBA frames with normal blending
+
+use flate2::read::ZlibDecoder;
+use std::io::Read;
+
+const TRUNCATED: &str = "Aseprite file is truncated";
+
+/// Little endian reads over a byte slice
+struct Reader<'a> {
+	data: &'a [u8],
+	pos: usize,
+}
+
+impl<'a> Reader<'a> {
+	fn bytes(&mut self, len: usize) -> Result<&'a [u8], &'static str> {
+		match self.data.get(self.pos..self.pos + len) {
+			Some(b) => {
+				self.pos += len;
+				Ok(b)
+			}
+			None => Err(TRUNCATED),
+		}
+	}
+
+	fn u8(&mut self) -> Result<u8, &'static str> {
+		Ok(self.bytes(1)?[0])
+	}
+
+	fn u16(&mut self) -> Result<u16, &'static str> {
+		let b = self.bytes(2)?;
+		Ok(u16::from_le_bytes([b[0], b[1]]))
+	}
+
+	fn i16(&mut self) -> Result<i16, &'static str> {
+		Ok(self.u16()? as i16)
+	}
+
+	fn u32(&mut self) -> Result<u32, &'static str> {
+		let b = self.bytes(4)?;
+		Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+	}
+
+	fn string(&mut self) -> Result<String, &'static str> {
+		let len = self.u16()? as usize;
+		match String::from_utf8(self.bytes(len)?.to_vec()) {
+			Ok(s) => Ok(s),
+			Err(_) => Err("Invalid string in Aseprite file"),
+		}
+	}
+}
+
+/// A layer, in the order they're stacked from the bottom
+#[derive(Clone, Debug)]
+pub struct AsepriteLayer {
+	/// Layer name
+	pub name: String,
+	/// Whether the layer and all the groups containing it are visible
+	pub visible: bool,
+	/// Whether this is an image layer, rather than a group or tilemap
+	pub is_image: bool,
+	/// Whether this is the background layer, which is always opaque
+	pub is_background: bool,
+	/// Layer opacity
+	pub opacity: u8,
+}
+
+/// A tag, naming a range of frames
+#[derive(Clone, Debug)]
+pub struct AsepriteTag {
+	/// Tag name
+	pub name: String,
+	/// First frame
+	pub from: usize,
+	/// Last frame (inclusive)
+	pub to: usize,
+	/// Animation direction: 0 forward, 1 reverse, 2 ping-pong,
+	/// 3 ping-pong reverse
+	pub direction: u8,
+}
+
+/// Image data of a layer in a frame
+#[derive(Clone, Debug)]
+enum CelData {
+	/// Width, height and raw pixel data at the file's colour depth
+	Image(usize, usize, Vec<u8>),
+	/// Same cel as in the given frame
+	Linked(usize),
+}
+
+#[derive(Clone, Debug)]
+struct AsepriteCel {
+	layer: usize,
+	x: i16,
+	y: i16,
+	opacity: u8,
+	data: CelData,
+}
+
+/// A single animation frame
+#[derive(Clone, Debug)]
+pub struct AsepriteFrame {
+	/// Duration in milliseconds
+	pub duration: u16,
+	cels: Vec<AsepriteCel>,
+}
+
+/// The contents of an Aseprite file
+#[derive(Clone, Debug)]
+pub struct AsepriteFile {
+	/// Canvas width
+	pub width: u32,
+	/// Canvas height
+	pub height: u32,
+	/// Colour depth: 32 (RGBA), 16 (grayscale) or 8 (indexed)
+	pub depth: u16,
+	/// Palette index treated as transparent in indexed files
+	pub transparent_index: u8,
+	/// Layers, from the bottom up
+	pub layers: Vec<AsepriteLayer>,
+	/// Animation frames
+	pub frames: Vec<AsepriteFrame>,
+	/// Frame tags
+	pub tags: Vec<AsepriteTag>,
+	/// Palette as RGBA values
+	pub palette: Vec<[u8; 4]>,
+}
+
+/// Parses an Aseprite file provided as a slice of bytes
+pub fn parse_aseprite_from_bytes(ase_data: &[u8]) -> Result<AsepriteFile, &'static str> {
+	let mut r = Reader {
+		data: ase_data,
+		pos: 4,
+	};
+	if r.u16()? != 0xa5e0 {
+		return Err("Invalid Aseprite file header");
+	}
+	let frame_count = r.u16()? as usize;
+	let (width, height, depth) = (r.u16()? as u32, r.u16()? as u32, r.u16()?);
+	if depth != 32 && depth != 16 && depth != 8 {
+		return Err("Unsupported Aseprite colour depth");
+	}
+	let layer_opacity_valid = r.u32()? & 1 != 0;
+	r.pos = 28;
+	let transparent_index = r.u8()?;
+	r.pos = 128;
+
+	let mut file = AsepriteFile {
+		width,
+		height,
+		depth,
+		transparent_index,
+		layers: vec![],
+		frames: vec![],
+		tags: vec![],
+		palette: vec![],
+	};
+	// visibility of the groups containing the current layer, by child level
+	let mut group_visible: Vec<bool> = vec![];
+	let mut new_palette = false;
+	for _ in 0..frame_count {
+		let frame_start = r.pos;
+		let frame_len = r.u32()? as usize;
+		if r.u16()? != 0xf1fa {
+			return Err("Invalid Aseprite frame header");
+		}
+		let old_chunks = r.u16()? as u32;
+		let duration = r.u16()?;
+		r.pos += 2;
+		let chunks = match r.u32()? {
+			0 => old_chunks,
+			n => n,
+		};
+		let mut frame = AsepriteFrame {
+			duration,
+			cels: vec![],
+		};
+		for _ in 0..chunks {
+			let chunk_start = r.pos;
+			let chunk_len = r.u32()? as usize;
+			let chunk_type = r.u16()?;
+			let mut c = Reader {
+				data: r.bytes(chunk_len.saturating_sub(6))?,
+				pos: 0,
+			};
+			match chunk_type {
+				0x0004 if !new_palette => {
+					let mut index = 0;
+					for _ in 0..c.u16()? {
+						index += c.u8()? as usize;
+						let count = match c.u8()? {
+							0 => 256,
+							n => n as usize,
+						};
+						for _ in 0..count {
+							let rgb = c.bytes(3)?;
+							if file.palette.len() <= index {
+								file.palette.resize(index + 1, [0, 0, 0, 255]);
+							}
+							file.palette[index] = [rgb[0], rgb[1], rgb[2], 255];
+							index += 1;
+						}
+					}
+				}
+				0x2004 => {
+					let flags = c.u16()?;
+					let layer_type = c.u16()?;
+					let level = c.u16()? as usize;
+					c.pos += 6;
+					let opacity = c.u8()?;
+					c.pos += 3;
+					let name = c.string()?;
+					group_visible.truncate(level);
+					let visible = flags & 1 != 0 && group_visible.iter().all(|v| *v);
+					group_visible.push(visible);
+					file.layers.push(AsepriteLayer {
+						name,
+						visible,
+						is_image: layer_type == 0,
+						is_background: flags & 8 != 0,
+						opacity: match layer_opacity_valid {
+							true => opacity,
+							false => 255,
+						},
+					});
+				}
+				0x2005 => {
+					let (layer, x, y) = (c.u16()? as usize, c.i16()?, c.i16()?);
+					let opacity = c.u8()?;
+					let cel_type = c.u16()?;
+					c.pos += 7;
+					let data = match cel_type {
+						0 | 2 => {
+							let (w, h) = (c.u16()? as usize, c.u16()? as usize);
+							let size = w * h * depth as usize / 8;
+							let rest = &c.data[c.pos.min(c.data.len())..];
+							let mut pixels = vec![];
+							match cel_type {
+								0 => pixels.extend_from_slice(rest),
+								_ => {
+									// stop a byte past the cel's size, rather than
+									// inflating whatever the stream claims
+									let mut decoder = ZlibDecoder::new(rest).take(size as u64 + 1);
+									if decoder.read_to_end(&mut pixels).is_err() {
+										return Err("Invalid compressed Aseprite cel");
+									}
+								}
+							}
+							if pixels.len() < size {
+								return Err(TRUNCATED);
+							}
+							if pixels.len() > size {
+								return Err("Aseprite cel holds more pixels than its size");
+							}
+							Some(CelData::Image(w, h, pixels))
+						}
+						1 => Some(CelData::Linked(c.u16()? as usize)),
+						// tilemap cels aren't supported
+						_ => None,
+					};
+					if let Some(data) = data {
+						frame.cels.push(AsepriteCel {
+							layer,
+							x,
+							y,
+							opacity,
+							data,
+						});
+					}
+				}
+				0x2018 => {
+					let count = c.u16()?;
+					c.pos += 8;
+					for _ in 0..count {
+						let (from, to) = (c.u16()? as usize, c.u16()? as usize);
+						let direction = c.u8()?;
+						c.pos += 12;
+						let name = c.string()?;
+						file.tags.push(AsepriteTag {
+							name,
+							from,
+							to,
+							direction,
+						});
+					}
+				}
+				0x2019 => {
+					new_palette = true;
+					let size = c.u32()? as usize;
+					let (first, last) = (c.u32()? as usize, c.u32()? as usize);
+					c.pos += 8;
+					if first > last || last > 255 {
+						return Err("Invalid Aseprite palette range");
+					}
+					// each entry takes at least 6 bytes
+					if (last - first + 1) * 6 > c.data.len().saturating_sub(c.pos) {
+						return Err(TRUNCATED);
+					}
+					file.palette
+						.resize(size.min(256).max(last + 1), [0, 0, 0, 255]);
+					for entry in file.palette[first..=last].iter_mut() {
+						let flags = c.u16()?;
+						let rgba = c.bytes(4)?;
+						*entry = [rgba[0], rgba[1], rgba[2], rgba[3]];
+						if flags & 1 != 0 {
+							c.string()?;
+						}
+					}
+				}
+				_ => {}
+			}
+			r.pos = chunk_start + chunk_len.max(6);
+		}
+		file.frames.push(frame);
+		r.pos = frame_start + frame_len.max(16);
+	}
+	Ok(file)
+}
+
+impl AsepriteFile {
+	/// Index of the layer with the given name
+	pub fn layer_index(&self, name: &str) -> Option<usize> {
+		self.layers.iter().position(|l| l.name == name)
+	}
+
+	/// RGBA value of a cel pixel
+	fn cel_pixel(&self, layer: &AsepriteLayer, data: &[u8], i: usize) -> [u8; 4] {
+		match self.depth {
+			32 => [
+				data[i * 4],
+				data[i * 4 + 1],
+				data[i * 4 + 2],
+				data[i * 4 + 3],
+			],
+			16 => [data[i * 2], data[i * 2], data[i * 2], data[i * 2 + 1]],
+			_ => {
+				let index = data[i];
+				if index == self.transparent_index && !layer.is_background {
+					return [0; 4];
+				}
+				match self.palette.get(index as usize) {
+					Some(p) => *p,
+					None => [0; 4],
+				}
+			}
+		}
+	}
+
+	/// Flatten a frame into its RGBA values, one per pixel, blending either
+	/// the visible image layers or just the given layer
+	pub fn flatten_frame(
+		&self,
+		frame: usize,
+		layer: Option<usize>,
+	) -> Result<Vec<[u8; 4]>, &'static str> {
+		let (width, height) = (self.width as usize, self.height as usize);
+		let mut canvas = vec![[0u8; 4]; width * height];
+		let cels = match self.frames.get(frame) {
+			Some(f) => &f.cels,
+			None => return Err("Aseprite frame out of range"),
+		};
+		let mut cels: Vec<&AsepriteCel> = cels.iter().collect();
+		cels.sort_by_key(|c| c.layer);
+		for cel in cels {
+			let l = match self.layers.get(cel.layer) {
+				Some(l) => l,
+				None => return Err("Aseprite cel refers to a missing layer"),
+			};
+			let included = match layer {
+				Some(i) => i == cel.layer,
+				None => l.visible,
+			};
+			if !included || !l.is_image {
+				continue;
+			}
+			let data = match &cel.data {
+				CelData::Image(..) => &cel.data,
+				CelData::Linked(f) => match self.frames.get(*f).and_then(|f| {
+					f.cels
+						.iter()
+						.find(|c| c.layer == cel.layer && matches!(c.data, CelData::Image(..)))
+				}) {
+					Some(c) => &c.data,
+					None => return Err("Aseprite linked cel not found"),
+				},
+			};
+			let (w, h, pixels) = match data {
+				CelData::Image(w, h, p) => (*w, *h, p),
+				_ => continue,
+			};
+			let opacity = cel.opacity as u32 * l.opacity as u32;
+			for i in 0..w * h {
+				let x = cel.x as isize + (i % w) as isize;
+				let y = cel.y as isize + (i / w) as isize;
+				if x < 0 || y < 0 || x >= width as isize || y >= height as isize {
+					continue;
+				}
+				let [r, g, b, a] = self.cel_pixel(l, pixels, i);
+				let src_a = a as u32 * opacity / (255 * 255);
+				if src_a == 0 {
+					continue;
+				}
+				let dst = &mut canvas[y as usize * width + x as usize];
+				let dst_a = dst[3] as u32 * (255 - src_a) / 255;
+				let out_a = src_a + dst_a;
+				let blend = |s: u8, d: u8| ((s as u32 * src_a + d as u32 * dst_a) / out_a) as u8;
+				*dst = [
+					blend(r, dst[0]),
+					blend(g, dst[1]),
+					blend(b, dst[2]),
+					out_a as u8,
+				];
+			}
+		}
+		Ok(canvas)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use flate2::write::ZlibEncoder;
+	use flate2::Compression;
+	use std::io::Write;
+	#[test]
+	fn test_parse_aseprite_from_bytes() {
+		let test_ase = include_bytes!("data/sprite-rgba.aseprite");
+		let ase = parse_aseprite_from_bytes(test_ase).unwrap();
+		assert_eq!((ase.width, ase.height, ase.depth), (2, 2, 32));
+		assert_eq!(ase.layers.len(), 4);
+		assert_eq!(ase.layer_index("top"), Some(3));
+		assert!(!ase.layers[2].visible);
+		let durations: Vec<u16> = ase.frames.iter().map(|f| f.duration).collect();
+		assert_eq!(durations, vec![100, 200]);
+		assert_eq!(ase.tags[0].name, "walk");
+		assert_eq!((ase.tags[0].from, ase.tags[0].to), (0, 1));
+		assert_eq!(ase.tags[0].direction, 2);
+		assert_eq!(ase.palette.len(), 4);
+
+		let (red, green, blue) = ([255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]);
+		assert_eq!(
+			ase.flatten_frame(0, None).unwrap(),
+			vec![red, red, red, blue]
+		);
+		// linked background cel with a half opaque cel over it
+		let blended = [127, 0, 128, 255];
+		assert_eq!(
+			ase.flatten_frame(1, None).unwrap(),
+			vec![blended, red, red, red]
+		);
+		// hidden layers can still be picked out on their own
+		assert_eq!(ase.flatten_frame(0, Some(2)).unwrap(), vec![green; 4]);
+		assert!(ase.flatten_frame(2, None).is_err());
+
+		let test_ase = include_bytes!("data/sprite-indexed.aseprite");
+		let ase = parse_aseprite_from_bytes(test_ase).unwrap();
+		assert_eq!(
+			ase.flatten_frame(0, None).unwrap(),
+			vec![[0, 0, 0, 0], [17, 102, 136, 255]]
+		);
+
+		assert!(parse_aseprite_from_bytes(&test_ase[..140]).is_err());
+
+		// palette chunks can't claim more entries than a palette or the chunk holds
+		let palette_file = |size: u32, last: u32, entries: usize| {
+			let mut chunk = vec![];
+			for v in [size, 0, last, 0, 0].iter() {
+				chunk.extend_from_slice(&v.to_le_bytes());
+			}
+			for _ in 0..entries {
+				chunk.extend_from_slice(&[0, 0, 1, 2, 3, 255]);
+			}
+			let mut data = vec![0; 128];
+			data[4..14].copy_from_slice(&[0xe0, 0xa5, 1, 0, 1, 0, 1, 0, 32, 0]);
+			let frame_len = 16 + 6 + chunk.len() as u32;
+			data.extend_from_slice(&frame_len.to_le_bytes());
+			data.extend_from_slice(&[0xfa, 0xf1, 1, 0, 100, 0, 0, 0, 1, 0, 0, 0]);
+			data.extend_from_slice(&(6 + chunk.len() as u32).to_le_bytes());
+			data.extend_from_slice(&[0x19, 0x20]);
+			data.extend_from_slice(&chunk);
+			parse_aseprite_from_bytes(&data)
+		};
+		assert_eq!(palette_file(1, 0, 1).unwrap().palette, vec![[1, 2, 3, 255]]);
+		assert_eq!(palette_file(u32::MAX, 0, 1).unwrap().palette.len(), 256);
+		assert!(palette_file(u32::MAX, 255, 1).is_err());
+		assert!(palette_file(u32::MAX, u32::MAX, 1).is_err());
+		assert!(parse_aseprite_from_bytes(include_bytes!("data/palette-jasc.pal")).is_err());
+
+		// cels must hold exactly as many pixels as their size, however
+		// much a compressed one would inflate to
+		let cel_file = |cel_type: u16, pixels: &[u8]| {
+			let mut chunk = vec![0, 0, 0, 0, 0, 0, 255];
+			chunk.extend_from_slice(&cel_type.to_le_bytes());
+			chunk.extend_from_slice(&[0; 7]);
+			chunk.extend_from_slice(&[1, 0, 1, 0]);
+			chunk.extend_from_slice(pixels);
+			let mut data = vec![0; 128];
+			data[4..14].copy_from_slice(&[0xe0, 0xa5, 1, 0, 1, 0, 1, 0, 32, 0]);
+			let frame_len = 16 + 6 + chunk.len() as u32;
+			data.extend_from_slice(&frame_len.to_le_bytes());
+			data.extend_from_slice(&[0xfa, 0xf1, 1, 0, 100, 0, 0, 0, 1, 0, 0, 0]);
+			data.extend_from_slice(&(6 + chunk.len() as u32).to_le_bytes());
+			data.extend_from_slice(&[0x05, 0x20]);
+			data.extend_from_slice(&chunk);
+			parse_aseprite_from_bytes(&data)
+		};
+		let compress = |pixels: &[u8]| {
+			let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+			encoder.write_all(pixels).unwrap();
+			encoder.finish().unwrap()
+		};
+		let cel_pixels = |ase: AsepriteFile| match &ase.frames[0].cels[0].data {
+			CelData::Image(_, _, pixels) => pixels.clone(),
+			CelData::Linked(_) => vec![],
+		};
+		let pixel = [1, 2, 3, 255];
+		assert_eq!(cel_pixels(cel_file(0, &pixel).unwrap()), pixel);
+		assert!(cel_file(0, &pixel[..3]).is_err());
+		assert!(cel_file(0, &[0; 5]).is_err());
+		assert_eq!(cel_pixels(cel_file(2, &compress(&pixel)).unwrap()), pixel);
+		assert!(cel_file(2, &compress(&pixel[..3])).is_err());
+		assert!(cel_file(2, &compress(&vec![0; 1 << 20])).is_err());
+	}
+}
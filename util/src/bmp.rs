@@ -0,0 +1,232 @@
+// Copyright 2020 Revcore Technologies Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Implements basic support for reading uncompressed Windows .bmp images,
+//! either indexed (1, 4 or 8 bit) or true colour (16, 24 or 32 bit)
+
+fn read_u16(data: &[u8], at: usize) -> Result<u16, &'static str> {
+	match data.get(at..at + 2) {
+		Some(b) => Ok(u16::from_le_bytes([b[0], b[1]])),
+		None => Err("BMP file is truncated"),
+	}
+}
+
+fn read_u32(data: &[u8], at: usize) -> Result<u32, &'static str> {
+	match data.get(at..at + 4) {
+		Some(b) => Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+		None => Err("BMP file is truncated"),
+	}
+}
+
+/// Scale the bits of `value` selected by `mask` to 8 bits
+fn masked_channel(value: u32, mask: u32) -> u8 {
+	if mask == 0 {
+		return 0;
+	}
+	let shift = mask.trailing_zeros();
+	let max = (mask >> shift) as u64;
+	((((value & mask) >> shift) as u64 * 255 + max / 2) / max) as u8
+}
+
+/// Parses a BMP file into width, height and a flat list of RGBA values,
+/// one per pixel, starting from the top row
+pub fn parse_bmp_from_bytes(bmp_data: &[u8]) -> Result<(u32, u32, Vec<[u8; 4]>), &'static str> {
+	if bmp_data.get(0..2) != Some(b"BM") {
+		return Err("Invalid BMP file header");
+	}
+	let pixel_start = read_u32(bmp_data, 10)? as usize;
+	let header_size = read_u32(bmp_data, 14)? as usize;
+	let (width, height, bpp, compression, colors_used, pal_entry_size) = match header_size {
+		12 => (
+			read_u16(bmp_data, 18)? as i32,
+			read_u16(bmp_data, 20)? as i32,
+			read_u16(bmp_data, 24)? as usize,
+			0,
+			0,
+			3,
+		),
+		40 | 52 | 56 | 108 | 124 => (
+			read_u32(bmp_data, 18)? as i32,
+			read_u32(bmp_data, 22)? as i32,
+			read_u16(bmp_data, 28)? as usize,
+			read_u32(bmp_data, 30)?,
+			read_u32(bmp_data, 46)? as usize,
+			4,
+		),
+		_ => return Err("Unsupported BMP header version"),
+	};
+	if width <= 0 || height == 0 {
+		return Err("Invalid BMP dimensions");
+	}
+	// Rows are stored bottom up unless the height is negative
+	let top_down = height < 0;
+	let (width, height) = (width as usize, height.unsigned_abs() as usize);
+
+	// Red, green, blue and alpha masks for true colour pixels
+	let masks = match (compression, bpp) {
+		(0, 1) | (0, 4) | (0, 8) | (0, 24) => [0; 4],
+		(0, 16) => [0x7c00, 0x03e0, 0x001f, 0],
+		(0, 32) => [0x00ff_0000, 0x0000_ff00, 0x0000_00ff, 0],
+		(3, 16) | (3, 32) => [
+			read_u32(bmp_data, 54)?,
+			read_u32(bmp_data, 58)?,
+			read_u32(bmp_data, 62)?,
+			match header_size >= 56 {
+				true => read_u32(bmp_data, 66)?,
+				false => 0,
+			},
+		],
+		(0, _) | (3, _) => return Err("Unsupported BMP bit depth"),
+		_ => return Err("Compressed BMP files aren't supported"),
+	};
+
+	let palette: Vec<[u8; 4]> = match bpp <= 8 {
+		true => {
+			let count = match colors_used {
+				0 => 1 << bpp,
+				n => n,
+			};
+			let start = 14 + header_size;
+			match bmp_data.get(start..start + count * pal_entry_size) {
+				Some(p) => p
+					.chunks(pal_entry_size)
+					.map(|c| [c[2], c[1], c[0], 255])
+					.collect(),
+				None => return Err("BMP palette is truncated"),
+			}
+		}
+		false => vec![],
+	};
+
+	let stride = (width * bpp).div_ceil(32) * 4;
+	// Make sure every row is there before allocating for them
+	match stride
+		.checked_mul(height)
+		.and_then(|n| n.checked_add(pixel_start))
+	{
+		Some(end) if end <= bmp_data.len() => {}
+		_ => return Err("BMP pixel data is truncated"),
+	}
+	let mut pixels = Vec::with_capacity(width * height);
+	for y in 0..height {
+		let row = match top_down {
+			true => y,
+			false => height - 1 - y,
+		};
+		let start = pixel_start + row * stride;
+		let row = match bmp_data.get(start..start + stride) {
+			Some(r) => r,
+			None => return Err("BMP pixel data is truncated"),
+		};
+		for x in 0..width {
+			let pixel = match bpp {
+				1 | 4 | 8 => {
+					let bit = x * bpp;
+					let index = row[bit / 8] >> (8 - bpp - bit % 8) & ((1u16 << bpp) - 1) as u8;
+					match palette.get(index as usize) {
+						Some(p) => *p,
+						None => return Err("BMP pixel index is outside the palette"),
+					}
+				}
+				24 => [row[x * 3 + 2], row[x * 3 + 1], row[x * 3], 255],
+				_ => {
+					let value = match bpp {
+						16 => read_u16(row, x * 2)? as u32,
+						_ => read_u32(row, x * 4)?,
+					};
+					let [r, g, b, a] = masks;
+					[
+						masked_channel(value, r),
+						masked_channel(value, g),
+						masked_channel(value, b),
+						match a {
+							0 => 255,
+							a => masked_channel(value, a),
+						},
+					]
+				}
+			};
+			pixels.push(pixel);
+		}
+	}
+	Ok((width as u32, height as u32, pixels))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	/// Build a bottom up BMP with a 40 byte header around the given rows
+	fn build_bmp(width: u32, height: i32, bpp: u16, palette: &[[u8; 3]], rows: &[u8]) -> Vec<u8> {
+		let pixel_start = 54 + palette.len() as u32 * 4;
+		let mut bmp = b"BM".to_vec();
+		bmp.extend(&(pixel_start + rows.len() as u32).to_le_bytes());
+		bmp.extend(&[0; 4]);
+		bmp.extend(&pixel_start.to_le_bytes());
+		bmp.extend(&40u32.to_le_bytes());
+		bmp.extend(&width.to_le_bytes());
+		bmp.extend(&height.to_le_bytes());
+		bmp.extend(&1u16.to_le_bytes());
+		bmp.extend(&bpp.to_le_bytes());
+		bmp.extend(&[0; 16]);
+		bmp.extend(&(palette.len() as u32).to_le_bytes());
+		bmp.extend(&[0; 4]);
+		for [r, g, b] in palette {
+			bmp.extend(&[*b, *g, *r, 0]);
+		}
+		bmp.extend(rows);
+		bmp
+	}
+
+	#[test]
+	fn test_parse_bmp_from_bytes() {
+		// 3x2 24 bit, rows padded to 12 bytes and stored bottom up
+		let rows = [
+			0, 0, 255, 0, 255, 0, 255, 0, 0, 0, 0, 0, // red, green, blue
+			255, 255, 255, 0, 0, 0, 17, 102, 136, 0, 0, 0, // white, black, brown
+		];
+		let (w, h, pixels) = parse_bmp_from_bytes(&build_bmp(3, 2, 24, &[], &rows)).unwrap();
+		assert_eq!((w, h), (3, 2));
+		assert_eq!(pixels[0], [255, 255, 255, 255]);
+		assert_eq!(pixels[2], [136, 102, 17, 255]);
+		assert_eq!(pixels[3], [255, 0, 0, 255]);
+		assert_eq!(pixels[5], [0, 0, 255, 255]);
+
+		// top down rows when the height is negative
+		let (_, _, pixels) = parse_bmp_from_bytes(&build_bmp(3, -2, 24, &[], &rows)).unwrap();
+		assert_eq!(pixels[0], [255, 0, 0, 255]);
+
+		// 4 bit indexed, 5x1
+		let palette = [[0, 0, 0], [255, 255, 255], [136, 102, 17]];
+		let rows = [0x12, 0x01, 0x20, 0];
+		let (w, _, pixels) = parse_bmp_from_bytes(&build_bmp(5, 1, 4, &palette, &rows)).unwrap();
+		assert_eq!(w, 5);
+		let expected = [[255, 255, 255, 255], [136, 102, 17, 255], [0, 0, 0, 255]];
+		assert_eq!(pixels[..3], expected);
+		assert_eq!(pixels[4], [136, 102, 17, 255]);
+
+		// 16 bit 5-5-5
+		let rows = [0x00, 0x7c, 0, 0];
+		let (_, _, pixels) = parse_bmp_from_bytes(&build_bmp(1, 1, 16, &[], &rows)).unwrap();
+		assert_eq!(pixels[0], [255, 0, 0, 255]);
+
+		// index past the end of the palette
+		let rows = [0x30, 0, 0, 0];
+		assert!(parse_bmp_from_bytes(&build_bmp(1, 1, 4, &palette, &rows)).is_err());
+		assert!(parse_bmp_from_bytes(&build_bmp(3, 2, 24, &[], &[0; 12])).is_err());
+		let huge = build_bmp(0x7fff_ffff, 0x7fff_ffff, 32, &[], &[0; 4]);
+		assert!(parse_bmp_from_bytes(&huge).is_err());
+		assert!(parse_bmp_from_bytes(b"GIF89a").is_err());
+	}
+}
@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-//! Implements basic support for reading Gimp .gpl files.
+//! Implements basic support for reading and writing Gimp .gpl files.
 
 /// Parses a Gimp gpl file provided as a vector of bytes into a vector of (r,g,b) tuples.
 pub fn parse_gpl_from_bytes(gpl_data: Vec<u8>) -> Result<Vec<(u8, u8, u8)>, &'static str> {
@@ -93,6 +93,19 @@ pub fn parse_gpl_from_bytes(gpl_data: Vec<u8>) -> Result<Vec<(u8, u8, u8)>, &'st
 	Ok(colors)
 }
 
+/// Writes a vector of (r,g,b) tuples out as the contents of a Gimp gpl file.
+pub fn write_gpl_to_bytes(name: &str, colors: &[(u8, u8, u8)]) -> Vec<u8> {
+	let mut gpl_string = String::new();
+	gpl_string.push_str("GIMP Palette\n");
+	gpl_string.push_str(&format!("Name: {}\n", name));
+	gpl_string.push_str("Columns: 16\n");
+	gpl_string.push_str("#\n");
+	for (i, (r, g, b)) in colors.iter().enumerate() {
+		gpl_string.push_str(&format!("{:3} {:3} {:3}\tIndex {}\n", r, g, b, i));
+	}
+	gpl_string.into_bytes()
+}
+
 #[cfg(test)]
 mod test {
 	use super::*;
@@ -129,4 +142,11 @@ mod test {
 			}
 		}
 	}
+
+	#[test]
+	fn test_write_gpl_to_bytes() {
+		let colors = vec![(0, 0, 0), (255, 255, 255), (136, 102, 17)];
+		let gpl = write_gpl_to_bytes("TestPalette", &colors);
+		assert_eq!(parse_gpl_from_bytes(gpl).unwrap(), colors);
+	}
 }
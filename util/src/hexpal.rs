@@ -0,0 +1,77 @@
+// Copyright 2020 Revcore Technologies Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Implements basic support for reading plain text .hex palette files, as
+//! exported by Lospec. Each line holds a single RRGGBB value.
+
+/// Parses a hex palette file provided as a vector of bytes into a vector of (r,g,b) tuples.
+pub fn parse_hex_palette_from_bytes(hex_data: Vec<u8>) -> Result<Vec<(u8, u8, u8)>, &'static str> {
+	let hex_string = match String::from_utf8(hex_data) {
+		Ok(s) => s,
+		_ => {
+			return Err("Invalid hex palette file content");
+		}
+	};
+
+	fn parse_channel(s: &str) -> Result<u8, &'static str> {
+		match u8::from_str_radix(s, 16) {
+			Ok(n) => Ok(n),
+			_ => Err("Failed to parse hex color value"),
+		}
+	}
+
+	let mut colors = vec![];
+	for line in hex_string.lines() {
+		let line = line.trim();
+		// allow for blank lines and paint.net style comments
+		if line.is_empty() || line.starts_with(';') {
+			continue;
+		}
+		let line = line.trim_start_matches('#');
+		if line.len() != 6 || !line.is_ascii() {
+			return Err("Invalid hex palette file line");
+		}
+		let r = parse_channel(&line[0..2])?;
+		let g = parse_channel(&line[2..4])?;
+		let b = parse_channel(&line[4..6])?;
+		colors.push((r, g, b));
+	}
+	if colors.is_empty() {
+		return Err("Hex palette file contains no colors");
+	}
+
+	Ok(colors)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	#[test]
+	fn test_parse_hex_palette_from_bytes() {
+		let test_hex = include_bytes!("data/palette-lospec.hex");
+		let hex = parse_hex_palette_from_bytes(test_hex.to_vec()).unwrap();
+		assert_eq!(hex[0], (0, 0, 0));
+		assert_eq!(hex[2], (255, 255, 255));
+		assert_eq!(hex[4], (136, 102, 17));
+		assert_eq!(hex.len(), 16);
+
+		let with_hashes = b"; comment\n#FF0000\n\n#00ff00\n";
+		let hex = parse_hex_palette_from_bytes(with_hashes.to_vec()).unwrap();
+		assert_eq!(hex, vec![(255, 0, 0), (0, 255, 0)]);
+
+		let invalid_hex = include_bytes!("data/palette-gimp.gpl");
+		assert!(parse_hex_palette_from_bytes(invalid_hex.to_vec()).is_err());
+		assert!(parse_hex_palette_from_bytes(b"ff00zz\n".to_vec()).is_err());
+	}
+}
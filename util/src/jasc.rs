@@ -0,0 +1,108 @@
+// Copyright 2020 Revcore Technologies Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Implements basic support for reading and writing JASC (Paint Shop Pro / Aseprite) .pal files.
+
+/// Parses a JASC pal file provided as a vector of bytes into a vector of (r,g,b) tuples.
+pub fn parse_jasc_from_bytes(jasc_data: Vec<u8>) -> Result<Vec<(u8, u8, u8)>, &'static str> {
+	let jasc_string = match String::from_utf8(jasc_data) {
+		Ok(s) => s,
+		_ => {
+			return Err("Invalid JASC pal file content");
+		}
+	};
+
+	fn parse_rgb_value(s: &str) -> Result<u8, &'static str> {
+		match s.parse::<u8>() {
+			Ok(n) => Ok(n),
+			_ => Err("Failed to parse rgb value"),
+		}
+	}
+
+	let mut lines = jasc_string.lines().map(|l| l.trim());
+	if lines.next() != Some("JASC-PAL") {
+		return Err("Invalid JASC pal file line 1");
+	}
+	if lines.next() != Some("0100") {
+		return Err("Unsupported JASC pal file version");
+	}
+	let count = match lines.next().map(|l| l.parse::<usize>()) {
+		Some(Ok(n)) => n,
+		_ => {
+			return Err("Invalid JASC pal file color count");
+		}
+	};
+
+	let mut colors = vec![];
+	for line in lines {
+		if line.is_empty() {
+			continue;
+		}
+		let mut split = line.split_whitespace();
+		match (split.next(), split.next(), split.next()) {
+			(Some(r_str), Some(g_str), Some(b_str)) => {
+				let r = parse_rgb_value(r_str)?;
+				let g = parse_rgb_value(g_str)?;
+				let b = parse_rgb_value(b_str)?;
+				colors.push((r, g, b));
+			}
+			_ => {
+				return Err("Invalid JASC pal file");
+			}
+		}
+	}
+	if colors.len() != count {
+		return Err("JASC pal file color count doesn't match its contents");
+	}
+
+	Ok(colors)
+}
+
+/// Writes a vector of (r,g,b) tuples out as the contents of a JASC pal file.
+pub fn write_jasc_to_bytes(colors: &[(u8, u8, u8)]) -> Vec<u8> {
+	let mut jasc_string = String::new();
+	jasc_string.push_str("JASC-PAL\r\n");
+	jasc_string.push_str("0100\r\n");
+	jasc_string.push_str(&format!("{}\r\n", colors.len()));
+	for (r, g, b) in colors.iter() {
+		jasc_string.push_str(&format!("{} {} {}\r\n", r, g, b));
+	}
+	jasc_string.into_bytes()
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	#[test]
+	fn test_parse_jasc_from_bytes() {
+		let test_jasc = include_bytes!("data/palette-jasc.pal");
+		let jasc = parse_jasc_from_bytes(test_jasc.to_vec()).unwrap();
+		assert_eq!(jasc[0], (0, 0, 0));
+		assert_eq!(jasc[2], (255, 255, 255));
+		assert_eq!(jasc[4], (136, 102, 17));
+		assert_eq!(jasc.len(), 16);
+
+		let invalid_jasc = include_bytes!("data/palette-gimp.gpl");
+		assert!(parse_jasc_from_bytes(invalid_jasc.to_vec()).is_err());
+		let truncated = b"JASC-PAL\r\n0100\r\n3\r\n0 0 0\r\n255 255 255\r\n";
+		assert!(parse_jasc_from_bytes(truncated.to_vec()).is_err());
+	}
+
+	#[test]
+	fn test_write_jasc_to_bytes() {
+		let colors = vec![(0, 0, 0), (255, 255, 255), (136, 102, 17)];
+		let jasc = write_jasc_to_bytes(&colors);
+		assert_eq!(parse_jasc_from_bytes(jasc).unwrap(), colors);
+	}
+}
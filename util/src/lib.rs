@@ -41,3 +41,13 @@ pub mod hex;
 
 // Parsing Gimp .gpl files
 pub mod gpl;
+
+// Parsing other palette formats
+pub mod adobe;
+pub mod hexpal;
+pub mod jasc;
+
+// Parsing other image formats
+pub mod aseprite;
+pub mod bmp;
+pub mod pcx;
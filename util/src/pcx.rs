@@ -0,0 +1,166 @@
+// Copyright 2020 Revcore Technologies Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Implements basic support for reading ZSoft .pcx images, either indexed
+//! (1 to 8 bits, packed or planar) or 24 bit RGB
+
+/// Parses a PCX file into width, height and a flat list of RGBA values,
+/// one per pixel, starting from the top row
+pub fn parse_pcx_from_bytes(pcx_data: &[u8]) -> Result<(u32, u32, Vec<[u8; 4]>), &'static str> {
+	if pcx_data.len() < 128 || pcx_data[0] != 0x0a {
+		return Err("Invalid PCX file header");
+	}
+	if pcx_data[2] != 1 {
+		return Err("Unsupported PCX encoding");
+	}
+	let word = |at: usize| u16::from_le_bytes([pcx_data[at], pcx_data[at + 1]]) as usize;
+	let (bpp, planes, line_bytes) = (pcx_data[3] as usize, pcx_data[65] as usize, word(66));
+	let (x_min, y_min, x_max, y_max) = (word(4), word(6), word(8), word(10));
+
+	// An empty palette means each plane holds one RGB(A) channel
+	let to_rgba = |c: &[u8]| [c[0], c[1], c[2], 255];
+	let palette: Vec<[u8; 4]> = match (bpp, planes) {
+		(8, 1) => {
+			// 256 colour palettes follow the image data, after a 0x0c marker
+			match pcx_data.len().checked_sub(769) {
+				Some(start) if start >= 128 && pcx_data[start] == 0x0c => {
+					pcx_data[start + 1..].chunks(3).map(to_rgba).collect()
+				}
+				_ => return Err("PCX file is missing its 256 colour palette"),
+			}
+		}
+		(1, 1) => vec![[0, 0, 0, 255], [255, 255, 255, 255]],
+		(1, 2) | (1, 3) | (1, 4) | (2, 1) | (4, 1) => {
+			pcx_data[16..64].chunks(3).map(to_rgba).collect()
+		}
+		(8, 3) | (8, 4) => vec![],
+		_ => return Err("Unsupported PCX bit depth"),
+	};
+
+	if x_max < x_min || y_max < y_min {
+		return Err("Invalid PCX dimensions");
+	}
+	let (width, height) = (x_max - x_min + 1, y_max - y_min + 1);
+	if line_bytes * 8 < width * bpp {
+		return Err("Invalid PCX line length");
+	}
+
+	// Run length decode every scanline, each holding all planes in turn
+	let scan_len = planes * line_bytes;
+	// Not preallocated, as the header can claim far more than the file holds
+	let needed = scan_len * height;
+	let mut decoded = vec![];
+	let mut bytes = pcx_data[128..].iter();
+	while decoded.len() < needed {
+		match bytes.next() {
+			Some(b) if *b >= 0xc0 => match bytes.next() {
+				Some(v) => decoded.resize(decoded.len() + (b & 0x3f) as usize, *v),
+				None => return Err("PCX pixel data is truncated"),
+			},
+			Some(b) => decoded.push(*b),
+			None => return Err("PCX pixel data is truncated"),
+		}
+	}
+
+	let mask = ((1u16 << bpp) - 1) as u8;
+	let mut pixels = Vec::with_capacity(width * height);
+	for line in decoded[..needed].chunks(scan_len) {
+		for x in 0..width {
+			let bit = x * bpp;
+			let sample = |p: usize| line[p * line_bytes + bit / 8] >> (8 - bpp - bit % 8) & mask;
+			let pixel = match palette.is_empty() {
+				true => [
+					sample(0),
+					sample(1),
+					sample(2),
+					match planes {
+						4 => sample(3),
+						_ => 255,
+					},
+				],
+				false => {
+					let index = (0..planes).fold(0, |acc, p| acc | sample(p) << (p * bpp));
+					palette[index as usize]
+				}
+			};
+			pixels.push(pixel);
+		}
+	}
+	Ok((width as u32, height as u32, pixels))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	/// Build a PCX header for a `width` x `height` image
+	fn build_pcx(width: u16, height: u16, bpp: u8, planes: u8, line_bytes: u16) -> Vec<u8> {
+		let mut pcx = vec![0x0a, 5, 1, bpp];
+		pcx.extend(&[0; 4]);
+		pcx.extend(&(width - 1).to_le_bytes());
+		pcx.extend(&(height - 1).to_le_bytes());
+		pcx.resize(65, 0);
+		pcx.push(planes);
+		pcx.extend(&line_bytes.to_le_bytes());
+		pcx.resize(128, 0);
+		pcx
+	}
+
+	#[test]
+	fn test_parse_pcx_from_bytes() {
+		// 8 bit indexed, 3x2 with a run crossing the end of the first line
+		let mut pcx = build_pcx(3, 2, 8, 1, 4);
+		pcx.extend(&[1, 0xc4, 2, 0xc3, 0]);
+		pcx.push(0x0c);
+		let mut palette = vec![0; 768];
+		palette[3..9].copy_from_slice(&[255, 255, 255, 136, 102, 17]);
+		pcx.extend(&palette);
+		let (w, h, pixels) = parse_pcx_from_bytes(&pcx).unwrap();
+		assert_eq!((w, h), (3, 2));
+		let expected = [
+			[255, 255, 255, 255],
+			[136, 102, 17, 255],
+			[136, 102, 17, 255],
+		];
+		assert_eq!(pixels[..3], expected);
+		assert_eq!(pixels[3], [136, 102, 17, 255]);
+		assert_eq!(pixels[4], [0, 0, 0, 255]);
+
+		// 24 bit as 3 planes, 2x1
+		let mut pcx = build_pcx(2, 1, 8, 3, 2);
+		pcx.extend(&[0xc1, 0xc1, 0, 0, 0, 0xc2, 0xff]);
+		let (_, _, pixels) = parse_pcx_from_bytes(&pcx).unwrap();
+		assert_eq!(pixels, vec![[0xc1, 0, 255, 255], [0, 0, 255, 255]]);
+
+		// 16 colour planar, 2x1 with header palette
+		let mut pcx = build_pcx(2, 1, 1, 4, 1);
+		pcx[19..22].copy_from_slice(&[255, 0, 0]);
+		pcx[16 + 9 * 3..16 + 10 * 3].copy_from_slice(&[0, 0, 255]);
+		pcx.extend(&[0xc1, 0xc0, 0x00, 0x00, 0x80]);
+		let (_, _, pixels) = parse_pcx_from_bytes(&pcx).unwrap();
+		assert_eq!(pixels, vec![[0, 0, 255, 255], [255, 0, 0, 255]]);
+
+		// truncated data and missing palette
+		let mut pcx = build_pcx(2, 1, 8, 3, 2);
+		pcx.extend(&[0xc5, 0]);
+		assert!(parse_pcx_from_bytes(&pcx).is_err());
+		let mut pcx = build_pcx(3, 2, 8, 1, 4);
+		pcx.extend(&[0xc8, 1]);
+		assert!(parse_pcx_from_bytes(&pcx).is_err());
+		let mut pcx = build_pcx(0xffff, 0xffff, 8, 4, 0xffff);
+		pcx.extend(&[0xff, 0]);
+		assert!(parse_pcx_from_bytes(&pcx).is_err());
+		assert!(parse_pcx_from_bytes(b"BM").is_err());
+	}
+}
@@ -0,0 +1,170 @@
+// Copyright 2020 Revcore Technologies Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Named animation sequences declared on an imageset
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::asm::to_const_name;
+use crate::{Assemblable, AssembledPrimitive};
+use crate::{Error, ErrorKind};
+use crate::{VeraFrameRef, VeraImageSet};
+
+/// What happens when an animation reaches its last frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VeraAnimationLoop {
+	/// Start again from the first frame
+	Loop = 0,
+	/// Stay on the last frame
+	Once = 1,
+	/// Play back to the first frame, then forward again
+	PingPong = 2,
+}
+
+impl fmt::Display for VeraAnimationLoop {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let out = match self {
+			VeraAnimationLoop::Loop => "loop",
+			VeraAnimationLoop::Once => "once",
+			VeraAnimationLoop::PingPong => "pingpong",
+		};
+		write!(f, "{}", out)
+	}
+}
+
+impl FromStr for VeraAnimationLoop {
+	type Err = Error;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.to_lowercase().as_str() {
+			"loop" => Ok(VeraAnimationLoop::Loop),
+			"once" => Ok(VeraAnimationLoop::Once),
+			"pingpong" => Ok(VeraAnimationLoop::PingPong),
+			other => Err(ErrorKind::UnknownAnimationLoop(other.to_owned()).into()),
+		}
+	}
+}
+
+/// A single step of an animation
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VeraAnimationFrame {
+	/// Frame of the imageset, and whether it's flipped
+	pub frame: VeraFrameRef,
+	/// Display frames to show it for
+	pub duration: u8,
+}
+
+/// A named sequence of imageset frames
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VeraAnimation {
+	/// Name, used for output constants
+	pub name: String,
+	/// Frames, in playing order
+	pub frames: Vec<VeraAnimationFrame>,
+	/// What to do after the last frame
+	pub loop_mode: VeraAnimationLoop,
+}
+
+/// All animations declared on an imageset, assembled as a table of
+/// 4 bytes per step (frame index low, high, flip and palette offset, duration),
+/// each animation's steps following the last, along with named constants for
+/// each animation
+#[derive(Clone, Debug)]
+pub struct VeraAnimationTable {
+	/// id
+	pub id: String,
+	/// Imageset the animations belong to
+	pub imageset_id: String,
+	/// Size of each imageset frame in bytes, if formatted
+	pub frame_size: Option<usize>,
+	/// Palette offset of each imageset frame
+	pub frame_pal_offsets: Vec<u8>,
+	/// Animations
+	pub animations: Vec<VeraAnimation>,
+}
+
+impl VeraAnimationTable {
+	/// Collect the animations declared on an imageset, if it has any
+	pub fn from_imageset(imageset: &VeraImageSet) -> Option<Self> {
+		if imageset.animations().is_empty() {
+			return None;
+		}
+		let frame_size = match imageset.formatted {
+			true => imageset.frame_data.first().map(|f| f.size()),
+			false => None,
+		};
+		Some(VeraAnimationTable {
+			id: format!("{}_anims", imageset.id),
+			imageset_id: imageset.id.clone(),
+			frame_size,
+			frame_pal_offsets: imageset.frame_data.iter().map(|f| f.pal_offset).collect(),
+			animations: imageset.animations().to_vec(),
+		})
+	}
+}
+
+impl Assemblable for VeraAnimationTable {
+	fn id(&self) -> &str {
+		&self.id
+	}
+
+	fn size_in_bytes(&self, _conflated: bool) -> Result<usize, Error> {
+		Ok(self.animations.iter().map(|a| a.frames.len() * 4).sum())
+	}
+
+	fn assemble(&self) -> Result<AssembledPrimitive, Error> {
+		let frame_size = match self.frame_size {
+			Some(s) => s,
+			None => return Err(ErrorKind::ImageSetNotFormatted(self.imageset_id.clone()).into()),
+		};
+		let mut retval = AssembledPrimitive::new(self.id());
+		retval.add_meta(format!(
+			"{} - {} animations for imageset {}",
+			self.id,
+			self.animations.len(),
+			self.imageset_id
+		));
+		retval.add_meta(
+			"Each step is frame index (low, high), flip (bit 0 horizontal, bit 1 vertical, as in sprite attributes) with palette offset / 16 in bits 4-7, display frames to show it for"
+				.to_owned(),
+		);
+		retval.add_meta(format!(
+			"A frame's VRAM address is that of {} + index * {}",
+			self.imageset_id, frame_size
+		));
+		let id = to_const_name(&self.id);
+		retval.add_const(&format!("{}_count", id), self.animations.len());
+		retval.add_const(&format!("{}_frame_size", id), frame_size);
+		let mut offset = 0;
+		for (i, a) in self.animations.iter().enumerate() {
+			let name = format!("{}_{}", id, a.name);
+			retval.add_const(&format!("{}_index", name), i);
+			retval.add_const(&format!("{}_offset", name), offset);
+			retval.add_const(&format!("{}_len", name), a.frames.len());
+			retval.add_const(&format!("{}_loop", name), a.loop_mode as usize);
+			for f in a.frames.iter() {
+				let index = f.frame.frame;
+				let pal_offset = match f.frame.pal_offset {
+					Some(o) => o,
+					None => self.frame_pal_offsets.get(index).cloned().unwrap_or(0),
+				};
+				let flip =
+					f.frame.h_flip as u8 | (f.frame.v_flip as u8) << 1 | (pal_offset / 16) << 4;
+				retval.add_data(&[index as u8, (index >> 8) as u8, flip, f.duration]);
+			}
+			offset += a.frames.len() * 4;
+		}
+		Ok(retval)
+	}
+}
@@ -71,6 +71,22 @@ impl FromStr for AsmFormat {
 	}
 }
 
+/// Whether a name can be used as is in output constants
+pub(crate) fn is_const_name(name: &str) -> bool {
+	!name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// A name made usable in output constants, replacing anything
+/// other than letters and numbers with underscores
+pub(crate) fn to_const_name(name: &str) -> String {
+	name.chars()
+		.map(|c| match c.is_ascii_alphanumeric() {
+			true => c,
+			false => '_',
+		})
+		.collect()
+}
+
 /// Holds and can format 'assembled' strings
 #[derive(Clone, Debug)]
 pub struct AssembledString {
@@ -151,6 +167,8 @@ pub struct AssembledPrimitive {
 	id: String,
 	/// Conflation data for tilemaps
 	conflate_info: Option<ConflateInfo>,
+	/// Named constants, output as symbols where the format allows
+	consts: Vec<(String, usize)>,
 }
 
 impl AssembledPrimitive {
@@ -161,6 +179,7 @@ impl AssembledPrimitive {
 			meta: vec![],
 			data: vec![],
 			conflate_info: None,
+			consts: vec![],
 		}
 	}
 
@@ -186,6 +205,7 @@ impl AssembledPrimitive {
 	pub fn add_prim(&mut self, mut other: AssembledPrimitive) {
 		self.meta.append(&mut other.meta);
 		self.data.append(&mut other.data);
+		self.consts.append(&mut other.consts);
 	}
 
 	/// Add meta information
@@ -193,6 +213,11 @@ impl AssembledPrimitive {
 		self.meta.push(new_meta);
 	}
 
+	/// Add a named constant
+	pub fn add_const(&mut self, name: &str, value: usize) {
+		self.consts.push((name.to_uppercase(), value));
+	}
+
 	/// Add assembled byte data
 	pub fn add_data(&mut self, new_data: &[u8]) {
 		for b in new_data {
@@ -280,6 +305,15 @@ impl AssembledPrimitive {
 		if out_format == AsmFormat::Cc65 {
 			retval.add(format!(" */"));
 		}
+		for (name, value) in self.consts.iter() {
+			retval.add(match out_format {
+				AsmFormat::Ca65 => format!("{} = {}", name, value),
+				// no named constants in BASIC, so just note the value
+				AsmFormat::Basic => format!("REM {} = {}", name, value),
+				AsmFormat::Cc65 => format!("#define {} {}", name, value),
+				AsmFormat::Bin => format!("{} = {}", name, value),
+			});
+		}
 		Ok(retval)
 	}
 
@@ -388,3 +422,25 @@ fn test_assemble() -> Result<(), Error> {
 
 	Ok(())
 }
+
+#[test]
+fn test_assemble_consts() -> Result<(), Error> {
+	let mut prim = AssembledPrimitive::new("my_prim");
+	prim.add_meta("here is some metadata".to_owned());
+	prim.add_const("my_prim_count", 3);
+
+	let meta_str = prim
+		.assemble_meta(AsmFormat::Ca65, false)?
+		.to_string(None)?;
+	assert!(meta_str.ends_with("MY_PRIM_COUNT = 3\n"));
+	let meta_str = prim
+		.assemble_meta(AsmFormat::Cc65, false)?
+		.to_string(None)?;
+	assert!(meta_str.ends_with(" */\n#define MY_PRIM_COUNT 3\n"));
+	let meta_str = prim
+		.assemble_meta(AsmFormat::Basic, false)?
+		.to_string(Some(10))?;
+	assert!(meta_str.ends_with("11 REM MY_PRIM_COUNT = 3\n"));
+
+	Ok(())
+}
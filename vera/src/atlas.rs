@@ -0,0 +1,195 @@
+// Copyright 2020 Revcore Technologies Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Texture atlas descriptions, as exported by TexturePacker or Aseprite
+//! in either their JSON hash or JSON array layouts
+
+use serde::de::{Deserialize, Deserializer, MapAccess, Visitor};
+use std::fmt;
+
+use crate::{Error, ErrorKind};
+use crate::{VeraAnimationDirection, VeraImageSetTag};
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+struct AtlasRect {
+	x: u32,
+	y: u32,
+	w: u32,
+	h: u32,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+struct AtlasSize {
+	w: u32,
+	h: u32,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct AtlasEntry {
+	filename: Option<String>,
+	frame: AtlasRect,
+	#[serde(default)]
+	rotated: bool,
+	#[serde(rename = "spriteSourceSize")]
+	sprite_source_size: Option<AtlasRect>,
+	#[serde(rename = "sourceSize")]
+	source_size: Option<AtlasSize>,
+	duration: Option<u16>,
+}
+
+/// Entries of the hash layout, kept in file order
+struct AtlasHash(Vec<(String, AtlasEntry)>);
+
+impl<'de> Deserialize<'de> for AtlasHash {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		struct HashVisitor;
+		impl<'de> Visitor<'de> for HashVisitor {
+			type Value = AtlasHash;
+			fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+				write!(f, "a map of frame names to frames")
+			}
+			fn visit_map<M: MapAccess<'de>>(self, mut map: M) -> Result<AtlasHash, M::Error> {
+				let mut entries = vec![];
+				while let Some(entry) = map.next_entry()? {
+					entries.push(entry);
+				}
+				Ok(AtlasHash(entries))
+			}
+		}
+		deserializer.deserialize_map(HashVisitor)
+	}
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AtlasFrames {
+	Array(Vec<AtlasEntry>),
+	Hash(AtlasHash),
+}
+
+#[derive(Deserialize)]
+struct AtlasFrameTag {
+	name: String,
+	from: usize,
+	to: usize,
+	#[serde(default)]
+	direction: String,
+}
+
+#[derive(Default, Deserialize)]
+struct AtlasMeta {
+	#[serde(default, rename = "frameTags")]
+	frame_tags: Vec<AtlasFrameTag>,
+}
+
+#[derive(Deserialize)]
+struct AtlasFile {
+	frames: AtlasFrames,
+	#[serde(default)]
+	meta: AtlasMeta,
+}
+
+/// A named rectangle of an atlas sheet
+#[derive(Clone, Debug)]
+pub(crate) struct AtlasFrame {
+	/// Frame name (the index for unnamed array entries)
+	pub name: String,
+	/// Left of the rectangle on the sheet
+	pub x: u32,
+	/// Top of the rectangle on the sheet
+	pub y: u32,
+	/// Rectangle width
+	pub width: u32,
+	/// Rectangle height
+	pub height: u32,
+	/// Left of the rectangle within its untrimmed source sprite
+	pub source_x: u32,
+	/// Top of the rectangle within its untrimmed source sprite
+	pub source_y: u32,
+	/// Untrimmed source sprite width
+	pub source_width: u32,
+	/// Untrimmed source sprite height
+	pub source_height: u32,
+	/// Duration in milliseconds, from Aseprite exports
+	pub duration: Option<u16>,
+}
+
+/// Frames and tags read from an atlas
+pub(crate) struct Atlas {
+	/// Frames, in file order
+	pub frames: Vec<AtlasFrame>,
+	/// Aseprite frame tags
+	pub tags: Vec<VeraImageSetTag>,
+}
+
+/// Parse a TexturePacker or Aseprite JSON atlas
+pub(crate) fn parse_atlas(atlas_json: &[u8]) -> Result<Atlas, Error> {
+	let file: AtlasFile = match serde_json::from_slice(atlas_json) {
+		Ok(f) => f,
+		Err(e) => return Err(ErrorKind::AtlasInvalid(format!("{}", e)).into()),
+	};
+	let entries = match file.frames {
+		AtlasFrames::Array(e) => e
+			.into_iter()
+			.enumerate()
+			.map(|(i, e)| (e.filename.clone().unwrap_or_else(|| format!("{}", i)), e))
+			.collect(),
+		AtlasFrames::Hash(h) => h.0,
+	};
+	let mut frames = vec![];
+	for (name, e) in entries {
+		if e.rotated {
+			let msg = format!("frame {} is rotated, which isn't supported", name);
+			return Err(ErrorKind::AtlasInvalid(msg).into());
+		}
+		let (source_x, source_y) = match e.sprite_source_size {
+			Some(s) => (s.x, s.y),
+			None => (0, 0),
+		};
+		let (source_width, source_height) = match e.source_size {
+			Some(s) => (s.w, s.h),
+			None => (e.frame.w, e.frame.h),
+		};
+		frames.push(AtlasFrame {
+			name,
+			x: e.frame.x,
+			y: e.frame.y,
+			width: e.frame.w,
+			height: e.frame.h,
+			source_x,
+			source_y,
+			source_width,
+			source_height,
+			duration: e.duration,
+		});
+	}
+	let tags = file
+		.meta
+		.frame_tags
+		.into_iter()
+		.map(|t| VeraImageSetTag {
+			direction: match t.direction.as_str() {
+				"reverse" => VeraAnimationDirection::Reverse,
+				"pingpong" => VeraAnimationDirection::PingPong,
+				"pingpong_reverse" => VeraAnimationDirection::PingPongReverse,
+				_ => VeraAnimationDirection::Forward,
+			},
+			name: t.name,
+			from: t.from,
+			to: t.to,
+		})
+		.collect();
+	debug!("Parsed atlas with {} frames", frames.len());
+	Ok(Atlas { frames, tags })
+}
@@ -0,0 +1,115 @@
+// Copyright 2020 Revcore Technologies Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Colour space conversions, used wherever colours need to be
+//! compared the way the eye sees them rather than by raw RGB value
+
+/// D65 reference white
+const REF_X: f64 = 0.950_47;
+const REF_Y: f64 = 1.0;
+const REF_Z: f64 = 1.088_83;
+
+const EPSILON: f64 = 216.0 / 24389.0;
+const KAPPA: f64 = 24389.0 / 27.0;
+
+fn srgb_to_linear(c: u8) -> f64 {
+	let c = c as f64 / 255.0;
+	if c <= 0.04045 {
+		c / 12.92
+	} else {
+		((c + 0.055) / 1.055).powf(2.4)
+	}
+}
+
+fn linear_to_srgb(c: f64) -> u8 {
+	let c = if c <= 0.003_130_8 {
+		c * 12.92
+	} else {
+		1.055 * c.powf(1.0 / 2.4) - 0.055
+	};
+	(c * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+/// Convert an 8 bit per channel sRGB value to CIE L*a*b*
+pub(crate) fn rgb_to_lab(r: u8, g: u8, b: u8) -> [f64; 3] {
+	let r = srgb_to_linear(r);
+	let g = srgb_to_linear(g);
+	let b = srgb_to_linear(b);
+	let x = (r * 0.412_456_4 + g * 0.357_576_1 + b * 0.180_437_5) / REF_X;
+	let y = (r * 0.212_672_9 + g * 0.715_152_2 + b * 0.072_175_0) / REF_Y;
+	let z = (r * 0.019_333_9 + g * 0.119_192_0 + b * 0.950_304_1) / REF_Z;
+	let f = |t: f64| {
+		if t > EPSILON {
+			t.cbrt()
+		} else {
+			(KAPPA * t + 16.0) / 116.0
+		}
+	};
+	let (fx, fy, fz) = (f(x), f(y), f(z));
+	[116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// Convert a CIE L*a*b* value back to 8 bit per channel sRGB,
+/// clamping anything outside of the sRGB gamut
+pub(crate) fn lab_to_rgb(lab: [f64; 3]) -> (u8, u8, u8) {
+	let fy = (lab[0] + 16.0) / 116.0;
+	let fx = fy + lab[1] / 500.0;
+	let fz = fy - lab[2] / 200.0;
+	let f_inv = |t: f64| {
+		let t3 = t * t * t;
+		if t3 > EPSILON {
+			t3
+		} else {
+			(116.0 * t - 16.0) / KAPPA
+		}
+	};
+	let x = f_inv(fx) * REF_X;
+	let y = f_inv(fy) * REF_Y;
+	let z = f_inv(fz) * REF_Z;
+	let r = x * 3.240_454_2 - y * 1.537_138_5 - z * 0.498_531_4;
+	let g = -x * 0.969_266_0 + y * 1.876_010_8 + z * 0.041_556_0;
+	let b = x * 0.055_643_4 - y * 0.204_025_9 + z * 1.057_225_2;
+	(linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b))
+}
+
+/// Perceptual distance between two L*a*b* values (CIE76 delta E)
+pub(crate) fn lab_distance(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+	let dl = a[0] - b[0];
+	let da = a[1] - b[1];
+	let db = a[2] - b[2];
+	(dl * dl + da * da + db * db).sqrt()
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn lab_round_trip() {
+		for &(r, g, b) in [
+			(0, 0, 0),
+			(255, 255, 255),
+			(0x80, 0x40, 0x20),
+			(0x12, 0xef, 0x99),
+		]
+		.iter()
+		{
+			let lab = rgb_to_lab(r, g, b);
+			assert_eq!(lab_to_rgb(lab), (r, g, b));
+		}
+		let white = rgb_to_lab(255, 255, 255);
+		assert!((white[0] - 100.0).abs() < 0.01);
+		assert!(lab_distance(&rgb_to_lab(0, 0, 0), &white) > 99.0);
+	}
+}
@@ -0,0 +1,163 @@
+// Copyright 2020 Revcore Technologies Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Colour cycling ranges declared on a palette
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::asm::to_const_name;
+use crate::VeraPalette;
+use crate::{Assemblable, AssembledPrimitive};
+use crate::{Error, ErrorKind};
+
+/// Which way colours move through a cycle range
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VeraPaletteCycleDirection {
+	/// Each step, colours move up one index, the last wrapping to the start
+	Forward = 0,
+	/// Each step, colours move down one index, the first wrapping to the end
+	Backward = 1,
+}
+
+impl fmt::Display for VeraPaletteCycleDirection {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let out = match self {
+			VeraPaletteCycleDirection::Forward => "forward",
+			VeraPaletteCycleDirection::Backward => "backward",
+		};
+		write!(f, "{}", out)
+	}
+}
+
+impl FromStr for VeraPaletteCycleDirection {
+	type Err = Error;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.to_lowercase().as_str() {
+			"forward" => Ok(VeraPaletteCycleDirection::Forward),
+			"backward" => Ok(VeraPaletteCycleDirection::Backward),
+			other => Err(ErrorKind::UnknownCycleDirection(other.to_owned()).into()),
+		}
+	}
+}
+
+/// A range of palette entries that rotate at runtime
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VeraPaletteCycle {
+	/// Name, used for output constants
+	pub name: String,
+	/// First palette index in the range
+	pub start: usize,
+	/// Number of entries in the range
+	pub len: usize,
+	/// Rotation direction
+	pub direction: VeraPaletteCycleDirection,
+	/// Display frames to wait between steps
+	pub frames_per_step: u8,
+}
+
+impl VeraPaletteCycle {
+	/// Palette index whose colour sits at `index` after `step` steps.
+	/// Indices outside the range are unchanged
+	pub fn source_index(&self, index: usize, step: usize) -> usize {
+		if index < self.start || index >= self.start + self.len {
+			return index;
+		}
+		let pos = index - self.start;
+		let step = step % self.len;
+		let src = match self.direction {
+			VeraPaletteCycleDirection::Forward => (pos + self.len - step) % self.len,
+			VeraPaletteCycleDirection::Backward => (pos + step) % self.len,
+		};
+		self.start + src
+	}
+}
+
+/// All cycles declared on a palette, assembled as a table
+/// of 4 bytes per cycle (start, length, direction, frames per step)
+/// along with named constants for each value
+#[derive(Clone, Debug)]
+pub struct VeraPaletteCycleTable {
+	/// id
+	pub id: String,
+	/// Palette the cycles belong to
+	pub palette_id: String,
+	/// Number of entries in the palette
+	pub palette_len: usize,
+	/// Cycles
+	pub cycles: Vec<VeraPaletteCycle>,
+}
+
+impl VeraPaletteCycleTable {
+	/// Collect the cycles declared on a palette, if it has any
+	pub fn from_palette(palette: &VeraPalette) -> Option<Self> {
+		if palette.cycles().is_empty() {
+			return None;
+		}
+		Some(VeraPaletteCycleTable {
+			id: format!("{}_cycles", palette.id),
+			palette_id: palette.id.clone(),
+			palette_len: palette.len(),
+			cycles: palette.cycles().to_vec(),
+		})
+	}
+}
+
+impl Assemblable for VeraPaletteCycleTable {
+	fn id(&self) -> &str {
+		&self.id
+	}
+
+	fn size_in_bytes(&self, _conflated: bool) -> Result<usize, Error> {
+		Ok(self.cycles.len() * 4)
+	}
+
+	fn assemble(&self) -> Result<AssembledPrimitive, Error> {
+		for c in self.cycles.iter() {
+			if c.len < 2 || c.len > 255 || c.start + c.len > self.palette_len {
+				return Err(
+					ErrorKind::PaletteCycleRangeInvalid(c.start, c.len, self.palette_len).into(),
+				);
+			}
+		}
+		let mut retval = AssembledPrimitive::new(self.id());
+		retval.add_meta(format!(
+			"{} - {} color cycles for palette {}",
+			self.id,
+			self.cycles.len(),
+			self.palette_id
+		));
+		retval.add_meta(
+			"Each entry is start, length, direction (0 forward, 1 backward), frames per step"
+				.to_owned(),
+		);
+		let id = to_const_name(&self.id);
+		retval.add_const(&format!("{}_count", id), self.cycles.len());
+		for (i, c) in self.cycles.iter().enumerate() {
+			let name = format!("{}_{}", id, c.name);
+			retval.add_const(&format!("{}_index", name), i);
+			retval.add_const(&format!("{}_start", name), c.start);
+			retval.add_const(&format!("{}_len", name), c.len);
+			retval.add_const(&format!("{}_dir", name), c.direction as usize);
+			retval.add_const(&format!("{}_frames", name), c.frames_per_step as usize);
+			retval.add_data(&[
+				c.start as u8,
+				c.len as u8,
+				c.direction as u8,
+				c.frames_per_step,
+			]);
+		}
+		Ok(retval)
+	}
+}
@@ -0,0 +1,163 @@
+// Copyright 2020 Revcore Technologies Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Strategies for reducing 8 bit per channel image data down
+//! to the 4 bits per channel VERA's palette can hold
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{Error, ErrorKind};
+
+/// Distance between two 4 bit levels, in 8 bit terms
+const LEVEL_STEP: f32 = 17.0;
+
+/// 4x4 Bayer threshold matrix
+const BAYER_4X4: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// How colours are reduced to 4 bits per channel
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VeraColorConversion {
+	/// Drop the lower 4 bits of each channel
+	#[default]
+	Truncate,
+	/// Round each channel to the nearest 4 bit level
+	Round,
+	/// Ordered dither with a 4x4 Bayer matrix
+	Bayer,
+	/// Floyd-Steinberg error diffusion
+	FloydSteinberg,
+}
+
+impl fmt::Display for VeraColorConversion {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let out = match self {
+			VeraColorConversion::Truncate => "truncate",
+			VeraColorConversion::Round => "round",
+			VeraColorConversion::Bayer => "bayer",
+			VeraColorConversion::FloydSteinberg => "floyd-steinberg",
+		};
+		write!(f, "{}", out)
+	}
+}
+
+impl FromStr for VeraColorConversion {
+	type Err = Error;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.to_lowercase().as_str() {
+			"truncate" => Ok(VeraColorConversion::Truncate),
+			"round" => Ok(VeraColorConversion::Round),
+			"bayer" => Ok(VeraColorConversion::Bayer),
+			"floyd-steinberg" => Ok(VeraColorConversion::FloydSteinberg),
+			other => Err(ErrorKind::UnknownColorConversion(other.to_owned()).into()),
+		}
+	}
+}
+
+/// Closest 4 bit level to an 8 bit value, expanded back out to 8 bits
+/// so the result truncates to the chosen level
+fn nearest_level(c: f32) -> u8 {
+	let level = (c / LEVEL_STEP).round().clamp(0.0, 15.0) as u8;
+	level << 4 | level
+}
+
+/// Reduce a `width` x `height` block of pixels in place. Truncation
+/// leaves the values untouched, as it happens when the pixels are
+/// matched against the palette anyway
+pub(crate) fn convert_rgb(
+	pixels: &mut [(u8, u8, u8)],
+	width: usize,
+	height: usize,
+	conversion: VeraColorConversion,
+) {
+	let mask = vec![false; pixels.len()];
+	convert_rgb_masked(pixels, width, height, conversion, &mask);
+}
+
+/// As above, leaving pixels set in `mask` untouched. Masked pixels neither
+/// spread error to their neighbours nor receive it
+pub(crate) fn convert_rgb_masked(
+	pixels: &mut [(u8, u8, u8)],
+	width: usize,
+	height: usize,
+	conversion: VeraColorConversion,
+	mask: &[bool],
+) {
+	match conversion {
+		VeraColorConversion::Truncate => {}
+		VeraColorConversion::Round => {
+			for (p, _) in pixels.iter_mut().zip(mask).filter(|(_, m)| !**m) {
+				*p = (
+					nearest_level(p.0 as f32),
+					nearest_level(p.1 as f32),
+					nearest_level(p.2 as f32),
+				);
+			}
+		}
+		VeraColorConversion::Bayer => {
+			for (i, p) in pixels.iter_mut().enumerate() {
+				if mask[i] {
+					continue;
+				}
+				let (x, y) = (i % width, i / width);
+				// threshold centred on 0, scaled to one level
+				let t = ((BAYER_4X4[y % 4][x % 4] as f32 + 0.5) / 16.0 - 0.5) * LEVEL_STEP;
+				*p = (
+					nearest_level(p.0 as f32 + t),
+					nearest_level(p.1 as f32 + t),
+					nearest_level(p.2 as f32 + t),
+				);
+			}
+		}
+		VeraColorConversion::FloydSteinberg => {
+			let mut buf: Vec<[f32; 3]> = pixels
+				.iter()
+				.map(|p| [p.0 as f32, p.1 as f32, p.2 as f32])
+				.collect();
+			for y in 0..height {
+				for x in 0..width {
+					let i = y * width + x;
+					if mask[i] {
+						continue;
+					}
+					let old = buf[i];
+					let new = [
+						nearest_level(old[0]),
+						nearest_level(old[1]),
+						nearest_level(old[2]),
+					];
+					pixels[i] = (new[0], new[1], new[2]);
+					let mut spread = |dx: isize, dy: usize, weight: f32| {
+						let nx = x as isize + dx;
+						if nx < 0 || nx >= width as isize || y + dy >= height {
+							return;
+						}
+						let n = (y + dy) * width + nx as usize;
+						if mask[n] {
+							return;
+						}
+						let n = &mut buf[n];
+						for c in 0..3 {
+							n[c] += (old[c] - new[c] as f32) * weight;
+						}
+					};
+					spread(1, 0, 7.0 / 16.0);
+					spread(-1, 1, 3.0 / 16.0);
+					spread(0, 1, 5.0 / 16.0);
+					spread(1, 1, 1.0 / 16.0);
+				}
+			}
+		}
+	}
+}
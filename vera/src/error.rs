@@ -20,7 +20,7 @@ use std::io;
 use std::num::ParseIntError;
 
 use failure::{Backtrace, Context, Fail};
-use png::DecodingError;
+use png::{DecodingError, EncodingError};
 
 /// Error definition
 #[derive(Debug, Fail)]
@@ -40,12 +40,18 @@ pub enum ErrorKind {
 	/// Unknown ASM format string
 	#[fail(display = "Unknown ASM Format: {}", _0)]
 	UnknownAsmFormat(String),
+	/// Unknown colour conversion string
+	#[fail(display = "Unknown Color Conversion: {}", _0)]
+	UnknownColorConversion(String),
 	/// Invalid ASM Format
 	#[fail(display = "Invalide ASM Format: {}", _0)]
 	InvalidAsmFormat(String),
 	/// PNG Decoding Error
 	#[fail(display = "PNG Decoding error: {}", _0)]
 	PNGDecoding(String),
+	/// PNG Encoding Error
+	#[fail(display = "PNG Encoding error: {}", _0)]
+	PNGEncoding(String),
 	/// PNG Decoding Error
 	#[fail(
 		display = "PNG Dimensions do not match tileset width / height. ({}, {}) vs ({},{})",
@@ -53,8 +59,35 @@ pub enum ErrorKind {
 	)]
 	PNGIncorrectDimensions(u32, u32, u32, u32),
 	/// PNG Invalid
-	#[fail(display = "Invalid PNG: ")]
+	#[fail(display = "Invalid PNG: {}", _0)]
 	PNGInvalid(String),
+	/// GIF, BMP or PCX Decoding Error
+	#[fail(display = "Image Decoding error: {}", _0)]
+	ImageDecoding(String),
+	/// Image isn't in a supported format
+	#[fail(display = "Unrecognised image format, expected PNG, GIF, BMP or PCX")]
+	ImageFormatUnknown,
+	/// Named layer isn't in the Aseprite file
+	#[fail(display = "Aseprite file has no layer named {}", _0)]
+	AsepriteLayerNotFound(String),
+	/// Frame limit cuts a tilemap row short
+	#[fail(
+		display = "Tilemap frame limit of {} doesn't fill whole rows of {} tiles",
+		_0, _1
+	)]
+	TileMapPartialRow(usize, u32),
+	/// Atlas JSON can't be read
+	#[fail(display = "Invalid atlas: {}", _0)]
+	AtlasInvalid(String),
+	/// Atlas frame doesn't fit in the imageset's frame size
+	#[fail(
+		display = "Atlas frame {} ({}x{}) doesn't fit in a frame of {}x{}",
+		_0, _1, _2, _3, _4
+	)]
+	AtlasFrameTooLarge(String, u32, u32, u32, u32),
+	/// Atlas frame lies outside the sheet
+	#[fail(display = "Atlas frame {} lies outside the sheet", _0)]
+	AtlasFrameOutOfBounds(String),
 	/// Palette full
 	#[fail(display = "Pallette Full")]
 	PaletteFull,
@@ -64,6 +97,78 @@ pub enum ErrorKind {
 		_0, _1, _2
 	)]
 	PaletteIndexMissing(u8, u8, u8),
+	/// Closest palette entry is too far away to remap to
+	#[fail(
+		display = "Closest palette entry to (#{:x}{:x}{:x}) is too far away to remap (delta E {:.2})",
+		_0, _1, _2, _3
+	)]
+	PaletteRemapDistance(u8, u8, u8, f64),
+	/// Palette range out of bounds
+	#[fail(
+		display = "Palette range {}-{} is outside of the 256 available entries",
+		_0, _1
+	)]
+	PaletteRangeInvalid(usize, usize),
+	/// Palette slot already holds an entry
+	#[fail(display = "Palette index {} is already occupied", _0)]
+	PaletteSlotOccupied(usize),
+	/// Palette is empty
+	#[fail(display = "Palette {} is empty", _0)]
+	PaletteEmpty(String),
+	/// Palettes to combine have different lengths
+	#[fail(display = "Palette lengths differ: {} vs {}", _0, _1)]
+	PaletteLengthMismatch(usize, usize),
+	/// Cycle range doesn't fit the palette
+	#[fail(
+		display = "Invalid cycle range starting at {} of length {} for palette with {} entries",
+		_0, _1, _2
+	)]
+	PaletteCycleRangeInvalid(usize, usize, usize),
+	/// Cycle name can't be used as a constant
+	#[fail(
+		display = "Invalid cycle name {}, use only letters, numbers and underscores",
+		_0
+	)]
+	PaletteCycleInvalidName(String),
+	/// No cycle with the given name
+	#[fail(display = "Palette has no cycle named {}", _0)]
+	PaletteCycleMissing(String),
+	/// Unknown cycle direction string
+	#[fail(display = "Unknown Cycle Direction: {}", _0)]
+	UnknownCycleDirection(String),
+	/// Frame or animation name can't be used as a constant
+	#[fail(
+		display = "Invalid name {}, use only letters, numbers and underscores",
+		_0
+	)]
+	ImageSetInvalidName(String),
+	/// Frame name already given to another frame
+	#[fail(display = "Frame name {} is already used by frame {}", _0, _1)]
+	FrameNameTaken(String, usize),
+	/// No frame with the given name
+	#[fail(display = "Image set has no frame named {}", _0)]
+	FrameNameMissing(String),
+	/// Animation with no frames
+	#[fail(display = "Animation {} has no frames", _0)]
+	AnimationEmpty(String),
+	/// Animation frame shown for no time
+	#[fail(display = "Animation {} has a frame with a duration of 0", _0)]
+	AnimationDurationZero(String),
+	/// No animation with the given name
+	#[fail(display = "Image set has no animation named {}", _0)]
+	AnimationMissing(String),
+	/// Unknown animation loop mode string
+	#[fail(display = "Unknown Animation Loop Mode: {}", _0)]
+	UnknownAnimationLoop(String),
+	/// Fade with no steps
+	#[fail(display = "Palette fade needs at least one step")]
+	PaletteFadeNoSteps,
+	/// Fade step doesn't exist
+	#[fail(display = "Palette fade has no step at index {}", _0)]
+	PaletteFadeStepOutOfRange(usize),
+	/// Malformed palette file
+	#[fail(display = "Invalid {} palette file: {}", _0, _1)]
+	PaletteFileInvalid(String, String),
 	/// Attempt to load a duplicate palette entry
 	#[fail(
 		display = "Attempt to load duplicate palette entry: (#{:x}{:x}{:x})",
@@ -97,6 +202,54 @@ pub enum ErrorKind {
 		_0, _1, _2
 	)]
 	DepthFormatError(String, u8, usize),
+	/// Source PNG index doesn't fit the target depth
+	#[fail(
+		display = "Frame {} uses palette index {}, which doesn't fit bit depth {}",
+		_0, _1, _2
+	)]
+	SourceIndexOutOfRange(String, u8, u8),
+	/// Palette index kept from a PNG, at its offset, is past the palette
+	#[fail(
+		display = "Frame {} uses palette index {}, past the end of palette {} ({} entries)",
+		_0, _1, _2, _3
+	)]
+	SourceIndexPastPalette(String, usize, String, usize),
+	/// Imageset wasn't loaded with its PNG indices
+	#[fail(
+		display = "Image set {} wasn't loaded with its PNG palette indices preserved",
+		_0
+	)]
+	ImageSetNoSourceIndices(String),
+	/// Can't append to an imageset holding its source PNG indices
+	#[fail(
+		display = "Image set {} holds its PNG palette indices, and can't be appended to",
+		_0
+	)]
+	ImageSetAppendSourceIndices(String),
+	/// Recoloured frames were culled against the imageset's last formatting
+	#[fail(
+		display = "Image set {} has had recoloured frames culled, and must be imported again before formatting or appending",
+		_0
+	)]
+	ImageSetRecoloured(String),
+	/// Palette offset can't be used at the given depth
+	#[fail(
+		display = "Palette offset {} is invalid for bit depth {} (must be a multiple of 16, or 0 at 8bpp)",
+		_0, _1
+	)]
+	PaletteOffsetInvalid(usize, u8),
+	/// Frame has more colours than fit in a sub-palette
+	#[fail(
+		display = "Frame {} has {} colors, more than the {} a sub-palette can hold",
+		_0, _1, _2
+	)]
+	SubPaletteTooManyColors(String, usize, usize),
+	/// Packing needs more sub-palettes than are available
+	#[fail(
+		display = "Imagesets need {} sub-palettes, more than the 16 available",
+		_0
+	)]
+	SubPaletteOverflow(usize),
 	/// Image must be formatted
 	#[fail(display = "Image set {} not formatted", _0)]
 	ImageSetNotFormatted(String),
@@ -136,6 +289,12 @@ pub enum ErrorKind {
 		_0, _1, _2, _3
 	)]
 	TileMapImageWrongSize(usize, usize, usize, usize),
+	/// Tilemap refers to palette offsets that have since moved
+	#[fail(
+		display = "Tile map {} no longer matches the palette offsets of its imageset, and must be initialized and loaded again",
+		_0
+	)]
+	TileMapStale(String),
 	/// Sprite doesn't have an Imageset
 	#[fail(display = "Sprite {} doesn't reference an imageset", _0)]
 	SpriteNoImageSet(String),
@@ -221,6 +380,22 @@ impl From<DecodingError> for Error {
 	}
 }
 
+impl From<gif::DecodingError> for Error {
+	fn from(error: gif::DecodingError) -> Error {
+		Error {
+			inner: Context::new(ErrorKind::ImageDecoding(format!("{}", error))),
+		}
+	}
+}
+
+impl From<EncodingError> for Error {
+	fn from(error: EncodingError) -> Error {
+		Error {
+			inner: Context::new(ErrorKind::PNGEncoding(format!("{}", error))),
+		}
+	}
+}
+
 impl From<ParseIntError> for Error {
 	fn from(error: ParseIntError) -> Error {
 		Error {
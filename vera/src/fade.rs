@@ -0,0 +1,148 @@
+// Copyright 2020 Revcore Technologies Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Precomputed palette fades, so fading or flashing the palette
+//! at runtime is just a copy into VERA palette RAM
+
+use crate::{Assemblable, AssembledPrimitive};
+use crate::{Error, ErrorKind};
+use crate::{VeraPalette, VeraPaletteEntry};
+
+/// What a palette fades towards
+#[derive(Clone, Copy, Debug)]
+pub enum VeraPaletteFadeTarget<'a> {
+	/// Every entry fades to a single colour (e.g. black or white)
+	Color(VeraPaletteEntry),
+	/// Each entry fades to the entry at the same index in another palette
+	Palette(&'a VeraPalette),
+}
+
+/// A series of palettes stepping from a source palette to a target
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct VeraPaletteFade {
+	/// id
+	pub id: String,
+	/// ID of the palette this fade starts from
+	pub palette_id: String,
+	/// Each intermediate palette, the last of which is the target
+	steps: Vec<Vec<VeraPaletteEntry>>,
+}
+
+/// Component `k` of `n` steps of the way from `a` to `b`, rounded
+fn lerp(a: u8, b: u8, k: usize, n: usize) -> u8 {
+	((a as usize * (n - k) + b as usize * k + n / 2) / n) as u8
+}
+
+impl VeraPaletteFade {
+	/// Create a fade of `steps` palettes from `palette` towards `target`.
+	/// The source palette itself isn't included
+	pub fn from_palette(
+		id: &str,
+		palette: &VeraPalette,
+		target: VeraPaletteFadeTarget,
+		steps: usize,
+	) -> Result<Self, Error> {
+		if palette.len() == 0 {
+			return Err(ErrorKind::PaletteEmpty(palette.id.clone()).into());
+		}
+		if steps == 0 {
+			return Err(ErrorKind::PaletteFadeNoSteps.into());
+		}
+		let mut targets = vec![];
+		for i in 0..palette.len() {
+			let t = match target {
+				VeraPaletteFadeTarget::Color(c) => c,
+				VeraPaletteFadeTarget::Palette(p) => {
+					if p.len() != palette.len() {
+						return Err(ErrorKind::PaletteLengthMismatch(palette.len(), p.len()).into());
+					}
+					p.value_at_index(i)?
+				}
+			};
+			targets.push((palette.value_at_index(i)?, t));
+		}
+		let steps = (1..=steps)
+			.map(|k| {
+				targets
+					.iter()
+					.map(|(s, t)| VeraPaletteEntry {
+						r: lerp(s.r, t.r, k, steps),
+						g: lerp(s.g, t.g, k, steps),
+						b: lerp(s.b, t.b, k, steps),
+					})
+					.collect()
+			})
+			.collect();
+		Ok(VeraPaletteFade {
+			id: id.to_owned(),
+			palette_id: palette.id.clone(),
+			steps,
+		})
+	}
+
+	/// Number of palettes in the fade
+	pub fn step_count(&self) -> usize {
+		self.steps.len()
+	}
+
+	/// Number of entries in each palette
+	pub fn palette_len(&self) -> usize {
+		self.steps.first().map_or(0, |s| s.len())
+	}
+
+	/// Entries of the palette at the given step
+	pub fn step_at(&self, index: usize) -> Result<&[VeraPaletteEntry], Error> {
+		match self.steps.get(index) {
+			Some(s) => Ok(s),
+			None => Err(ErrorKind::PaletteFadeStepOutOfRange(index).into()),
+		}
+	}
+
+	/// Size in bytes
+	pub fn size(&self) -> usize {
+		self.step_count() * self.palette_len() * 2
+	}
+}
+
+impl Assemblable for VeraPaletteFade {
+	fn id(&self) -> &str {
+		&self.id
+	}
+
+	fn size_in_bytes(&self, _conflated: bool) -> Result<usize, Error> {
+		Ok(self.size())
+	}
+
+	fn assemble(&self) -> Result<AssembledPrimitive, Error> {
+		let mut retval = AssembledPrimitive::new(self.id());
+		retval.add_meta(format!(
+			"{} - fade from palette {} - size is {}",
+			self.id,
+			self.palette_id,
+			self.size()
+		));
+		retval.add_meta(format!(
+			"{} palettes of {} entries ({} bytes each)",
+			self.step_count(),
+			self.palette_len(),
+			self.palette_len() * 2
+		));
+		for s in self.steps.iter() {
+			for e in s.iter() {
+				retval.add_data(&[e.g << 4 | e.b, e.r]);
+			}
+		}
+		Ok(retval)
+	}
+}
@@ -0,0 +1,138 @@
+// Copyright 2020 Revcore Technologies Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Decoding of the image formats other than PNG that can be sliced into
+//! frames, picked by the file's signature
+
+use crate::png_util::{decode_png, pixels_to_frames};
+use crate::{Error, ErrorKind};
+use crate::{VeraImage, VeraImageSetLoadConfig};
+
+use gif::SetParameter;
+use util::bmp::parse_bmp_from_bytes;
+use util::pcx::parse_pcx_from_bytes;
+
+/// Width, then the RGBA values of each image in a file
+pub(crate) type DecodedImages = (u32, Vec<Vec<[u8; 4]>>);
+
+/// Decode every frame of a gif, composited over the previous frames
+/// according to each frame's disposal method. Areas no frame has covered
+/// yet are left transparent
+fn decode_gif(gif_data: &[u8]) -> Result<DecodedImages, Error> {
+	let mut decoder = gif::Decoder::new(gif_data);
+	decoder.set(gif::ColorOutput::RGBA);
+	let mut reader = decoder.read_info()?;
+	let (width, height) = (reader.width() as usize, reader.height() as usize);
+
+	let mut canvas = vec![[0u8; 4]; width * height];
+	let mut images = vec![];
+	while let Some(frame) = reader.read_next_frame()? {
+		let (left, top) = (frame.left as usize, frame.top as usize);
+		let (f_width, f_height) = (frame.width as usize, frame.height as usize);
+		let saved = match frame.dispose {
+			gif::DisposalMethod::Previous => Some(canvas.clone()),
+			_ => None,
+		};
+		for (i, p) in frame.buffer.chunks(4).enumerate() {
+			let (x, y) = (left + i % f_width, top + i / f_width);
+			if p[3] != 0 && x < width && y < height {
+				canvas[y * width + x] = [p[0], p[1], p[2], p[3]];
+			}
+		}
+		images.push(canvas.clone());
+		match (frame.dispose, saved) {
+			(_, Some(s)) => canvas = s,
+			(gif::DisposalMethod::Background, _) => {
+				for y in top..height.min(top + f_height) {
+					for x in left..width.min(left + f_width) {
+						canvas[y * width + x] = [0; 4];
+					}
+				}
+			}
+			_ => {}
+		}
+	}
+	debug!(
+		"Decoded {} GIF frames of {}x{}",
+		images.len(),
+		width,
+		height
+	);
+	Ok((width as u32, images))
+}
+
+/// Decode a PNG, GIF, BMP or PCX into its width and the RGBA values of
+/// each of its images (one per animation frame for gifs)
+pub(crate) fn decode_image(image_data: &[u8]) -> Result<DecodedImages, Error> {
+	let to_err = |e: &str| Error::from(ErrorKind::ImageDecoding(e.to_owned()));
+	match image_data {
+		[0x89, b'P', b'N', b'G', ..] => {
+			let (width, _, pixels) = decode_png(image_data)?;
+			Ok((width, vec![pixels]))
+		}
+		[b'G', b'I', b'F', b'8', ..] => decode_gif(image_data),
+		[b'B', b'M', ..] => {
+			let (width, _, pixels) = parse_bmp_from_bytes(image_data).map_err(to_err)?;
+			Ok((width, vec![pixels]))
+		}
+		[0x0a, _, 1, ..] => {
+			let (width, _, pixels) = parse_pcx_from_bytes(image_data).map_err(to_err)?;
+			Ok((width, vec![pixels]))
+		}
+		_ => Err(ErrorKind::ImageFormatUnknown.into()),
+	}
+}
+
+/// Load frames tiled from a PNG, GIF, BMP or PCX, as with `png_to_frames`.
+/// Each frame of an animated gif is sliced in turn, so its frames follow
+/// on below the frames of the one before
+pub fn image_to_frames(
+	id: &str,
+	f_width: u32,
+	f_height: u32,
+	image_data: Vec<u8>,
+	config: &VeraImageSetLoadConfig,
+	result: &mut Vec<VeraImage>,
+) -> Result<(u32, u32), Error> {
+	let (width, images) = decode_image(&image_data)?;
+	sheets_to_frames(id, f_width, f_height, width, images, config, result)
+}
+
+/// Slice each of a series of same sized sheets in turn, with the frame
+/// limit applying to all of them together
+pub(crate) fn sheets_to_frames(
+	id: &str,
+	f_width: u32,
+	f_height: u32,
+	width: u32,
+	sheets: Vec<Vec<[u8; 4]>>,
+	config: &VeraImageSetLoadConfig,
+	result: &mut Vec<VeraImage>,
+) -> Result<(u32, u32), Error> {
+	let first = result.len();
+	let (mut frames_per_row, mut frames_per_col) = (0, 0);
+	for pixels in sheets {
+		let mut config = *config;
+		if let Some(limit) = config.limit {
+			match limit.saturating_sub(result.len() - first) {
+				0 => break,
+				l => config.limit = Some(l),
+			}
+		}
+		let (f_r, f_c) = pixels_to_frames(id, f_width, f_height, width, pixels, &config, result)?;
+		frames_per_row = f_r;
+		frames_per_col += f_c;
+	}
+	Ok((frames_per_row, frames_per_col))
+}
@@ -19,17 +19,21 @@
 //! * Sprites
 //! * Individual Images
 //! * Fonts (i.e. Text Tilesets)
-use permutate::Permutator;
-
 use std::collections::hash_map::DefaultHasher;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt;
 use std::hash::{Hash, Hasher};
 
-use crate::png_to_frames;
+use crate::animation::VeraAnimation;
+use crate::asm::{is_const_name, to_const_name};
+use crate::atlas::parse_atlas;
+use crate::image_util::{decode_image, sheets_to_frames};
+use crate::png_util::{convert_sheet, is_transparent, png_to_indices, slice_frames};
+use crate::{image_to_frames, png_to_frames};
 use crate::{Assemblable, AssembledPrimitive};
 use crate::{Error, ErrorKind};
-use crate::{VeraPalette, VeraPaletteEntry};
+use crate::{VeraColorConversion, VeraPalette, VeraPaletteEntry};
+use aloevera_util::aseprite::parse_aseprite_from_bytes;
 
 /// Constrain values to what's ddefined in VERA spec
 #[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
@@ -76,6 +80,9 @@ pub struct VeraPixel {
 	pub is_1bpp: bool,
 	/// if 1bpp, on or off
 	pub is_on: bool,
+	/// Transparent in the source image, so always index 0
+	/// whatever the frame's palette offset
+	pub is_transparent: bool,
 }
 
 impl Default for VeraPixel {
@@ -87,6 +94,7 @@ impl Default for VeraPixel {
 			pal_index: None,
 			is_1bpp: false,
 			is_on: false,
+			is_transparent: false,
 		}
 	}
 }
@@ -100,6 +108,7 @@ impl Hash for VeraPixel {
 				(self.r >> 4).hash(state);
 				(self.g >> 4).hash(state);
 				(self.b >> 4).hash(state);
+				self.is_transparent.hash(state);
 			}
 			true => {
 				self.is_on.hash(state);
@@ -134,6 +143,11 @@ pub struct VeraImage {
 	/// Also store hashes of each rotation
 	/// h_flipped, v_flipped, h_flipped and v_flipped
 	pub flip_hashes: [u64; 3],
+	/// Name of the frame, from the source file or given afterwards
+	pub name: Option<String>,
+	/// Position of the frame's top left corner within its untrimmed
+	/// source sprite, for frames taken from an atlas
+	pub source_offset: Option<(i32, i32)>,
 }
 
 impl Hash for VeraImage {
@@ -195,6 +209,8 @@ impl VeraImage {
 			foreground: 0,
 			background: 0,
 			flip_hashes: [0; 3],
+			name: None,
+			source_offset: None,
 		}
 	}
 
@@ -207,9 +223,23 @@ impl VeraImage {
 			pal_index,
 			is_1bpp: false,
 			is_on: false,
+			is_transparent: false,
+		});
+	}
+
+	/// push a transparent pixel, with its colour zeroed
+	pub fn push_transparent_pixel(&mut self) {
+		self.data.push(VeraPixel {
+			is_transparent: true,
+			..VeraPixel::default()
 		});
 	}
 
+	/// Whether any pixel in the image is transparent
+	pub fn has_transparency(&self) -> bool {
+		self.data.iter().any(|p| p.is_transparent)
+	}
+
 	/// Size in memory in bytes
 	pub fn size(&self) -> usize {
 		self.data.len() * self.depth as usize / 8
@@ -278,21 +308,187 @@ impl VeraImage {
 	}
 }
 
+/// A frame of an imageset, as drawn flipped or not
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VeraFrameRef {
+	/// Index of the frame
+	pub frame: usize,
+	/// Drawn horizontally flipped
+	pub h_flip: bool,
+	/// Drawn vertically flipped
+	pub v_flip: bool,
+	/// Palette offset to draw it with, if not the frame's own
+	pub pal_offset: Option<u8>,
+}
+
+impl VeraFrameRef {
+	/// Reference to an unflipped frame
+	pub fn new(frame: usize) -> Self {
+		VeraFrameRef {
+			frame,
+			h_flip: false,
+			v_flip: false,
+			pal_offset: None,
+		}
+	}
+
+	/// This reference, for a frame that is itself drawn as `to`
+	fn through(&self, to: &VeraFrameRef) -> Self {
+		VeraFrameRef {
+			frame: to.frame,
+			h_flip: self.h_flip ^ to.h_flip,
+			v_flip: self.v_flip ^ to.v_flip,
+			pal_offset: self.pal_offset.or(to.pal_offset),
+		}
+	}
+}
+
+impl fmt::Display for VeraFrameRef {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "frame {}", self.frame)?;
+		match (self.h_flip, self.v_flip) {
+			(true, true) => write!(f, ", h and v flipped")?,
+			(true, false) => write!(f, ", h flipped")?,
+			(false, true) => write!(f, ", v flipped")?,
+			(false, false) => {}
+		}
+		match self.pal_offset {
+			Some(o) => write!(f, ", palette offset {}", o),
+			None => Ok(()),
+		}
+	}
+}
+
+/// A frame culled as a recoloured copy of another, kept so maps
+/// drawn with it can still find it
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VeraRecolouredFrame {
+	/// Hashes of the culled frame as is, then h, v and h and v flipped
+	pub hashes: [u64; 4],
+	/// The frame it's drawn as instead
+	pub frame: VeraFrameRef,
+}
+
+/// Which source pixels are loaded as transparent, mapping to palette
+/// index 0 in every frame (which VERA doesn't draw for sprites and layer 1)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VeraTransparency {
+	/// Nothing is transparent
+	None,
+	/// Pixels with an alpha value of 0
+	Alpha,
+	/// Pixels of the given colour (compared at 4 bits per channel)
+	Key(u8, u8, u8),
+}
+
+/// How an animation plays through its frames
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VeraAnimationDirection {
+	/// First to last
+	Forward,
+	/// Last to first
+	Reverse,
+	/// First to last, then back again
+	PingPong,
+	/// Last to first, then back again
+	PingPongReverse,
+}
+
+impl fmt::Display for VeraAnimationDirection {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let out = match self {
+			VeraAnimationDirection::Forward => "forward",
+			VeraAnimationDirection::Reverse => "reverse",
+			VeraAnimationDirection::PingPong => "ping-pong",
+			VeraAnimationDirection::PingPongReverse => "ping-pong reverse",
+		};
+		write!(f, "{}", out)
+	}
+}
+
+/// A named range of animation frames, as tagged in the source file
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VeraImageSetTag {
+	/// Tag name
+	pub name: String,
+	/// First frame as loaded
+	pub from: usize,
+	/// Last frame as loaded (inclusive)
+	pub to: usize,
+	/// Play direction
+	pub direction: VeraAnimationDirection,
+}
+
 #[derive(Clone, Copy, Debug)]
 /// Image set load configuration
 pub struct VeraImageSetLoadConfig {
 	/// Whether to cull duplicate frames
 	pub cull_duplicates: bool,
+	/// When culling, whether to also cull frames that are a flipped
+	/// copy of an earlier frame
+	pub cull_flipped: bool,
+	/// How raw colours are reduced to 4 bits per channel
+	pub conversion: VeraColorConversion,
+	/// Which pixels are transparent
+	pub transparency: VeraTransparency,
+	/// Pixels around the edge of the sheet, before the first frame
+	pub margin: u32,
+	/// Pixels between neighbouring frames
+	pub spacing: u32,
+	/// Further pixels to skip from the left, after the margin
+	pub offset_x: u32,
+	/// Further pixels to skip from the top, after the margin
+	pub offset_y: u32,
+	/// Most frames to load, counting along each row in turn
+	pub limit: Option<usize>,
 }
 
 impl Default for VeraImageSetLoadConfig {
 	fn default() -> Self {
 		Self {
 			cull_duplicates: true,
+			cull_flipped: false,
+			conversion: VeraColorConversion::Truncate,
+			transparency: VeraTransparency::None,
+			margin: 0,
+			spacing: 0,
+			offset_x: 0,
+			offset_y: 0,
+			limit: None,
 		}
 	}
 }
 
+#[derive(Clone, Copy, Debug, Default)]
+/// Image set format configuration
+pub struct VeraImageSetFormatConfig {
+	/// If set, colours missing from the palette are remapped to the
+	/// perceptually closest entry, as long as it's no further away
+	/// than this (CIE76 delta E). Otherwise missing colours are an error
+	pub remap_max_distance: Option<f64>,
+}
+
+/// Per-frame summary of pixels remapped to a nearby palette entry
+#[derive(Clone, Debug, PartialEq)]
+pub struct VeraRemapReport {
+	/// Id of the frame
+	pub frame_id: String,
+	/// Number of pixels remapped
+	pub pixels: usize,
+	/// Largest distance (delta E) of any remapped colour
+	pub max_distance: f64,
+}
+
+impl fmt::Display for VeraRemapReport {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"Frame {}: remapped {} pixels to nearest palette entries - max distance (delta E): {:.2}",
+			self.frame_id, self.pixels, self.max_distance
+		)
+	}
+}
+
 /// An image set itself, basically an array of images (frames)
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct VeraImageSet {
@@ -314,6 +510,24 @@ pub struct VeraImageSet {
 	pub culled: bool,
 	/// whether this imageset has been formatted
 	pub formatted: bool,
+	/// Id of the palette this imageset was last formatted against,
+	/// if any (1bpp formatting doesn't use one)
+	pub palette_id: Option<String>,
+	/// Whether pixel palette indices are the source PNG's own,
+	/// to be kept when formatting
+	pub source_indices: bool,
+	/// Duration of each frame as loaded (see `source_frames`) from its
+	/// animation frame in the source file, in milliseconds, if it had any
+	pub frame_durations: Vec<u16>,
+	/// Tagged animation frame ranges from the source file, as ranges of
+	/// frames as loaded (see `source_frames`)
+	pub tags: Vec<VeraImageSetTag>,
+	/// Named animations declared on the imageset
+	animations: Vec<VeraAnimation>,
+	/// Where each frame as loaded ended up after culling, in load order
+	pub source_frames: Vec<VeraFrameRef>,
+	/// Frames culled as recoloured copies of others
+	pub recoloured_frames: Vec<VeraRecolouredFrame>,
 }
 
 impl VeraImageSet {
@@ -329,6 +543,13 @@ impl VeraImageSet {
 			frame_data: vec![],
 			culled: false,
 			formatted: false,
+			palette_id: None,
+			source_indices: false,
+			frame_durations: vec![],
+			tags: vec![],
+			animations: vec![],
+			source_frames: vec![],
+			recoloured_frames: vec![],
 		};
 		retval.reset();
 		retval
@@ -339,6 +560,20 @@ impl VeraImageSet {
 		self.frame_data = vec![];
 		self.culled = false;
 		self.formatted = false;
+		self.palette_id = None;
+		self.source_indices = false;
+		self.frame_durations = vec![];
+		self.tags = vec![];
+		self.animations = vec![];
+		self.source_frames = vec![];
+		self.recoloured_frames = vec![];
+	}
+
+	/// Track frames from `from` onwards as loaded
+	fn track_source_frames(&mut self, from: usize) {
+		for i in from..self.frame_data.len() {
+			self.source_frames.push(VeraFrameRef::new(i));
+		}
 	}
 
 	/// Get a frame at an index
@@ -366,32 +601,207 @@ impl VeraImageSet {
 		self.frame_data.iter().fold(0, |acc, t| acc + t.size())
 	}
 
-	/// Remove duplicates
+	/// Name a frame, or remove its name with `None`. Names must be unique
+	pub fn set_frame_name(&mut self, index: usize, name: Option<&str>) -> Result<(), Error> {
+		self.frame_at(index)?;
+		if let Some(n) = name {
+			if !is_const_name(n) {
+				return Err(ErrorKind::ImageSetInvalidName(n.to_owned()).into());
+			}
+			match self.frame_index(n) {
+				Some(i) if i != index => {
+					return Err(ErrorKind::FrameNameTaken(n.to_owned(), i).into())
+				}
+				_ => {}
+			}
+		}
+		self.frame_data[index].name = name.map(|n| n.to_owned());
+		Ok(())
+	}
+
+	/// Index of the frame with the given name
+	pub fn frame_index(&self, name: &str) -> Option<usize> {
+		self.frame_data
+			.iter()
+			.position(|f| f.name.as_deref() == Some(name))
+	}
+
+	/// All animations declared on this imageset
+	pub fn animations(&self) -> &[VeraAnimation] {
+		&self.animations
+	}
+
+	/// Declare an animation, replacing any existing animation of the same name
+	pub fn set_animation(&mut self, animation: VeraAnimation) -> Result<(), Error> {
+		if !is_const_name(&animation.name) {
+			return Err(ErrorKind::ImageSetInvalidName(animation.name).into());
+		}
+		if animation.frames.is_empty() {
+			return Err(ErrorKind::AnimationEmpty(animation.name).into());
+		}
+		for f in animation.frames.iter() {
+			self.frame_at(f.frame.frame)?;
+			if f.duration == 0 {
+				return Err(ErrorKind::AnimationDurationZero(animation.name).into());
+			}
+		}
+		match self
+			.animations
+			.iter_mut()
+			.find(|a| a.name == animation.name)
+		{
+			Some(a) => *a = animation,
+			None => self.animations.push(animation),
+		}
+		Ok(())
+	}
+
+	/// Remove the named animation
+	pub fn remove_animation(&mut self, name: &str) -> Result<(), Error> {
+		match self.animations.iter().position(|a| a.name == name) {
+			Some(i) => {
+				self.animations.remove(i);
+				Ok(())
+			}
+			None => Err(ErrorKind::AnimationMissing(name.to_owned()).into()),
+		}
+	}
+
+	/// Remove duplicates. Animations showing a removed frame show
+	/// the kept copy instead
 	pub fn remove_duplicate_frames(&mut self) -> Result<(), Error> {
+		self.remove_duplicate_frames_with_flips(false)
+	}
+
+	/// As above, optionally also removing frames that are a flipped copy
+	/// of an earlier frame, which are then shown as that frame flipped.
+	/// Where each loaded frame ended up is kept in `source_frames`
+	pub fn remove_duplicate_frames_with_flips(&mut self, flips: bool) -> Result<(), Error> {
+		// Indices only differ between frames of the same colours
+		// if kept from the source PNG
+		self.cull_frames(flips, |t| {
+			let indices: Vec<Option<u8>> = t.data.iter().map(|p| p.pal_index).collect();
+			(t.calc_hash(), indices)
+		});
+		Ok(())
+	}
+
+	/// Remove frames of a formatted imageset whose palette indices match an
+	/// earlier frame's, though drawn at another palette offset. These are
+	/// shown as the kept frame at their own offset, and listed in
+	/// `recoloured_frames` so tilemaps can still find them. These only hold
+	/// for the current formatting, so the imageset can't be formatted again
+	pub fn remove_recoloured_frames(&mut self, flips: bool) -> Result<(), Error> {
+		if !self.formatted {
+			return Err(ErrorKind::ImageSetNotFormatted(self.id.clone()).into());
+		}
+		self.cull_frames(flips, |t| {
+			t.data
+				.iter()
+				.map(|p| (p.pal_index, p.is_1bpp && p.is_on))
+				.collect::<Vec<_>>()
+		});
+		Ok(())
+	}
+
+	/// Cull frames with the same key as an earlier frame, or optionally
+	/// a flipped copy of it
+	fn cull_frames<K, F>(&mut self, flips: bool, key: F)
+	where
+		K: Ord + fmt::Debug,
+		F: Fn(&VeraImage) -> K,
+	{
+		if self.source_frames.is_empty() {
+			self.track_source_frames(0);
+		}
 		// could be more efficient than hashing every time, but these
 		// are going to be small data sets
 		let mut hashes_to_indices = BTreeMap::new();
+		// kept copy of each frame
+		let mut firsts = vec![];
+		// culled frames drawn at another palette offset
+		let mut recoloured = vec![];
 		// only keep first instance of each tile
 		for (i, t) in self.frame_data.iter().enumerate() {
-			let hash = t.calc_hash();
-			if !hashes_to_indices.contains_key(&hash) {
-				hashes_to_indices.insert(hash, i);
-			} else {
-				self.culled = true;
+			let mut keys = vec![(key(t), false, false)];
+			if flips {
+				let h_flipped = t.h_flip();
+				keys.push((key(&h_flipped), true, false));
+				keys.push((key(&t.v_flip()), false, true));
+				keys.push((key(&h_flipped.v_flip()), true, true));
+			}
+			let first = keys.iter().find_map(|(k, h_flip, v_flip)| {
+				hashes_to_indices.get(k).map(|f: &usize| VeraFrameRef {
+					frame: *f,
+					h_flip: *h_flip,
+					v_flip: *v_flip,
+					pal_offset: match self.frame_data[*f].pal_offset == t.pal_offset {
+						true => None,
+						false => Some(t.pal_offset),
+					},
+				})
+			});
+			match first {
+				Some(f) => {
+					if f.pal_offset.is_some() {
+						let h_flipped = t.h_flip();
+						let hashes = [
+							t.calc_hash(),
+							h_flipped.calc_hash(),
+							t.v_flip().calc_hash(),
+							h_flipped.v_flip().calc_hash(),
+						];
+						recoloured.push((hashes, i));
+					}
+					firsts.push(f);
+					self.culled = true;
+				}
+				None => {
+					firsts.push(VeraFrameRef::new(i));
+					hashes_to_indices.insert(keys.swap_remove(0).0, i);
+				}
 			}
 		}
 		debug!("Hashes to indices map: {:?}", hashes_to_indices);
-		self.frame_data = self
-			.frame_data
-			.clone()
-			.into_iter()
+		let kept: Vec<bool> = firsts
+			.iter()
 			.enumerate()
-			.filter(|(i, _)| hashes_to_indices.values().any(|val| val == i))
-			.map(|(_, t)| t)
+			.map(|(i, f)| f.frame == i)
+			.collect();
+		let mut new_index = vec![0; firsts.len()];
+		let mut count = 0;
+		for (i, k) in kept.iter().enumerate() {
+			if *k {
+				new_index[i] = count;
+				count += 1;
+			}
+		}
+		for f in firsts.iter_mut() {
+			f.frame = new_index[f.frame];
+		}
+		for r in self.source_frames.iter_mut() {
+			*r = r.through(&firsts[r.frame]);
+		}
+		for f in self.animations.iter_mut().flat_map(|a| a.frames.iter_mut()) {
+			f.frame = f.frame.through(&firsts[f.frame.frame]);
+		}
+		for r in self.recoloured_frames.iter_mut() {
+			r.frame = r.frame.through(&firsts[r.frame.frame]);
+		}
+		for (hashes, i) in recoloured {
+			self.recoloured_frames.push(VeraRecolouredFrame {
+				hashes,
+				frame: firsts[i],
+			});
+		}
+		let frames = std::mem::take(&mut self.frame_data);
+		self.frame_data = frames
+			.into_iter()
+			.zip(kept)
+			.filter(|(_, k)| *k)
+			.map(|(t, _)| t)
 			.collect();
 		debug!("Imageset culled? - {}", self.culled);
-
-		Ok(())
 	}
 
 	/// Load raw RGB values from a png
@@ -410,16 +820,361 @@ impl VeraImageSet {
 			self.frame_width,
 			self.frame_height,
 			png_data,
+			config,
+			&mut self.frame_data,
+		)?;
+		self.finish_load(f_r, f_c, config)
+	}
+
+	/// As `load_from_png`, but from a PNG, GIF, BMP or PCX. Every frame of
+	/// an animated GIF is loaded, one after the other
+	pub fn load_from_image(
+		&mut self,
+		image_data: Vec<u8>,
+		config: &VeraImageSetLoadConfig,
+	) -> Result<(), Error> {
+		self.reset();
+
+		let (f_r, f_c) = image_to_frames(
+			&self.id,
+			self.frame_width,
+			self.frame_height,
+			image_data,
+			config,
+			&mut self.frame_data,
+		)?;
+		self.finish_load(f_r, f_c, config)
+	}
+
+	/// Load every animation frame of an Aseprite file, flattening its visible
+	/// layers, or using only the named `layer`. Frames wider or taller than
+	/// the imageset's frame size are sliced as with `load_from_image`.
+	/// Frame durations and tags are kept alongside, for each frame as loaded
+	pub fn load_from_aseprite(
+		&mut self,
+		ase_data: Vec<u8>,
+		layer: Option<&str>,
+		config: &VeraImageSetLoadConfig,
+	) -> Result<(), Error> {
+		self.reset();
+		let to_err = |e: &str| Error::from(ErrorKind::ImageDecoding(e.to_owned()));
+		let ase = parse_aseprite_from_bytes(&ase_data).map_err(to_err)?;
+		let layer = match layer {
+			Some(name) => match ase.layer_index(name) {
+				Some(i) => Some(i),
+				None => return Err(ErrorKind::AsepriteLayerNotFound(name.to_owned()).into()),
+			},
+			None => None,
+		};
+
+		// first loaded frame of each animation frame, as each may be
+		// sliced into several
+		let mut starts = vec![];
+		let (mut f_r, mut f_c) = (0, 0);
+		for i in 0..ase.frames.len() {
+			let loaded = self.frame_data.len();
+			let limit = match config.limit {
+				Some(l) if l <= loaded => break,
+				Some(l) => Some(l - loaded),
+				None => None,
+			};
+			let sheet = ase.flatten_frame(i, layer).map_err(to_err)?;
+			let (r, c) = sheets_to_frames(
+				&self.id,
+				self.frame_width,
+				self.frame_height,
+				ase.width,
+				vec![sheet],
+				&VeraImageSetLoadConfig { limit, ..*config },
+				&mut self.frame_data,
+			)?;
+			f_r = r;
+			f_c += c;
+			starts.push(loaded);
+		}
+		let loaded = starts.len();
+		starts.push(self.frame_data.len());
+		for (i, f) in ase.frames.iter().take(loaded).enumerate() {
+			for _ in starts[i]..starts[i + 1] {
+				self.frame_durations.push(f.duration);
+			}
+		}
+		self.tags = ase
+			.tags
+			.iter()
+			.filter(|t| t.from < loaded && t.from <= t.to)
+			.map(|t| VeraImageSetTag {
+				name: t.name.clone(),
+				from: starts[t.from],
+				to: starts[t.to.min(loaded - 1) + 1] - 1,
+				direction: match t.direction {
+					1 => VeraAnimationDirection::Reverse,
+					2 => VeraAnimationDirection::PingPong,
+					3 => VeraAnimationDirection::PingPongReverse,
+					_ => VeraAnimationDirection::Forward,
+				},
+			})
+			.collect();
+		debug!(
+			"Loaded {} Aseprite frames with {} tags",
+			ase.frames.len(),
+			self.tags.len()
+		);
+		self.finish_load(f_r, f_c, config)
+	}
+
+	/// Load the named rectangles of an atlas sheet (PNG, GIF, BMP or PCX)
+	/// described by a TexturePacker or Aseprite JSON export, one frame each.
+	/// Each rectangle is placed where it sat in its untrimmed source sprite,
+	/// centred in the frame (or just centred itself if the source sprite is
+	/// too large), keeping the frame's name, made usable as a constant, and
+	/// offset within the source. Frames are never culled, as duplicates would
+	/// lose their names and offsets
+	pub fn load_from_atlas(
+		&mut self,
+		image_data: Vec<u8>,
+		atlas_json: &[u8],
+		config: &VeraImageSetLoadConfig,
+	) -> Result<(), Error> {
+		self.reset();
+		let atlas = parse_atlas(atlas_json)?;
+		let (width, sheets) = decode_image(&image_data)?;
+		let sheet = match sheets.into_iter().next() {
+			Some(s) => convert_sheet(s, width, config),
+			None => return Err(ErrorKind::ImageDecoding("Sheet has no image".to_owned()).into()),
+		};
+		let height = sheet.len() as u32 / width.max(1);
+		let (f_w, f_h) = (self.frame_width, self.frame_height);
+		// the frame around each rectangle is blank, and transparent if a
+		// blank pixel would be
+		let padding = match is_transparent(config.transparency, [0; 4]) {
+			true => None,
+			false => Some((0, 0, 0)),
+		};
+		let limit = config.limit.unwrap_or(atlas.frames.len());
+		let frames = &atlas.frames[..limit.min(atlas.frames.len())];
+		// names must still be unique once made usable as constants
+		let mut names = BTreeMap::new();
+		for (i, a) in frames.iter().enumerate() {
+			let name = to_const_name(&a.name);
+			if !is_const_name(&name) {
+				return Err(ErrorKind::ImageSetInvalidName(a.name.clone()).into());
+			}
+			if let Some(j) = names.insert(name, i) {
+				return Err(ErrorKind::FrameNameTaken(a.name.clone(), j).into());
+			}
+		}
+
+		for a in frames.iter() {
+			if a.width > f_w || a.height > f_h {
+				return Err(ErrorKind::AtlasFrameTooLarge(
+					a.name.clone(),
+					a.width,
+					a.height,
+					f_w,
+					f_h,
+				)
+				.into());
+			}
+			let inside = match (a.x.checked_add(a.width), a.y.checked_add(a.height)) {
+				(Some(r), Some(b)) => r <= width && b <= height,
+				_ => false,
+			};
+			if !inside {
+				return Err(ErrorKind::AtlasFrameOutOfBounds(a.name.clone()).into());
+			}
+			let (dest_x, dest_y) = match (
+				(f_w as i64 - a.source_width as i64) / 2 + a.source_x as i64,
+				(f_h as i64 - a.source_height as i64) / 2 + a.source_y as i64,
+			) {
+				(x, y)
+					if x >= 0
+						&& y >= 0 && x + a.width as i64 <= f_w as i64
+						&& y + a.height as i64 <= f_h as i64 =>
+				{
+					(x as u32, y as u32)
+				}
+				_ => ((f_w - a.width) / 2, (f_h - a.height) / 2),
+			};
+			let mut pixels = vec![padding; (f_w * f_h) as usize];
+			for y in 0..a.height {
+				let src = ((a.y + y) * width + a.x) as usize;
+				let dest = ((dest_y + y) * f_w + dest_x) as usize;
+				pixels[dest..dest + a.width as usize]
+					.copy_from_slice(&sheet[src..src + a.width as usize]);
+			}
+			slice_frames(
+				&self.id,
+				f_w,
+				f_h,
+				f_w,
+				pixels,
+				&VeraImageSetLoadConfig::default(),
+				&mut self.frame_data,
+			)?;
+			if let Some(frame) = self.frame_data.last_mut() {
+				frame.name = Some(to_const_name(&a.name));
+				frame.source_offset = Some((
+					a.source_x as i32 - dest_x as i32,
+					a.source_y as i32 - dest_y as i32,
+				));
+			}
+		}
+		self.frame_durations = frames
+			.iter()
+			.map(|a| a.duration)
+			.collect::<Option<Vec<u16>>>()
+			.unwrap_or_default();
+		self.tags = atlas
+			.tags
+			.into_iter()
+			.filter(|t| t.from < frames.len() && t.from <= t.to)
+			.map(|t| VeraImageSetTag {
+				to: t.to.min(frames.len() - 1),
+				..t
+			})
+			.collect();
+		let frames_per_col = match frames.is_empty() {
+			true => 0,
+			false => 1,
+		};
+		let config = VeraImageSetLoadConfig {
+			cull_duplicates: false,
+			..*config
+		};
+		self.finish_load(frames.len() as u32, frames_per_col, &config)
+	}
+
+	/// Add the frames of another PNG, GIF, BMP or PCX after the existing ones,
+	/// then cull duplicates across the whole set. Frames from a sheet with the
+	/// same number of frames per row are added as further rows, otherwise
+	/// every frame is laid out along a single row. Any formatting is dropped,
+	/// so the set must be formatted again
+	pub fn append_from_image(
+		&mut self,
+		image_data: Vec<u8>,
+		config: &VeraImageSetLoadConfig,
+	) -> Result<(), Error> {
+		if self.source_indices {
+			return Err(ErrorKind::ImageSetAppendSourceIndices(self.id.clone()).into());
+		}
+		self.check_not_recoloured()?;
+		let first = self.frame_data.len();
+		let tracked = match self.source_frames.is_empty() {
+			true => 0,
+			false => first,
+		};
+		let full_grid =
+			!self.culled && first == (self.frames_per_row * self.frames_per_col) as usize;
+		let (f_r, f_c) = image_to_frames(
+			&self.id,
+			self.frame_width,
+			self.frame_height,
+			image_data,
+			config,
 			&mut self.frame_data,
 		)?;
+		self.track_source_frames(tracked);
+		match full_grid && (first == 0 || f_r == self.frames_per_row) {
+			true => {
+				self.frames_per_row = f_r;
+				self.frames_per_col += f_c;
+			}
+			false => {
+				self.frames_per_row = self.frame_data.len() as u32;
+				self.frames_per_col = 1;
+			}
+		}
+		debug!(
+			"Appended {} frames to imageset {}",
+			self.frame_data.len() - first,
+			self.id
+		);
 
+		if self.formatted {
+			for p in self.frame_data[..first]
+				.iter_mut()
+				.flat_map(|f| f.data.iter_mut())
+			{
+				p.pal_index = None;
+				p.is_1bpp = false;
+			}
+			self.formatted = false;
+			self.palette_id = None;
+		}
+
+		if config.cull_duplicates {
+			self.remove_duplicate_frames_with_flips(config.cull_flipped)?;
+		}
+		info!("Image appended successfully");
+		Ok(())
+	}
+
+	/// Cull freshly loaded frames
+	fn finish_load(
+		&mut self,
+		frames_per_row: u32,
+		frames_per_col: u32,
+		config: &VeraImageSetLoadConfig,
+	) -> Result<(), Error> {
+		self.frames_per_row = frames_per_row;
+		self.frames_per_col = frames_per_col;
+
+		self.track_source_frames(0);
+		if config.cull_duplicates {
+			self.remove_duplicate_frames_with_flips(config.cull_flipped)?;
+		}
+		info!("Image parsed successfully");
+		Ok(())
+	}
+
+	/// Load a PNG already indexed against the target palette, keeping its
+	/// own palette indices instead of matching by colour when formatting
+	/// (see `format_source_indices`). Colours are still loaded from the PLTE
+	pub fn load_from_indexed_png(
+		&mut self,
+		png_data: Vec<u8>,
+		config: &VeraImageSetLoadConfig,
+	) -> Result<(), Error> {
+		self.reset();
+		let (width, indices) = png_to_indices(&png_data)?;
+		let (f_r, f_c) = png_to_frames(
+			&self.id,
+			self.frame_width,
+			self.frame_height,
+			png_data,
+			config,
+			&mut self.frame_data,
+		)?;
 		self.frames_per_row = f_r;
 		self.frames_per_col = f_c;
 
+		// slice the indices the same way, carried in the red channel
+		let mut index_frames = vec![];
+		slice_frames(
+			&self.id,
+			self.frame_width,
+			self.frame_height,
+			width,
+			indices.into_iter().map(|i| Some((i, 0, 0))).collect(),
+			config,
+			&mut index_frames,
+		)?;
+		for (frame, index_frame) in self.frame_data.iter_mut().zip(index_frames) {
+			for (p, i) in frame.data.iter_mut().zip(index_frame.data) {
+				p.pal_index = match p.is_transparent {
+					true => Some(0),
+					false => Some(i.r),
+				};
+			}
+		}
+		self.source_indices = true;
+
+		self.track_source_frames(0);
 		if config.cull_duplicates {
-			self.remove_duplicate_frames()?;
+			self.remove_duplicate_frames_with_flips(config.cull_flipped)?;
 		}
-		info!("Image parsed successfully");
+		info!("Image parsed successfully, palette indices preserved");
 		Ok(())
 	}
 
@@ -431,6 +1186,14 @@ impl VeraImageSet {
 		}
 	}
 
+	/// Recoloured frames are culled against one formatting only
+	fn check_not_recoloured(&self) -> Result<(), Error> {
+		match self.recoloured_frames.is_empty() {
+			true => Ok(()),
+			false => Err(ErrorKind::ImageSetRecoloured(self.id.clone()).into()),
+		}
+	}
+
 	/// Format the stored indices with a given palette and colour depth
 	/// Should fail if any frame in the set contains a range of colours
 	/// that can't be found within a single 2^BPP length range in the
@@ -440,17 +1203,32 @@ impl VeraImageSet {
 		palette: &VeraPalette,
 		depth: VeraPixelDepth,
 	) -> Result<(), Error> {
+		self.format_indices_with_config(palette, depth, &VeraImageSetFormatConfig::default())?;
+		Ok(())
+	}
+
+	/// As above, but optionally remapping colours missing from the palette
+	/// to the closest entry within the frame's palette range. Returns a report
+	/// for each frame that had pixels remapped
+	pub fn format_indices_with_config(
+		&mut self,
+		palette: &VeraPalette,
+		depth: VeraPixelDepth,
+		config: &VeraImageSetFormatConfig,
+	) -> Result<Vec<VeraRemapReport>, Error> {
 		if self.frame_data.is_empty() {
 			return Err(ErrorKind::ImageSetEmpty(self.id.clone()).into());
 		}
+		self.check_not_recoloured()?;
 		let palette_range = 2u32.pow(depth as u32) - 1;
 		info!(
 			"Formatting imageset {} to palette at depth of {}",
 			self.id, depth
 		);
+		let mut reports = vec![];
 		for frame in self.frame_data.iter_mut() {
 			if depth == VeraPixelDepth::BPP1 {
-				for mut p in frame.data.iter_mut() {
+				for p in frame.data.iter_mut() {
 					p.is_1bpp = true;
 					if p.r == 0 && p.g == 0 && p.b == 0 {
 						p.is_on = false;
@@ -462,15 +1240,33 @@ impl VeraImageSet {
 				continue;
 			} else {
 				// remove the 1 BPP flag from all pixels
-				for mut p in frame.data.iter_mut() {
+				for p in frame.data.iter_mut() {
 					p.is_1bpp = false;
 				}
 			}
+			let align_to_16 = match depth {
+				VeraPixelDepth::BPP8 => false,
+				_ => true,
+			};
 			let mut mappings = BTreeMap::new();
+			let mut unmatched = BTreeSet::new();
+			let transparent = frame.has_transparency();
 			for p in frame.data.iter_mut() {
-				let (val, indices) = palette.all_indices_of_rgb(p.r, p.g, p.b);
-				if indices.len() == 0 {
-					return Err(ErrorKind::PaletteIndexMissing(p.r >> 4, p.g >> 4, p.b >> 4).into());
+				if p.is_transparent {
+					continue;
+				}
+				let (val, mut indices) = palette.all_indices_of_rgb(p.r, p.g, p.b);
+				if transparent {
+					indices.retain(|i| !is_transparent_index(*i, align_to_16));
+				}
+				if indices.is_empty() {
+					if config.remap_max_distance.is_none() {
+						return Err(
+							ErrorKind::PaletteIndexMissing(p.r >> 4, p.g >> 4, p.b >> 4).into()
+						);
+					}
+					unmatched.insert(VeraPaletteEntry::new(p.r, p.g, p.b));
+					continue;
 				}
 				mappings.insert(val, indices);
 			}
@@ -478,11 +1274,15 @@ impl VeraImageSet {
 				mappings.into_iter().map(|(k, v)| (k, v)).collect();
 
 			trace!("Possible indices map: {:?}", mappings);
-			let align_to_16 = match depth {
-				VeraPixelDepth::BPP8 => false,
-				_ => true,
+			let res = match mappings.is_empty() {
+				// nothing matched exactly, so pick whichever range the remapped
+				// colours sit closest to
+				true => (
+					true,
+					closest_remap_range(palette, &unmatched, palette_range, align_to_16),
+				),
+				false => find_optimal_range(palette_range, &mut mappings, align_to_16)?,
 			};
-			let res = find_optimal_range(palette_range, &mut mappings, align_to_16)?;
 			trace!("Found?: {}", res.0);
 			if !res.0 {
 				return Err(ErrorKind::DepthFormatError(
@@ -501,6 +1301,47 @@ impl VeraImageSet {
 				final_map.insert(e.0, e.1[0]);
 			}
 
+			// Remap anything missing to the closest entry within range
+			if let Some(max_distance) = config.remap_max_distance {
+				let (mut start, mut len) = remap_window(palette, res.1, palette_range, align_to_16);
+				if transparent {
+					start += 1;
+					len = len.saturating_sub(1);
+				}
+				let mut report = VeraRemapReport {
+					frame_id: frame.id.clone(),
+					pixels: 0,
+					max_distance: 0.0,
+				};
+				for e in unmatched.iter() {
+					let (r, g, b) = e.to_rgb8();
+					let (index, distance) = match palette
+						.nearest_index_in_range(r, g, b, start, len)
+					{
+						Some(n) => n,
+						None => return Err(ErrorKind::PaletteIndexMissing(e.r, e.g, e.b).into()),
+					};
+					if distance > max_distance {
+						return Err(ErrorKind::PaletteRemapDistance(e.r, e.g, e.b, distance).into());
+					}
+					if distance > report.max_distance {
+						report.max_distance = distance;
+					}
+					final_map.insert(*e, index);
+				}
+				report.pixels = frame
+					.data
+					.iter()
+					.filter(|p| {
+						!p.is_transparent
+							&& unmatched.contains(&VeraPaletteEntry::new(p.r, p.g, p.b))
+					})
+					.count();
+				if report.pixels > 0 {
+					reports.push(report);
+				}
+			}
+
 			// Now set the palette indices and offsets
 			frame.pal_offset = res.1 as u8;
 			if depth == VeraPixelDepth::BPP8 {
@@ -508,6 +1349,10 @@ impl VeraImageSet {
 			}
 			frame.depth = depth;
 			for p in frame.data.iter_mut() {
+				if p.is_transparent {
+					p.pal_index = Some(0);
+					continue;
+				}
 				let entry = VeraPaletteEntry::new(p.r, p.g, p.b);
 				let index = final_map.get(&entry);
 				match index {
@@ -522,10 +1367,203 @@ impl VeraImageSet {
 			}
 		}
 		self.depth = Some(depth);
+		self.palette_id = match depth {
+			VeraPixelDepth::BPP1 => None,
+			_ => Some(palette.id.clone()),
+		};
 		self.store_flip_hashes();
 		self.formatted = true;
+		self.source_indices = false;
+		Ok(reports)
+	}
+
+	/// Format using the palette indices kept from the source PNG as they
+	/// are, with every frame at the given palette offset. Only checks that
+	/// the indices fit the depth and, at that offset, the palette
+	pub fn format_source_indices(
+		&mut self,
+		palette: &VeraPalette,
+		depth: VeraPixelDepth,
+		offset: usize,
+	) -> Result<(), Error> {
+		if !self.source_indices {
+			return Err(ErrorKind::ImageSetNoSourceIndices(self.id.clone()).into());
+		}
+		if self.frame_data.is_empty() {
+			return Err(ErrorKind::ImageSetEmpty(self.id.clone()).into());
+		}
+		self.check_not_recoloured()?;
+		let valid_offset = match depth {
+			VeraPixelDepth::BPP1 => {
+				let msg = "Palette indices can't be preserved at 1bpp".to_owned();
+				return Err(ErrorKind::UnexpectedDepthError(msg).into());
+			}
+			VeraPixelDepth::BPP8 => offset == 0,
+			_ => offset % 16 == 0 && offset < 256,
+		};
+		if !valid_offset {
+			return Err(ErrorKind::PaletteOffsetInvalid(offset, depth as u8).into());
+		}
+		let palette_range = 2usize.pow(depth as u32) - 1;
+		for frame in self.frame_data.iter() {
+			let out_of_range = frame
+				.data
+				.iter()
+				.filter_map(|p| p.pal_index)
+				.find(|i| *i as usize > palette_range);
+			if let Some(i) = out_of_range {
+				return Err(
+					ErrorKind::SourceIndexOutOfRange(frame.id.clone(), i, depth as u8).into(),
+				);
+			}
+			let past_palette = frame
+				.data
+				.iter()
+				.filter_map(|p| p.pal_index)
+				.map(|i| offset + i as usize)
+				.find(|i| *i >= palette.len());
+			if let Some(i) = past_palette {
+				return Err(ErrorKind::SourceIndexPastPalette(
+					frame.id.clone(),
+					i,
+					palette.id.clone(),
+					palette.len(),
+				)
+				.into());
+			}
+		}
+		info!(
+			"Formatting imageset {} at depth of {} with its own indices, at offset {}",
+			self.id, depth, offset
+		);
+		for frame in self.frame_data.iter_mut() {
+			for p in frame.data.iter_mut() {
+				p.is_1bpp = false;
+			}
+			frame.pal_offset = offset as u8;
+			frame.depth = depth;
+		}
+		self.depth = Some(depth);
+		self.palette_id = Some(palette.id.clone());
+		self.store_flip_hashes();
+		self.formatted = true;
+		Ok(())
+	}
+
+	/// Format the stored indices with a given palette and colour depth, using
+	/// an already chosen palette offset for each frame rather than searching
+	/// for one. Fails if a frame's colours aren't all found within its range
+	pub fn format_indices_at_offsets(
+		&mut self,
+		palette: &VeraPalette,
+		depth: VeraPixelDepth,
+		offsets: &[usize],
+	) -> Result<(), Error> {
+		if self.frame_data.is_empty() {
+			return Err(ErrorKind::ImageSetEmpty(self.id.clone()).into());
+		}
+		if offsets.len() < self.frame_data.len() {
+			return Err(ErrorKind::FrameDataMissing(offsets.len()).into());
+		}
+		self.check_not_recoloured()?;
+		let palette_range = 2usize.pow(depth as u32) - 1;
+		for (frame, offset) in self.frame_data.iter_mut().zip(offsets.iter()) {
+			let transparent = frame.has_transparency();
+			for p in frame.data.iter_mut() {
+				p.is_1bpp = false;
+				if p.is_transparent {
+					p.pal_index = Some(0);
+					continue;
+				}
+				let (_, indices) = palette.all_indices_of_rgb(p.r, p.g, p.b);
+				let index = indices
+					.into_iter()
+					.filter(|i| !(transparent && *i == *offset))
+					.find(|i| *i >= *offset && *i <= offset + palette_range);
+				match index {
+					Some(i) => p.pal_index = Some((i - offset) as u8),
+					None => {
+						return Err(
+							ErrorKind::PaletteIndexMissing(p.r >> 4, p.g >> 4, p.b >> 4).into()
+						);
+					}
+				}
+			}
+			frame.pal_offset = *offset as u8;
+			frame.depth = depth;
+		}
+		self.depth = Some(depth);
+		self.palette_id = Some(palette.id.clone());
+		self.store_flip_hashes();
+		self.formatted = true;
+		self.source_indices = false;
 		Ok(())
 	}
+
+	/// Follow entries of the palette this imageset was formatted against
+	/// to their new indices, given the old to new index map returned by a
+	/// palette edit. If any colour was removed, or a frame's colours no longer
+	/// fit in a single range, the imageset is left untouched but marked as
+	/// unformatted, and false is returned. Imagesets with recoloured frames
+	/// culled are always marked unformatted, as those frames may no longer
+	/// match the kept ones
+	pub fn reindex(&mut self, index_map: &[Option<usize>]) -> bool {
+		let depth = match self.depth {
+			Some(d) if self.formatted => d,
+			_ => return true,
+		};
+		if depth == VeraPixelDepth::BPP1 {
+			return true;
+		}
+		if !self.recoloured_frames.is_empty() {
+			self.formatted = false;
+			return false;
+		}
+		let palette_range = 2usize.pow(depth as u32) - 1;
+		let mut remapped = vec![];
+		for frame in self.frame_data.iter() {
+			let mut indices = vec![];
+			for p in frame.data.iter().filter(|p| !p.is_transparent) {
+				let old = match p.pal_index {
+					Some(i) => i as usize + frame.pal_offset as usize,
+					None => continue,
+				};
+				match index_map.get(old) {
+					Some(Some(new)) => indices.push(*new),
+					_ => {
+						self.formatted = false;
+						return false;
+					}
+				}
+			}
+			let offset = match depth {
+				VeraPixelDepth::BPP8 => 0,
+				_ => indices.iter().min().map_or(0, |i| i / 16 * 16),
+			};
+			// colours can't land on the transparent index either
+			let transparent = frame.has_transparency();
+			if indices
+				.iter()
+				.any(|i| *i > offset + palette_range || (transparent && *i == offset))
+			{
+				self.formatted = false;
+				return false;
+			}
+			remapped.push((offset, indices));
+		}
+		for (frame, (offset, indices)) in self.frame_data.iter_mut().zip(remapped) {
+			frame.pal_offset = offset as u8;
+			let pixels = frame
+				.data
+				.iter_mut()
+				.filter(|p| p.pal_index.is_some() && !p.is_transparent);
+			for (p, i) in pixels.zip(indices) {
+				p.pal_index = Some((i - offset) as u8);
+			}
+		}
+		self.store_flip_hashes();
+		true
+	}
 }
 
 impl fmt::Display for VeraImageSet {
@@ -564,6 +1602,11 @@ impl Assemblable for VeraImageSet {
 		}
 		let mut retval = AssembledPrimitive::new(self.id());
 		retval.add_meta(format!("{} - size is {}", self.id, self.size()));
+		for (i, f) in self.frame_data.iter().enumerate() {
+			if let Some(n) = &f.name {
+				retval.add_const(&to_const_name(&format!("{}_{}", self.id, n)), i);
+			}
+		}
 		let depth = match self.depth {
 			Some(d) => d,
 			None => {
@@ -637,88 +1680,175 @@ impl Assemblable for VeraImageSet {
 }
 
 /// Attempts to find a solution for a range of possible colours in the palette
-/// that fit within the depth mode
+/// that fit within the depth mode. Each colour's candidate indices must be in
+/// ascending order. Slides a window of `range` entries over each candidate
+/// offset from lowest to highest, and takes the first window that holds a
+/// candidate for every colour, narrowing each colour down to its lowest
+/// candidate within that window
 fn find_optimal_range(
 	range: u32,
-	range_vals: &mut Vec<(VeraPaletteEntry, Vec<usize>)>,
+	range_vals: &mut [(VeraPaletteEntry, Vec<usize>)],
 	align_to_16: bool,
 ) -> Result<(bool, usize), Error> {
-	// convert to permutator lib format
-	let mut lists = vec![];
-	for val in range_vals.iter() {
-		let mut in_vec = vec![];
-		for i in val.1.iter() {
-			in_vec.push(format!("{}", i));
+	let range = range as usize;
+	let mut offsets: Vec<usize> = range_vals
+		.iter()
+		.flat_map(|(_, candidates)| candidates.iter())
+		.map(|i| match align_to_16 {
+			true => i - i % 16,
+			false => *i,
+		})
+		.collect();
+	offsets.sort_unstable();
+	offsets.dedup();
+
+	for offset in offsets {
+		let chosen: Option<Vec<usize>> = range_vals
+			.iter()
+			.map(|(_, candidates)| {
+				candidates
+					.iter()
+					.find(|i| **i >= offset && **i <= offset + range)
+					.cloned()
+			})
+			.collect();
+		if let Some(chosen) = chosen {
+			for (val, index) in range_vals.iter_mut().zip(chosen) {
+				val.1 = vec![index];
+			}
+			return Ok((true, offset));
 		}
-		lists.push(in_vec);
 	}
+	Ok((false, 0))
+}
 
-	// Convert the `Vec<Vec<String>>` into a `Vec<Vec<&str>>`
-	let tmp: Vec<Vec<&str>> = lists
-		.iter()
-		.map(|list| list.iter().map(AsRef::as_ref).collect::<Vec<&str>>())
-		.collect();
+/// Whether a palette index lands on index 0 of a frame's range, so would be
+/// drawn transparent
+fn is_transparent_index(index: usize, align_to_16: bool) -> bool {
+	match align_to_16 {
+		true => index % 16 == 0,
+		false => index == 0,
+	}
+}
 
-	// Convert the `Vec<Vec<&str>>` into a `Vec<&[&str]>`
-	let vector_of_slices: Vec<&[&str]> = tmp.iter().map(AsRef::as_ref).collect();
+/// Start and length of the palette entries a frame at the given offset can use
+fn remap_window(
+	palette: &VeraPalette,
+	offset: usize,
+	range: u32,
+	align_to_16: bool,
+) -> (usize, usize) {
+	match align_to_16 {
+		true => (offset, range as usize + 1),
+		false => (0, palette.len()),
+	}
+}
 
-	// Initialize the Permutator
-	if vector_of_slices.len() == 1 {
-		// In the case of only one pixel to match, don't attempt to call permutator, which crashes out,
-		// just take min value
-		let mut min = usize::from_str_radix(vector_of_slices[0][0], 10).unwrap();
-		if align_to_16 {
-			min -= min % 16;
+/// When none of a frame's colours are in the palette, find the offset
+/// whose range holds the closest match for all of them
+fn closest_remap_range(
+	palette: &VeraPalette,
+	colors: &BTreeSet<VeraPaletteEntry>,
+	range: u32,
+	align_to_16: bool,
+) -> usize {
+	if !align_to_16 {
+		return 0;
+	}
+	let mut best = (0, f64::MAX);
+	for offset in (0..palette.len()).step_by(16) {
+		let (start, len) = remap_window(palette, offset, range, align_to_16);
+		let total = colors.iter().fold(0.0, |acc, e| {
+			let (r, g, b) = e.to_rgb8();
+			match palette.nearest_index_in_range(r, g, b, start, len) {
+				Some((_, d)) => acc + d,
+				None => f64::MAX,
+			}
+		});
+		if total < best.1 {
+			best = (offset, total);
 		}
-		return Ok((true, min));
 	}
-	let permutator = Permutator::new(&vector_of_slices);
+	best.0
+}
 
-	// iteration 2: allocates a new buffer for each permutation
-	// you may opt to re-allocate or not (see iteration 1)
-	for permutation in permutator {
-		let max = match permutation
-			.iter()
-			.map(|v| usize::from_str_radix(&v, 10))
-			.filter_map(Result::ok)
-			.max()
-		{
-			Some(i) => i,
-			None => {
-				return Err(ErrorKind::IteratorError("Finding max index".to_string()).into());
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	/// The exhaustive search this replaced: first combination of candidates,
+	/// with the last colour varying fastest, that fits in the range
+	fn brute_force(
+		range: u32,
+		vals: &[Vec<usize>],
+		align_to_16: bool,
+	) -> Option<(Vec<usize>, usize)> {
+		let mut counters = vec![0; vals.len()];
+		loop {
+			let chosen: Vec<usize> = counters
+				.iter()
+				.zip(vals.iter())
+				.map(|(c, v)| v[*c])
+				.collect();
+			let max = *chosen.iter().max().unwrap();
+			let mut min = *chosen.iter().min().unwrap();
+			if align_to_16 {
+				min -= min % 16;
 			}
-		};
-		let mut min = match permutation
-			.iter()
-			.map(|v| usize::from_str_radix(&v, 10))
-			.filter_map(Result::ok)
-			.min()
-		{
-			Some(i) => i,
-			None => {
-				return Err(ErrorKind::IteratorError("Finding min index".to_string()).into());
+			if max - min <= range as usize {
+				return Some((chosen, min));
 			}
-		};
-
-		if align_to_16 {
-			// Now min needs to be rounded down to the nearest multiple of 16, since pal offsets
-			// are calculated as offset * 16
-			min -= min % 16;
-		}
-
-		// if we're in range, we're done
-		if max - min <= range as usize {
-			for i in 0..permutation.len() {
-				if i == range_vals.len() {
-					break;
+			let mut i = vals.len();
+			loop {
+				if i == 0 {
+					return None;
 				}
-				range_vals[i].1 = vec![usize::from_str_radix(permutation[i], 10)?];
-				if max == min {
+				i -= 1;
+				counters[i] += 1;
+				if counters[i] < vals[i].len() {
 					break;
 				}
+				counters[i] = 0;
+			}
+		}
+	}
+
+	#[test]
+	fn optimal_range_matches_brute_force() {
+		// deterministic pseudo-random candidate lists
+		let mut seed = 0x2545_f491u32;
+		let mut next = move |m: u32| {
+			seed ^= seed << 13;
+			seed ^= seed >> 17;
+			seed ^= seed << 5;
+			seed % m
+		};
+		for _ in 0..500 {
+			let colors = 1 + next(5) as usize;
+			let vals: Vec<Vec<usize>> = (0..colors)
+				.map(|_| {
+					let mut v: Vec<usize> = (0..1 + next(4)).map(|_| next(64) as usize).collect();
+					v.sort_unstable();
+					v.dedup();
+					v
+				})
+				.collect();
+			for &(range, align) in [(3, true), (15, true), (255, false)].iter() {
+				let mut mappings: Vec<(VeraPaletteEntry, Vec<usize>)> = vals
+					.iter()
+					.map(|v| (VeraPaletteEntry::default(), v.clone()))
+					.collect();
+				let (found, offset) = find_optimal_range(range, &mut mappings, align).unwrap();
+				match brute_force(range, &vals, align) {
+					Some((chosen, min)) => {
+						assert!(found);
+						assert_eq!(offset, min);
+						let result: Vec<usize> = mappings.iter().map(|m| m.1[0]).collect();
+						assert_eq!(result, chosen);
+					}
+					None => assert!(!found),
+				}
 			}
-			return Ok((true, min));
 		}
 	}
-	Ok((false, 0))
 }
@@ -27,20 +27,42 @@ extern crate aloevera_util as util;
 #[macro_use]
 extern crate serde_derive;
 
+mod animation;
 mod asm;
+mod atlas;
 mod bitmap;
+mod color;
+mod cycle;
+mod dither;
 mod error;
+mod fade;
+mod image_util;
 mod imageset;
 mod palette;
+mod patch;
 mod png_util;
+mod quantize;
 mod sprite;
+mod subpalette;
 mod tilemap;
 
+pub use animation::{VeraAnimation, VeraAnimationFrame, VeraAnimationLoop, VeraAnimationTable};
 pub use asm::{AsmFormat, Assemblable, AssembledPrimitive};
 pub use bitmap::VeraBitmap;
+pub use cycle::{VeraPaletteCycle, VeraPaletteCycleDirection, VeraPaletteCycleTable};
+pub use dither::VeraColorConversion;
 pub use error::{Error, ErrorKind};
-pub use imageset::{VeraImage, VeraImageSet, VeraImageSetLoadConfig, VeraPixelDepth};
+pub use fade::{VeraPaletteFade, VeraPaletteFadeTarget};
+pub use image_util::image_to_frames;
+pub use imageset::{
+	VeraAnimationDirection, VeraFrameRef, VeraImage, VeraImageSet, VeraImageSetFormatConfig,
+	VeraImageSetLoadConfig, VeraImageSetTag, VeraPixelDepth, VeraRecolouredFrame, VeraRemapReport,
+	VeraTransparency,
+};
 pub use palette::{VeraPalette, VeraPaletteEntry, VeraPaletteLoadConfig};
+pub use patch::VeraPalettePatch;
 pub use png_util::png_to_frames;
+pub use quantize::VeraQuantizeReport;
 pub use sprite::VeraSprite;
+pub use subpalette::pack_sub_palettes;
 pub use tilemap::{VeraTileMap, VeraTileMapDim, VeraTileMapEntry, VeraTileMapMode};
@@ -13,9 +13,19 @@
 
 //! Vera Palette definition
 
+use crate::color::{lab_distance, rgb_to_lab};
+use crate::cycle::VeraPaletteCycle;
+use crate::dither::convert_rgb;
+use crate::png_util::{decode_png, png_to_rgb};
+use crate::quantize::{self, VeraQuantizeReport};
 use crate::{Assemblable, AssembledPrimitive};
-use crate::{Error, ErrorKind};
-use aloevera_util::gpl::parse_gpl_from_bytes;
+use crate::{Error, ErrorKind, VeraColorConversion, VeraPixelDepth};
+use aloevera_util::adobe::{parse_aco_from_bytes, parse_act_from_bytes};
+use aloevera_util::aseprite::parse_aseprite_from_bytes;
+use aloevera_util::gpl::{parse_gpl_from_bytes, write_gpl_to_bytes};
+use aloevera_util::hexpal::parse_hex_palette_from_bytes;
+use aloevera_util::jasc::{parse_jasc_from_bytes, write_jasc_to_bytes};
+use std::collections::BTreeSet;
 use std::fmt;
 
 const PALETTE_SIZE: usize = 256;
@@ -70,6 +80,27 @@ impl VeraPaletteEntry {
 			b: b >> 4,
 		}
 	}
+
+	/// new, rounding each component to the nearest
+	/// 4 bit value instead of truncating
+	pub fn from_rgb8_rounded(r: u8, g: u8, b: u8) -> Self {
+		let round = |c: u8| ((c as u16 * 15 + 127) / 255) as u8;
+		Self {
+			r: round(r),
+			g: round(g),
+			b: round(b),
+		}
+	}
+
+	/// Expand back out to 8 bits per component, as
+	/// the entry will be displayed
+	pub fn to_rgb8(&self) -> (u8, u8, u8) {
+		(
+			self.r << 4 | self.r,
+			self.g << 4 | self.g,
+			self.b << 4 | self.b,
+		)
+	}
 }
 
 /// Vera Palette
@@ -79,6 +110,11 @@ pub struct VeraPalette {
 	pub id: String,
 	/// Palette values
 	entries: Vec<VeraPaletteEntry>,
+	/// Indices that were only padded out to make room when importing
+	/// into a range, and haven't had anything loaded into them
+	empty_slots: BTreeSet<usize>,
+	/// Colour cycling ranges
+	cycles: Vec<VeraPaletteCycle>,
 }
 
 impl Default for VeraPalette {
@@ -95,6 +131,8 @@ impl Default for VeraPalette {
 		VeraPalette {
 			id: id.to_owned(),
 			entries,
+			empty_slots: BTreeSet::new(),
+			cycles: vec![],
 		}
 	}
 }
@@ -108,7 +146,14 @@ impl fmt::Display for VeraPalette {
 			let mut val: u16 = (e.r as u16) << 8;
 			val |= (e.g as u16) << 4;
 			val |= e.b as u16;
-			let _ = writeln!(f, "Index {}: 0x{:x}", i, val);
+			match self.empty_slots.contains(&i) {
+				true => {
+					let _ = writeln!(f, "Index {}: (empty)", i);
+				}
+				false => {
+					let _ = writeln!(f, "Index {}: 0x{:x}", i, val);
+				}
+			}
 			i += 1;
 		});
 		writeln!(f)
@@ -127,6 +172,15 @@ pub struct VeraPaletteLoadConfig {
 	pub include_defaults: bool,
 	/// Whether to sort palette entries on load
 	pub sort: bool,
+	/// If set, quantize PNG image data down to the number
+	/// of colours addressable at the given depth (e.g. 16 for 4bpp)
+	/// instead of failing when the image has too many colours
+	pub quantize: Option<VeraPixelDepth>,
+	/// How colours in PNG image data are reduced to 4 bits
+	/// per channel, should match the conversion used when
+	/// loading imagesets from the same image. Colours taken from
+	/// a PNG's own palette can't be dithered, so are rounded instead
+	pub conversion: VeraColorConversion,
 }
 
 impl Default for VeraPaletteLoadConfig {
@@ -135,6 +189,8 @@ impl Default for VeraPaletteLoadConfig {
 			direct_load: false,
 			include_defaults: true,
 			sort: true,
+			quantize: None,
+			conversion: VeraColorConversion::Truncate,
 		}
 	}
 }
@@ -145,6 +201,8 @@ impl VeraPalette {
 		VeraPalette {
 			id: id.to_owned(),
 			entries: vec![],
+			empty_slots: BTreeSet::new(),
+			cycles: vec![],
 		}
 	}
 
@@ -154,6 +212,8 @@ impl VeraPalette {
 		let mut retval = VeraPalette {
 			id: id.to_owned(),
 			entries: vec![],
+			empty_slots: BTreeSet::new(),
+			cycles: vec![],
 		};
 		let _ = retval.add_entry(true, 0, 0, 0);
 		let _ = retval.add_entry(true, 0xff, 0xff, 0xff);
@@ -174,18 +234,124 @@ impl VeraPalette {
 		let gpl_palette = match parse_gpl_from_bytes(gpl_data) {
 			Ok(p) => p,
 			Err(s) => {
-				return Err(ErrorKind::GenericError(format!("Error: {}", s)).into());
+				return Err(ErrorKind::PaletteFileInvalid("Gimp".into(), s.into()).into());
 			}
 		};
 		debug!(
 			"Palette load: Gimp palette with {} colors",
 			gpl_palette.len()
 		);
+		VeraPalette::derive_from_colors(id, gpl_palette, config)
+	}
+
+	/// Derives a palette from the given JASC (Paint Shop Pro / Aseprite) pal file
+	pub fn derive_from_jasc(
+		id: &str,
+		jasc_data: Vec<u8>,
+		config: &VeraPaletteLoadConfig,
+	) -> Result<Self, Error> {
+		let jasc_palette = match parse_jasc_from_bytes(jasc_data) {
+			Ok(p) => p,
+			Err(s) => {
+				return Err(ErrorKind::PaletteFileInvalid("JASC".into(), s.into()).into());
+			}
+		};
+		debug!(
+			"Palette load: JASC palette with {} colors",
+			jasc_palette.len()
+		);
+		VeraPalette::derive_from_colors(id, jasc_palette, config)
+	}
+
+	/// Derives a palette from the given Adobe act (Color Table) file
+	pub fn derive_from_act(
+		id: &str,
+		act_data: Vec<u8>,
+		config: &VeraPaletteLoadConfig,
+	) -> Result<Self, Error> {
+		let act_palette = match parse_act_from_bytes(act_data) {
+			Ok(p) => p,
+			Err(s) => {
+				return Err(ErrorKind::PaletteFileInvalid("Adobe act".into(), s.into()).into());
+			}
+		};
+		debug!(
+			"Palette load: Adobe act palette with {} colors",
+			act_palette.len()
+		);
+		VeraPalette::derive_from_colors(id, act_palette, config)
+	}
+
+	/// Derives a palette from the given Adobe aco (Color Swatch) file
+	pub fn derive_from_aco(
+		id: &str,
+		aco_data: Vec<u8>,
+		config: &VeraPaletteLoadConfig,
+	) -> Result<Self, Error> {
+		let aco_palette = match parse_aco_from_bytes(aco_data) {
+			Ok(p) => p,
+			Err(s) => {
+				return Err(ErrorKind::PaletteFileInvalid("Adobe aco".into(), s.into()).into());
+			}
+		};
+		debug!(
+			"Palette load: Adobe aco palette with {} colors",
+			aco_palette.len()
+		);
+		VeraPalette::derive_from_colors(id, aco_palette, config)
+	}
+
+	/// Derives a palette from the given plain text hex file (e.g. from Lospec),
+	/// one RRGGBB value per line
+	pub fn derive_from_hex(
+		id: &str,
+		hex_data: Vec<u8>,
+		config: &VeraPaletteLoadConfig,
+	) -> Result<Self, Error> {
+		let hex_palette = match parse_hex_palette_from_bytes(hex_data) {
+			Ok(p) => p,
+			Err(s) => {
+				return Err(ErrorKind::PaletteFileInvalid("hex".into(), s.into()).into());
+			}
+		};
+		debug!(
+			"Palette load: hex palette with {} colors",
+			hex_palette.len()
+		);
+		VeraPalette::derive_from_colors(id, hex_palette, config)
+	}
+
+	/// Derives a palette from the palette stored in an Aseprite file
+	pub fn derive_from_aseprite(
+		id: &str,
+		ase_data: Vec<u8>,
+		config: &VeraPaletteLoadConfig,
+	) -> Result<Self, Error> {
+		let ase = match parse_aseprite_from_bytes(&ase_data) {
+			Ok(a) => a,
+			Err(s) => {
+				return Err(ErrorKind::PaletteFileInvalid("Aseprite".into(), s.into()).into());
+			}
+		};
+		debug!(
+			"Palette load: Aseprite palette with {} colors",
+			ase.palette.len()
+		);
+		let colors = ase.palette.iter().map(|p| (p[0], p[1], p[2])).collect();
+		VeraPalette::derive_from_colors(id, colors, config)
+	}
+
+	/// Derives a palette from a list of RGB values, as read from a palette file
+	fn derive_from_colors(
+		id: &str,
+		colors: Vec<(u8, u8, u8)>,
+		config: &VeraPaletteLoadConfig,
+	) -> Result<Self, Error> {
 		let mut palette = match config.include_defaults {
 			true => VeraPalette::blank_with_defaults(id),
 			false => VeraPalette::blank(id),
 		};
-		for color in gpl_palette.iter() {
+		for color in colors.iter() {
 			palette.add_entry(
 				config.direct_load,
 				color.0 as u8,
@@ -207,30 +373,37 @@ impl VeraPalette {
 		png_data: Vec<u8>,
 		config: &VeraPaletteLoadConfig,
 	) -> Result<Self, Error> {
+		if config.quantize.is_some() {
+			let (palette, report) = VeraPalette::quantize_from_png(id, png_data, config)?;
+			info!("{}", report);
+			return Ok(palette);
+		}
 		let decoder = png::Decoder::new(&*png_data);
-		let (dec_info, mut reader) = decoder.read_info()?;
+		let (_, reader) = decoder.read_info()?;
 		let info = reader.info();
-		if info.bit_depth == png::BitDepth::Sixteen {
-			return Err(
-				ErrorKind::PNGInvalid(format!("PNG must be 8 bit color depth or less")).into(),
-			);
-		}
-
-		let reader_step = info.color_type.samples();
 
 		debug!("Palette load: Decoded PNG Info: {:?}", info);
-		let (step, buf) = match info.palette.clone() {
+		let (mut colors, width, conversion) = match info.palette.clone() {
 			Some(p) => {
 				info!("Creating new palette from image palette");
-				(3, p)
+				let conversion = match config.conversion {
+					VeraColorConversion::Truncate => VeraColorConversion::Truncate,
+					_ => VeraColorConversion::Round,
+				};
+				let colors: Vec<(u8, u8, u8)> = p.chunks(3).map(|c| (c[0], c[1], c[2])).collect();
+				let width = colors.len();
+				(colors, width, conversion)
 			}
 			None => {
 				info!("Creating new palette from image data");
-				let mut buf = vec![0; dec_info.buffer_size()];
-				reader.next_frame(&mut buf)?;
-				(reader_step, buf)
+				let (width, _, pixels) = decode_png(&png_data)?;
+				let colors = pixels.iter().map(|p| (p[0], p[1], p[2])).collect();
+				(colors, width as usize, config.conversion)
 			}
 		};
+		if let Some(height) = colors.len().checked_div(width) {
+			convert_rgb(&mut colors, width, height, conversion);
+		}
 
 		let mut palette = match config.include_defaults {
 			true => VeraPalette::blank_with_defaults(id),
@@ -239,8 +412,8 @@ impl VeraPalette {
 
 		// if creating a new palette, pass through the image data adding all palette
 		// entries until we're done or we can't anymore
-		for i in (0..buf.len()).step_by(step) {
-			palette.add_entry(config.direct_load, buf[i], buf[i + 1], buf[i + 2])?;
+		for (r, g, b) in colors.into_iter() {
+			palette.add_entry(config.direct_load, r, g, b)?;
 		}
 		if config.sort {
 			palette.sort();
@@ -249,6 +422,37 @@ impl VeraPalette {
 		Ok(palette)
 	}
 
+	/// Derives a palette from the given png image by quantizing its
+	/// colours into the entries available at the configured `quantize`
+	/// depth (256 entries if unset), returning the palette along with
+	/// a report of the colour error introduced
+	pub fn quantize_from_png(
+		id: &str,
+		png_data: Vec<u8>,
+		config: &VeraPaletteLoadConfig,
+	) -> Result<(Self, VeraQuantizeReport), Error> {
+		let depth = config.quantize.unwrap_or(VeraPixelDepth::BPP8);
+		let pixels = png_to_rgb(png_data)?;
+		let mut palette = match config.include_defaults {
+			true => VeraPalette::blank_with_defaults(id),
+			false => VeraPalette::blank(id),
+		};
+		let max_colors = 2usize.pow(depth as u32);
+		let available = max_colors.saturating_sub(palette.len());
+		info!("Quantizing image data into {} palette entries", available);
+		for e in quantize::quantize(&pixels, available) {
+			if !palette.entries.contains(&e) {
+				palette.entries.push(e);
+			}
+		}
+		if config.sort {
+			palette.sort();
+		}
+		let report = quantize::measure_error(&pixels, &palette.entries);
+		info!("Palette creation successful");
+		Ok((palette, report))
+	}
+
 	/// Return the size of said palette
 	pub fn len(&self) -> usize {
 		self.entries.len()
@@ -264,7 +468,8 @@ impl VeraPalette {
 		let b = b >> 4;
 		self.entries
 			.iter()
-			.position(|&e| e.r == r && e.g == g && e.b == b)
+			.enumerate()
+			.position(|(i, &e)| e.r == r && e.g == g && e.b == b && !self.empty_slots.contains(&i))
 	}
 
 	/// Return all instances of a colour in the palette
@@ -277,7 +482,7 @@ impl VeraPalette {
 			.entries
 			.iter()
 			.enumerate()
-			.filter(|(_, e)| e.r == r && e.g == g && e.b == b)
+			.filter(|(i, e)| e.r == r && e.g == g && e.b == b && !self.empty_slots.contains(i))
 			.map(|(i, e)| {
 				ret_entry = e.clone();
 				i
@@ -286,6 +491,32 @@ impl VeraPalette {
 		(ret_entry, ret_vec)
 	}
 
+	/// return the index of the entry perceptually closest to the given
+	/// colour within `len` entries from `start`, along with its distance (delta E)
+	pub fn nearest_index_in_range(
+		&self,
+		r: u8,
+		g: u8,
+		b: u8,
+		start: usize,
+		len: usize,
+	) -> Option<(usize, f64)> {
+		let lab = rgb_to_lab(r, g, b);
+		let mut best: Option<(usize, f64)> = None;
+		for (i, e) in self.entries.iter().enumerate().skip(start).take(len) {
+			if self.empty_slots.contains(&i) {
+				continue;
+			}
+			let (er, eg, eb) = e.to_rgb8();
+			let d = lab_distance(&lab, &rgb_to_lab(er, eg, eb));
+			match best {
+				Some((_, bd)) if bd <= d => {}
+				_ => best = Some((i, d)),
+			}
+		}
+		best
+	}
+
 	/// return entry at given index
 	pub fn value_at_index(&self, index: usize) -> Result<VeraPaletteEntry, Error> {
 		if index as usize >= self.entries.len() {
@@ -316,9 +547,281 @@ impl VeraPalette {
 		Ok(self.entries.len().saturating_sub(1))
 	}
 
-	/// Sorts the palette in ascending order, rgb
+	/// Whether anything has been loaded into the given index
+	pub fn is_slot_occupied(&self, index: usize) -> bool {
+		index < self.entries.len() && !self.empty_slots.contains(&index)
+	}
+
+	/// Copy all entries of `source` into this palette starting at index `start`,
+	/// padding out the palette with empty slots if it doesn't reach `start`.
+	/// Fails if any target slot is already occupied, unless `overwrite` is set
+	pub fn import_at(
+		&mut self,
+		start: usize,
+		source: &VeraPalette,
+		overwrite: bool,
+	) -> Result<(), Error> {
+		let end = start + source.entries.len();
+		if end > 256 {
+			return Err(ErrorKind::PaletteRangeInvalid(start, end - 1).into());
+		}
+		if !overwrite {
+			if let Some(i) = (start..end).find(|i| self.is_slot_occupied(*i)) {
+				return Err(ErrorKind::PaletteSlotOccupied(i).into());
+			}
+		}
+		while self.entries.len() < start {
+			self.empty_slots.insert(self.entries.len());
+			self.entries.push(VeraPaletteEntry::default());
+		}
+		for (i, e) in (start..end).zip(source.entries.iter()) {
+			match i < self.entries.len() {
+				true => self.entries[i] = *e,
+				false => self.entries.push(*e),
+			}
+			self.empty_slots.remove(&i);
+		}
+		Ok(())
+	}
+
+	/// Sorts the palette in ascending order, rgb, with any empty slots last
 	pub fn sort(&mut self) {
-		self.entries.sort()
+		self.sort_entries();
+	}
+
+	fn check_index(&self, index: usize) -> Result<(), Error> {
+		if index >= self.entries.len() {
+			return Err(ErrorKind::PaletteInvalidIndex(index).into());
+		}
+		Ok(())
+	}
+
+	/// Rearrange entries so that new index `i` holds what was at `order[i]`.
+	/// Returns a map from each old index to its new one, `None` if dropped.
+	/// Cycles follow their entries, and are dropped if those are no longer
+	/// all present and in order
+	fn apply_order(&mut self, order: &[usize]) -> Vec<Option<usize>> {
+		let mut index_map = vec![None; self.entries.len()];
+		for (new, old) in order.iter().enumerate() {
+			index_map[*old] = Some(new);
+		}
+		self.entries = order.iter().map(|i| self.entries[*i]).collect();
+		self.empty_slots = self
+			.empty_slots
+			.iter()
+			.filter_map(|i| index_map[*i])
+			.collect();
+		let cycles = std::mem::take(&mut self.cycles);
+		for mut c in cycles {
+			let start = index_map.get(c.start).cloned().flatten();
+			let kept = start.is_some_and(|s| {
+				(0..c.len).all(|i| index_map.get(c.start + i).cloned().flatten() == Some(s + i))
+			});
+			match (start, kept) {
+				(Some(s), true) => {
+					c.start = s;
+					self.cycles.push(c);
+				}
+				_ => warn!(
+					"Cycle {} no longer covers a run of palette {}, and was removed",
+					c.name, self.id
+				),
+			}
+		}
+		index_map
+	}
+
+	/// Replace the colour at the given index. Indices don't move
+	pub fn set_entry(&mut self, index: usize, r: u8, g: u8, b: u8) -> Result<(), Error> {
+		self.check_index(index)?;
+		self.entries[index] = VeraPaletteEntry::new(r, g, b);
+		self.empty_slots.remove(&index);
+		Ok(())
+	}
+
+	/// Swap two entries, returning the old to new index map
+	pub fn swap_entries(&mut self, a: usize, b: usize) -> Result<Vec<Option<usize>>, Error> {
+		self.check_index(a)?;
+		self.check_index(b)?;
+		let mut order: Vec<usize> = (0..self.entries.len()).collect();
+		order.swap(a, b);
+		Ok(self.apply_order(&order))
+	}
+
+	/// Move an entry to a new index, shifting those in between.
+	/// Returns the old to new index map
+	pub fn move_entry(&mut self, from: usize, to: usize) -> Result<Vec<Option<usize>>, Error> {
+		self.check_index(from)?;
+		self.check_index(to)?;
+		let mut order: Vec<usize> = (0..self.entries.len()).collect();
+		let moved = order.remove(from);
+		order.insert(to, moved);
+		Ok(self.apply_order(&order))
+	}
+
+	/// Insert a new entry at the given index, shifting everything after it
+	/// up by one. Returns the old to new index map
+	pub fn insert_entry(
+		&mut self,
+		index: usize,
+		r: u8,
+		g: u8,
+		b: u8,
+	) -> Result<Vec<Option<usize>>, Error> {
+		if index > self.entries.len() {
+			return Err(ErrorKind::PaletteInvalidIndex(index).into());
+		}
+		if self.entries.len() >= 256 {
+			return Err(ErrorKind::PaletteFull.into());
+		}
+		let len = self.entries.len();
+		self.entries.push(VeraPaletteEntry::new(r, g, b));
+		let mut order: Vec<usize> = (0..len).collect();
+		order.insert(index, len);
+		let mut index_map = self.apply_order(&order);
+		index_map.truncate(len);
+		Ok(index_map)
+	}
+
+	/// Remove the entry at the given index, shifting everything after it
+	/// down by one. Returns the old to new index map
+	pub fn remove_entry(&mut self, index: usize) -> Result<Vec<Option<usize>>, Error> {
+		self.check_index(index)?;
+		let order: Vec<usize> = (0..self.entries.len()).filter(|i| *i != index).collect();
+		Ok(self.apply_order(&order))
+	}
+
+	/// As `sort`, returning the old to new index map
+	pub fn sort_entries(&mut self) -> Vec<Option<usize>> {
+		let mut order: Vec<usize> = (0..self.entries.len()).collect();
+		order.sort_by_key(|i| (self.empty_slots.contains(i), self.entries[*i]));
+		self.apply_order(&order)
+	}
+
+	/// All entries, expanded back out to 8 bits per channel
+	pub fn to_rgb8(&self) -> Vec<(u8, u8, u8)> {
+		self.entries.iter().map(|e| e.to_rgb8()).collect()
+	}
+
+	/// Output the palette as the contents of a Gimp gpl file
+	pub fn to_gpl(&self) -> Vec<u8> {
+		write_gpl_to_bytes(&self.id, &self.to_rgb8())
+	}
+
+	/// Output the palette as the contents of a JASC pal file
+	pub fn to_jasc(&self) -> Vec<u8> {
+		write_jasc_to_bytes(&self.to_rgb8())
+	}
+
+	/// Output the palette as an indexed PNG, 16 entries per row with each
+	/// entry drawn as a `swatch_size` square. The PNG's own palette holds
+	/// the same entries, so it can be imported again as-is
+	pub fn to_png_swatch(&self, swatch_size: u32) -> Result<Vec<u8>, Error> {
+		if self.entries.is_empty() {
+			return Err(ErrorKind::PaletteEmpty(self.id.clone()).into());
+		}
+		let rows = (0..self.entries.len())
+			.collect::<Vec<usize>>()
+			.chunks(16)
+			.map(|r| r.to_vec())
+			.collect();
+		self.png_from_index_rows(rows, swatch_size)
+	}
+
+	/// All cycles declared on this palette
+	pub fn cycles(&self) -> &[VeraPaletteCycle] {
+		&self.cycles
+	}
+
+	/// Declare a colour cycle, replacing any existing cycle of the same name
+	pub fn set_cycle(&mut self, cycle: VeraPaletteCycle) -> Result<(), Error> {
+		if cycle.name.is_empty()
+			|| !cycle
+				.name
+				.chars()
+				.all(|c| c.is_ascii_alphanumeric() || c == '_')
+		{
+			return Err(ErrorKind::PaletteCycleInvalidName(cycle.name).into());
+		}
+		if cycle.len < 2 || cycle.len > 255 || cycle.start + cycle.len > self.entries.len() {
+			return Err(ErrorKind::PaletteCycleRangeInvalid(
+				cycle.start,
+				cycle.len,
+				self.entries.len(),
+			)
+			.into());
+		}
+		match self.cycles.iter_mut().find(|c| c.name == cycle.name) {
+			Some(c) => *c = cycle,
+			None => self.cycles.push(cycle),
+		}
+		Ok(())
+	}
+
+	/// Remove the named cycle
+	pub fn remove_cycle(&mut self, name: &str) -> Result<(), Error> {
+		match self.cycles.iter().position(|c| c.name == name) {
+			Some(i) => {
+				self.cycles.remove(i);
+				Ok(())
+			}
+			None => Err(ErrorKind::PaletteCycleMissing(name.to_owned()).into()),
+		}
+	}
+
+	/// Output an indexed PNG previewing the named cycle, with one row of
+	/// the cycled entries per step, in order
+	pub fn to_png_cycle_preview(&self, name: &str, swatch_size: u32) -> Result<Vec<u8>, Error> {
+		let cycle = match self.cycles.iter().find(|c| c.name == name) {
+			Some(c) => c,
+			None => return Err(ErrorKind::PaletteCycleMissing(name.to_owned()).into()),
+		};
+		let rows = (0..cycle.len)
+			.map(|step| {
+				(cycle.start..cycle.start + cycle.len)
+					.map(|i| cycle.source_index(i, step))
+					.collect()
+			})
+			.collect();
+		self.png_from_index_rows(rows, swatch_size)
+	}
+
+	/// Draw rows of palette indices as `swatch_size` squares, in an indexed
+	/// PNG using this palette. Short rows are padded out with index 0
+	fn png_from_index_rows(
+		&self,
+		rows: Vec<Vec<usize>>,
+		swatch_size: u32,
+	) -> Result<Vec<u8>, Error> {
+		let columns = rows.iter().map(|r| r.len()).max().unwrap_or(0) as u32;
+		let width = columns * swatch_size;
+		let height = rows.len() as u32 * swatch_size;
+		let mut data = vec![];
+		for row in rows.iter() {
+			let mut line = vec![];
+			for x in 0..columns as usize {
+				let index = row.get(x).copied().unwrap_or(0) as u8;
+				line.extend(std::iter::repeat_n(index, swatch_size as usize));
+			}
+			for _ in 0..swatch_size {
+				data.extend_from_slice(&line);
+			}
+		}
+		let mut plte = vec![];
+		for (r, g, b) in self.to_rgb8() {
+			plte.extend_from_slice(&[r, g, b]);
+		}
+
+		let mut retval = vec![];
+		{
+			let mut encoder = png::Encoder::new(&mut retval, width, height);
+			encoder.set_color(png::ColorType::Indexed);
+			encoder.set_depth(png::BitDepth::Eight);
+			let mut writer = encoder.write_header()?;
+			writer.write_chunk(*b"PLTE", &plte)?;
+			writer.write_image_data(&data)?;
+		}
+		Ok(retval)
 	}
 }
 
@@ -405,6 +908,39 @@ mod test {
 		Ok(())
 	}
 
+	#[test]
+	fn palette_edit_index_maps() -> Result<(), Error> {
+		let mut palette = VeraPalette::blank_with_defaults("my_palette");
+		palette.add_entry(true, 0xF0, 0x00, 0x00)?;
+
+		// 0: black, 1: white, 2: red
+		assert_eq!(palette.swap_entries(0, 2)?, vec![Some(2), Some(1), Some(0)]);
+		assert_eq!(palette.move_entry(0, 2)?, vec![Some(2), Some(0), Some(1)]);
+		assert_eq!(palette.index_of_rgb(0xF0, 0x00, 0x00), Some(2));
+		assert_eq!(
+			palette.insert_entry(1, 0x00, 0xF0, 0x00)?,
+			vec![Some(0), Some(2), Some(3)]
+		);
+		assert_eq!(
+			palette.remove_entry(0)?,
+			vec![None, Some(0), Some(1), Some(2)]
+		);
+		palette.set_entry(0, 0x00, 0x00, 0xF0)?;
+		assert_eq!(palette.index_of_rgb(0x00, 0xF0, 0x00), None);
+		assert!(palette.set_entry(3, 0, 0, 0).is_err());
+
+		// empty slots stay empty and sort to the end
+		let mut palette = VeraPalette::blank("my_palette");
+		palette.import_at(2, &VeraPalette::blank_with_defaults("defaults"), false)?;
+		assert_eq!(
+			palette.sort_entries(),
+			vec![Some(2), Some(3), Some(0), Some(1)]
+		);
+		assert!(palette.is_slot_occupied(1));
+		assert!(!palette.is_slot_occupied(2));
+		Ok(())
+	}
+
 	#[test]
 	fn palette_assemble() -> Result<(), Error> {
 		let palette = VeraPalette::default();
@@ -0,0 +1,123 @@
+// Copyright 2020 Revcore Technologies Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Sparse palette output, containing only the entries that differ
+//! from a base palette (usually the VERA default)
+
+use crate::asm::to_const_name;
+use crate::{Assemblable, AssembledPrimitive};
+use crate::{Error, ErrorKind};
+use crate::{VeraPalette, VeraPaletteEntry};
+
+/// Most entries a single record can hold, so the count fits in a byte
+const MAX_RUN_LEN: usize = 255;
+
+/// The changed runs of a palette relative to a base palette
+#[derive(Clone, Debug)]
+pub struct VeraPalettePatch {
+	/// id
+	pub id: String,
+	/// ID of the palette being patched in
+	pub palette_id: String,
+	/// ID of the palette assumed to already be loaded
+	pub base_id: String,
+	/// Start index and entries of each changed run
+	runs: Vec<(usize, Vec<VeraPaletteEntry>)>,
+}
+
+impl VeraPalettePatch {
+	/// Diff `palette` against `base`. Entries past the end of `base` always
+	/// count as changed, while empty slots in `palette` never do
+	pub fn from_palettes(palette: &VeraPalette, base: &VeraPalette) -> Result<Self, Error> {
+		if palette.len() == 0 {
+			return Err(ErrorKind::PaletteEmpty(palette.id.clone()).into());
+		}
+		let mut runs: Vec<(usize, Vec<VeraPaletteEntry>)> = vec![];
+		for i in 0..palette.len() {
+			if !palette.is_slot_occupied(i) {
+				continue;
+			}
+			let entry = palette.value_at_index(i)?;
+			if base.is_slot_occupied(i) && base.value_at_index(i)? == entry {
+				continue;
+			}
+			match runs.last_mut() {
+				Some((start, entries))
+					if *start + entries.len() == i && entries.len() < MAX_RUN_LEN =>
+				{
+					entries.push(entry)
+				}
+				_ => runs.push((i, vec![entry])),
+			}
+		}
+		Ok(VeraPalettePatch {
+			id: format!("{}_patch", palette.id),
+			palette_id: palette.id.clone(),
+			base_id: base.id.clone(),
+			runs,
+		})
+	}
+
+	/// Start index and entries of each changed run
+	pub fn runs(&self) -> &[(usize, Vec<VeraPaletteEntry>)] {
+		&self.runs
+	}
+
+	/// Number of changed entries
+	pub fn changed_count(&self) -> usize {
+		self.runs.iter().map(|(_, e)| e.len()).sum()
+	}
+
+	/// Size in bytes, including record headers and the terminating record
+	pub fn size(&self) -> usize {
+		self.runs.len() * 2 + self.changed_count() * 2 + 2
+	}
+}
+
+impl Assemblable for VeraPalettePatch {
+	fn id(&self) -> &str {
+		&self.id
+	}
+
+	fn size_in_bytes(&self, _conflated: bool) -> Result<usize, Error> {
+		Ok(self.size())
+	}
+
+	fn assemble(&self) -> Result<AssembledPrimitive, Error> {
+		let mut retval = AssembledPrimitive::new(self.id());
+		retval.add_meta(format!(
+			"{} - palette {} patched over {} - size is {}",
+			self.id,
+			self.palette_id,
+			self.base_id,
+			self.size()
+		));
+		retval.add_meta(format!(
+			"{} records of start index, entry count, then entries (2 bytes each), ending with a zero count",
+			self.runs.len()
+		));
+		retval.add_const(
+			&format!("{}_records", to_const_name(&self.id)),
+			self.runs.len(),
+		);
+		for (start, entries) in self.runs.iter() {
+			retval.add_data(&[*start as u8, entries.len() as u8]);
+			for e in entries.iter() {
+				retval.add_data(&[e.g << 4 | e.b, e.r]);
+			}
+		}
+		retval.add_data(&[0, 0]);
+		Ok(retval)
+	}
+}
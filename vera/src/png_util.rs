@@ -12,63 +12,233 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::VeraImage;
+use crate::dither::convert_rgb_masked;
 use crate::{Error, ErrorKind};
+use crate::{VeraColorConversion, VeraImage, VeraImageSetLoadConfig};
+use crate::{VeraPaletteEntry, VeraTransparency};
 
-/// Load png frames tiled
+/// Round a 16 bit sample to the nearest 8 bit value
+fn downsample_16(hi: u8, lo: u8) -> u8 {
+	((u16::from_be_bytes([hi, lo]) as u32 * 255 + 32767) / 65535) as u8
+}
+
+/// Decode a png into width, height and a flat list of RGBA values, one
+/// per pixel. Indexed pixels are expanded through the PLTE (and tRNS),
+/// grayscale or low bit depth samples scaled up to 8 bits and 16 bit
+/// samples rounded down to 8 bits
+pub(crate) fn decode_png(png_data: &[u8]) -> Result<(u32, u32, Vec<[u8; 4]>), Error> {
+	let mut decoder = png::Decoder::new(png_data);
+	decoder.set_transformations(png::Transformations::EXPAND);
+	let (info, mut reader) = decoder.read_info()?;
+	debug!("Decoded PNG Info: {:?}", info);
+	let mut buf = vec![0; info.buffer_size()];
+	reader.next_frame(&mut buf)?;
+	trace!("buf data is: {:?}", buf);
+
+	let step = info.color_type.samples();
+	let wide = info.bit_depth == png::BitDepth::Sixteen;
+	let row_len = info.width as usize * step * if wide { 2 } else { 1 };
+	let mut samples = vec![];
+	for row in buf.chunks(info.line_size) {
+		let row = &row[..row_len];
+		match wide {
+			true => samples.extend(row.chunks(2).map(|c| downsample_16(c[0], c[1]))),
+			false => samples.extend_from_slice(row),
+		}
+	}
+	let pixels = samples
+		.chunks(step)
+		.map(|p| match step {
+			1 => [p[0], p[0], p[0], 255],
+			2 => [p[0], p[0], p[0], p[1]],
+			3 => [p[0], p[1], p[2], 255],
+			_ => [p[0], p[1], p[2], p[3]],
+		})
+		.collect();
+	Ok((info.width, info.height, pixels))
+}
+
+/// Load png frames tiled according to the layout in `config`, marking
+/// pixels as transparent according to its `transparency`
 pub fn png_to_frames(
 	id: &str,
 	f_width: u32,
 	f_height: u32,
 	png_data: Vec<u8>,
+	config: &VeraImageSetLoadConfig,
 	result: &mut Vec<VeraImage>,
 ) -> Result<(u32, u32), Error> {
-	let decoder = png::Decoder::new(&*png_data);
-	let (info, mut reader) = decoder.read_info()?;
-	if info.width % f_width != 0 || info.height % f_height != 0 {
-		return Err(ErrorKind::PNGIncorrectDimensions(
-			info.width,
-			info.height,
-			f_width as u32,
-			f_height as u32,
-		)
-		.into());
-	}
-	debug!("Decoded PNG Info: {:?}", info);
-	let mut buf = vec![0; info.buffer_size()];
-	reader.next_frame(&mut buf)?;
-	trace!("buf data is: {:?}", buf);
+	let (width, _, pixels) = decode_png(&png_data)?;
+	pixels_to_frames(id, f_width, f_height, width, pixels, config, result)
+}
 
-	let frames_per_row = info.width / f_width;
-	let frames_per_col = info.height / f_height;
-	let frame_count = frames_per_col * frames_per_row;
+/// Slice decoded RGBA pixels `width` wide into frames as `slice_frames`
+/// does, after converting them with `convert_sheet`
+pub(crate) fn pixels_to_frames(
+	id: &str,
+	f_width: u32,
+	f_height: u32,
+	width: u32,
+	pixels: Vec<[u8; 4]>,
+	config: &VeraImageSetLoadConfig,
+	result: &mut Vec<VeraImage>,
+) -> Result<(u32, u32), Error> {
+	let pixels = convert_sheet(pixels, width, config);
+	slice_frames(id, f_width, f_height, width, pixels, config, result)
+}
 
-	if info.bit_depth == png::BitDepth::Sixteen {
-		return Err(ErrorKind::PNGInvalid(format!("PNG must be 8 bit color depth or less")).into());
+/// Slice RGB pixels `width` wide into frames, appending them to `result`,
+/// with `None` pixels loaded as transparent. Frames are read in rows after
+/// the margin and offset, skipping the spacing between them, up to the
+/// frame limit. Sheets without margin, offset or spacing must be an exact
+/// multiple of the frame size
+pub(crate) fn slice_frames(
+	id: &str,
+	f_width: u32,
+	f_height: u32,
+	width: u32,
+	pixels: Vec<Option<(u8, u8, u8)>>,
+	config: &VeraImageSetLoadConfig,
+	result: &mut Vec<VeraImage>,
+) -> Result<(u32, u32), Error> {
+	let height = match width {
+		0 => 0,
+		w => pixels.len() as u32 / w,
+	};
+	let start_x = config.margin + config.offset_x;
+	let start_y = config.margin + config.offset_y;
+	let packed = start_x == 0 && start_y == 0 && config.spacing == 0;
+	let dims_err = ErrorKind::PNGIncorrectDimensions(width, height, f_width, f_height);
+	if packed && (width % f_width != 0 || height % f_height != 0) {
+		return Err(dims_err.into());
 	}
 
-	let step = info.color_type.samples();
+	// how many frames fit along a side after the start, with spacing between
+	let fit = |size: u32, start: u32, frame: u32| match size >= start + frame {
+		true => (size - start - frame) / (frame + config.spacing) + 1,
+		false => 0,
+	};
+	let frames_per_row = fit(width, start_x, f_width);
+	let mut frames_per_col = fit(height, start_y, f_height);
+	let mut frame_count = (frames_per_col * frames_per_row) as usize;
+	if !packed && frame_count == 0 {
+		return Err(dims_err.into());
+	}
+	if let Some(limit) = config.limit {
+		if limit < frame_count {
+			frame_count = limit;
+			frames_per_col = (limit as u32).div_ceil(frames_per_row);
+		}
+	}
 
 	// read data png again, saving palette indices in the appropriate places
 	info!("Parsing {} frames", frame_count);
+	let first = result.len();
 	for i in 0..frame_count {
-		result.push(VeraImage::new(&format!("{}_{}", id, i), f_width, f_height));
+		result.push(VeraImage::new(
+			&format!("{}_{}", id, first + i),
+			f_width,
+			f_height,
+		));
 	}
-	for i in (0..buf.len()).step_by(step) {
-		let pixel_loc = i / step;
-		let row = pixel_loc % buf.len() / info.width as usize;
-		let col = pixel_loc - row * info.width as usize;
-		let frame_y = row / f_height as usize;
-		let frame_x = col / f_width as usize;
+	let (cell_width, cell_height) = (f_width + config.spacing, f_height + config.spacing);
+	for (pixel_loc, pixel) in pixels.into_iter().enumerate() {
+		let row = (pixel_loc / width as usize) as u32;
+		let col = (pixel_loc % width as usize) as u32;
+		if row < start_y || col < start_x {
+			continue;
+		}
+		let (row, col) = (row - start_y, col - start_x);
+		if row % cell_height >= f_height || col % cell_width >= f_width {
+			continue;
+		}
+		let frame_y = (row / cell_height) as usize;
+		let frame_x = (col / cell_width) as usize;
+		let index = frame_y * frames_per_row as usize + frame_x;
+		if frame_x >= frames_per_row as usize || index >= frame_count {
+			continue;
+		}
 		// Just load raw RGB values for now, and reconcile with a palette/depth
 		// later
-		result[frame_y * frames_per_row as usize + frame_x].push_pixel(
-			buf[i],
-			buf[i + 1],
-			buf[i + 2],
-			None,
-		);
+		let frame = &mut result[first + index];
+		match pixel {
+			None => frame.push_transparent_pixel(),
+			Some((r, g, b)) => frame.push_pixel(r, g, b, None),
+		}
 	}
 
 	Ok((frames_per_row, frames_per_col))
 }
+
+/// Whether a pixel is loaded as transparent
+pub(crate) fn is_transparent(transparency: VeraTransparency, [r, g, b, a]: [u8; 4]) -> bool {
+	match transparency {
+		VeraTransparency::None => false,
+		VeraTransparency::Alpha => a == 0,
+		VeraTransparency::Key(kr, kg, kb) => {
+			VeraPaletteEntry::new(r, g, b) == VeraPaletteEntry::new(kr, kg, kb)
+		}
+	}
+}
+
+/// Reduce the colours of a whole sheet with the configured conversion
+/// before it's sliced, so dithering runs across frame edges. Transparency
+/// is decided on the source colours first, so conversion can't move a
+/// pixel onto or off the key, and transparent pixels take no part in the
+/// dithering. Returns the RGB of each pixel, `None` where transparent
+pub(crate) fn convert_sheet(
+	pixels: Vec<[u8; 4]>,
+	width: u32,
+	config: &VeraImageSetLoadConfig,
+) -> Vec<Option<(u8, u8, u8)>> {
+	let mask: Vec<bool> = pixels
+		.iter()
+		.map(|p| is_transparent(config.transparency, *p))
+		.collect();
+	let mut rgb: Vec<(u8, u8, u8)> = pixels.iter().map(|p| (p[0], p[1], p[2])).collect();
+	if config.conversion != VeraColorConversion::Truncate && width > 0 {
+		info!("Converting colors with {}", config.conversion);
+		let height = pixels.len() / width as usize;
+		convert_rgb_masked(&mut rgb, width as usize, height, config.conversion, &mask);
+	}
+	rgb.into_iter()
+		.zip(mask)
+		.map(|(p, m)| match m {
+			true => None,
+			false => Some(p),
+		})
+		.collect()
+}
+
+/// Decode a png into a flat list of RGB values, one per pixel
+pub(crate) fn png_to_rgb(png_data: Vec<u8>) -> Result<Vec<(u8, u8, u8)>, Error> {
+	let (_, _, pixels) = decode_png(&png_data)?;
+	Ok(pixels.into_iter().map(|p| (p[0], p[1], p[2])).collect())
+}
+
+/// Decode an indexed png into its width and raw palette indices, one per
+/// pixel, ignoring the PLTE
+pub(crate) fn png_to_indices(png_data: &[u8]) -> Result<(u32, Vec<u8>), Error> {
+	let mut decoder = png::Decoder::new(png_data);
+	decoder.set_transformations(png::Transformations::IDENTITY);
+	let (info, mut reader) = decoder.read_info()?;
+	if info.color_type != png::ColorType::Indexed {
+		return Err(
+			ErrorKind::PNGInvalid("PNG must be indexed to preserve its indices".into()).into(),
+		);
+	}
+	let mut buf = vec![0; info.buffer_size()];
+	reader.next_frame(&mut buf)?;
+
+	let depth = info.bit_depth as usize;
+	let mask = ((1u16 << depth) - 1) as u8;
+	let mut indices = vec![];
+	for row in buf.chunks(info.line_size) {
+		for x in 0..info.width as usize {
+			let bit = x * depth;
+			let shift = 8 - depth - bit % 8;
+			indices.push(row[bit / 8] >> shift & mask);
+		}
+	}
+	Ok((info.width, indices))
+}
@@ -0,0 +1,254 @@
+// Copyright 2020 Revcore Technologies Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Colour quantization, for reducing truecolor image data down
+//! to a palette VERA can hold. Colours are split via median cut in
+//! L*a*b* space, then refined with a few rounds of k-means
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::color::{lab_distance, lab_to_rgb, rgb_to_lab};
+use crate::VeraPaletteEntry;
+
+/// Number of k-means refinement passes after the median cut
+const KMEANS_ITERATIONS: usize = 4;
+
+/// Report on the error introduced by quantizing an image
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct VeraQuantizeReport {
+	/// Number of distinct colours in the source image
+	pub source_colors: usize,
+	/// Number of palette entries the source was reduced to
+	pub palette_colors: usize,
+	/// Mean error per pixel, as CIE76 delta E
+	pub mean_error: f64,
+	/// Largest error of any single pixel, as CIE76 delta E
+	pub max_error: f64,
+}
+
+impl fmt::Display for VeraQuantizeReport {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"Quantized {} source colors to {} palette entries - mean error (delta E): {:.2}, max error: {:.2}",
+			self.source_colors, self.palette_colors, self.mean_error, self.max_error
+		)
+	}
+}
+
+/// A distinct source colour and the number of pixels using it
+struct HistEntry {
+	lab: [f64; 3],
+	count: usize,
+}
+
+fn histogram(pixels: &[(u8, u8, u8)]) -> Vec<HistEntry> {
+	let mut counts = BTreeMap::new();
+	for p in pixels.iter() {
+		*counts.entry(*p).or_insert(0) += 1;
+	}
+	counts
+		.into_iter()
+		.map(|((r, g, b), count)| HistEntry {
+			lab: rgb_to_lab(r, g, b),
+			count,
+		})
+		.collect()
+}
+
+fn weighted_mean(hist: &[HistEntry], members: &[usize]) -> [f64; 3] {
+	let mut sum = [0.0; 3];
+	let mut total = 0.0;
+	for &m in members.iter() {
+		let w = hist[m].count as f64;
+		for (s, l) in sum.iter_mut().zip(hist[m].lab.iter()) {
+			*s += l * w;
+		}
+		total += w;
+	}
+	if total == 0.0 {
+		return sum;
+	}
+	[sum[0] / total, sum[1] / total, sum[2] / total]
+}
+
+/// Sum of squared distances from the box mean, used to
+/// pick which box is worth splitting next
+fn box_error(hist: &[HistEntry], members: &[usize]) -> f64 {
+	let mean = weighted_mean(hist, members);
+	members.iter().fold(0.0, |acc, &m| {
+		let d = lab_distance(&hist[m].lab, &mean);
+		acc + d * d * hist[m].count as f64
+	})
+}
+
+/// Split a box at the weighted median of its widest axis
+fn split_box(hist: &[HistEntry], mut members: Vec<usize>) -> (Vec<usize>, Vec<usize>) {
+	let mut axis = 0;
+	let mut widest = -1.0;
+	for c in 0..3 {
+		let min = members
+			.iter()
+			.map(|&m| hist[m].lab[c])
+			.fold(f64::MAX, f64::min);
+		let max = members
+			.iter()
+			.map(|&m| hist[m].lab[c])
+			.fold(f64::MIN, f64::max);
+		if max - min > widest {
+			widest = max - min;
+			axis = c;
+		}
+	}
+	members.sort_by(|a, b| {
+		hist[*a].lab[axis]
+			.partial_cmp(&hist[*b].lab[axis])
+			.unwrap_or(std::cmp::Ordering::Equal)
+	});
+	let total: usize = members.iter().map(|&m| hist[m].count).sum();
+	let mut acc = 0;
+	let mut split = 1;
+	for (i, &m) in members.iter().enumerate() {
+		acc += hist[m].count;
+		if acc * 2 >= total {
+			split = i + 1;
+			break;
+		}
+	}
+	// both halves must contain something
+	let split = split.max(1).min(members.len() - 1);
+	let upper = members.split_off(split);
+	(members, upper)
+}
+
+fn nearest(centroids: &[[f64; 3]], lab: &[f64; 3]) -> (usize, f64) {
+	let mut best = (0, f64::MAX);
+	for (i, c) in centroids.iter().enumerate() {
+		let d = lab_distance(c, lab);
+		if d < best.1 {
+			best = (i, d);
+		}
+	}
+	best
+}
+
+/// Reduce the given pixels to at most `max_colors` 12 bit palette entries.
+/// Entries are returned in no particular order and without duplicates
+pub(crate) fn quantize(pixels: &[(u8, u8, u8)], max_colors: usize) -> Vec<VeraPaletteEntry> {
+	let hist = histogram(pixels);
+	if hist.is_empty() || max_colors == 0 {
+		return vec![];
+	}
+
+	// median cut
+	let mut boxes: Vec<Vec<usize>> = vec![(0..hist.len()).collect()];
+	while boxes.len() < max_colors {
+		let candidate = boxes
+			.iter()
+			.enumerate()
+			.filter(|(_, b)| b.len() > 1)
+			.map(|(i, b)| (i, box_error(&hist, b)))
+			.max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+		let index = match candidate {
+			Some((i, _)) => i,
+			None => break,
+		};
+		let (lower, upper) = split_box(&hist, boxes.swap_remove(index));
+		boxes.push(lower);
+		boxes.push(upper);
+	}
+	let mut centroids: Vec<[f64; 3]> = boxes.iter().map(|b| weighted_mean(&hist, b)).collect();
+
+	// k-means refinement
+	for _ in 0..KMEANS_ITERATIONS {
+		let mut members = vec![vec![]; centroids.len()];
+		for (i, h) in hist.iter().enumerate() {
+			members[nearest(&centroids, &h.lab).0].push(i);
+		}
+		centroids = members
+			.iter()
+			.filter(|m| !m.is_empty())
+			.map(|m| weighted_mean(&hist, m))
+			.collect();
+	}
+
+	let mut entries: Vec<VeraPaletteEntry> = vec![];
+	for c in centroids.iter() {
+		let (r, g, b) = lab_to_rgb(*c);
+		let entry = VeraPaletteEntry::from_rgb8_rounded(r, g, b);
+		if !entries.contains(&entry) {
+			entries.push(entry);
+		}
+	}
+	entries
+}
+
+/// Measure how far each pixel is from its closest entry in `entries`
+pub(crate) fn measure_error(
+	pixels: &[(u8, u8, u8)],
+	entries: &[VeraPaletteEntry],
+) -> VeraQuantizeReport {
+	let hist = histogram(pixels);
+	let entry_labs: Vec<[f64; 3]> = entries
+		.iter()
+		.map(|e| {
+			let (r, g, b) = e.to_rgb8();
+			rgb_to_lab(r, g, b)
+		})
+		.collect();
+	let mut report = VeraQuantizeReport {
+		source_colors: hist.len(),
+		palette_colors: entries.len(),
+		..VeraQuantizeReport::default()
+	};
+	if entry_labs.is_empty() || pixels.is_empty() {
+		return report;
+	}
+	let mut total = 0.0;
+	for h in hist.iter() {
+		let (_, d) = nearest(&entry_labs, &h.lab);
+		total += d * h.count as f64;
+		if d > report.max_error {
+			report.max_error = d;
+		}
+	}
+	report.mean_error = total / pixels.len() as f64;
+	report
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn quantize_small_sets() {
+		// fewer colours than entries should come through untouched
+		let pixels = vec![(0, 0, 0), (0xff, 0, 0), (0xff, 0, 0), (0, 0, 0xff)];
+		let entries = quantize(&pixels, 16);
+		assert_eq!(entries.len(), 3);
+		assert!(entries.contains(&VeraPaletteEntry { r: 0xf, g: 0, b: 0 }));
+		let report = measure_error(&pixels, &entries);
+		assert_eq!(report.source_colors, 3);
+		assert!(report.max_error < 0.01);
+
+		// a grey ramp squeezed into 4 entries
+		let pixels: Vec<(u8, u8, u8)> = (0..=255u8).map(|v| (v, v, v)).collect();
+		let entries = quantize(&pixels, 4);
+		assert_eq!(entries.len(), 4);
+		let report = measure_error(&pixels, &entries);
+		assert!(report.mean_error > 0.0);
+		assert!(report.max_error < 25.0);
+	}
+}
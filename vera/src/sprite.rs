@@ -150,6 +150,13 @@ impl<'a> Assemblable for VeraSprite<'a> {
 			));
 			retval.add_meta(format!("Frame {} pal offset - {}", i, f.pal_offset));
 		}
+		// frames culled as flipped or recoloured copies are drawn with the
+		// sprite's flip bits and palette offset
+		for (i, r) in imageset.source_frames.iter().enumerate() {
+			if r.h_flip || r.v_flip || r.pal_offset.is_some() {
+				retval.add_meta(format!("Source frame {} is {}", i, r));
+			}
+		}
 		let imageset_asm = imageset.assemble()?;
 		retval.add_prim(imageset_asm);
 		Ok(retval)
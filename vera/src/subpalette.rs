@@ -0,0 +1,134 @@
+// Copyright 2020 Revcore Technologies Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Packing of the colours used by 4bpp and 2bpp imagesets into
+//! a palette of 16 entry sub-palettes, one per palette offset
+
+use std::cmp::Reverse;
+use std::collections::BTreeSet;
+
+use crate::{Error, ErrorKind};
+use crate::{VeraImageSet, VeraPalette, VeraPaletteEntry, VeraPixelDepth};
+
+/// Number of sub-palettes (palette offsets) available
+const MAX_SUB_PALETTES: usize = 16;
+
+/// Build a palette made up of the fewest 16 entry sub-palettes needed so that
+/// every frame in the given imagesets fits entirely within one of them, then
+/// format the imagesets against it at the given depth (4bpp or 2bpp).
+/// Frames are placed largest first into the sub-palette they add the fewest
+/// new colours to, so the result is usually but not always minimal.
+/// If any frame has transparent pixels, the first entry of every sub-palette
+/// is left black and unused, as it's drawn transparent
+pub fn pack_sub_palettes(
+	id: &str,
+	imagesets: &mut [VeraImageSet],
+	depth: VeraPixelDepth,
+) -> Result<VeraPalette, Error> {
+	let transparent = imagesets
+		.iter()
+		.flat_map(|s| s.frame_data.iter())
+		.any(|f| f.has_transparency());
+	let reserved = transparent as usize;
+	let capacity = match depth {
+		VeraPixelDepth::BPP4 => 16 - reserved,
+		VeraPixelDepth::BPP2 => 4 - reserved,
+		_ => {
+			let msg = format!(
+				"Sub-palettes can only be packed at 4bpp or 2bpp, not {}",
+				depth
+			);
+			return Err(ErrorKind::UnexpectedDepthError(msg).into());
+		}
+	};
+
+	// colours used by each frame, as (imageset, frame, colours)
+	let mut frames = vec![];
+	for (i, set) in imagesets.iter().enumerate() {
+		if set.frame_data.is_empty() {
+			return Err(ErrorKind::ImageSetEmpty(set.id.clone()).into());
+		}
+		for (j, frame) in set.frame_data.iter().enumerate() {
+			let colors: BTreeSet<VeraPaletteEntry> = frame
+				.data
+				.iter()
+				.filter(|p| !p.is_transparent)
+				.map(|p| VeraPaletteEntry::new(p.r, p.g, p.b))
+				.collect();
+			if colors.len() > capacity {
+				return Err(ErrorKind::SubPaletteTooManyColors(
+					frame.id.clone(),
+					colors.len(),
+					capacity,
+				)
+				.into());
+			}
+			frames.push((i, j, colors));
+		}
+	}
+	frames.sort_by_key(|f| Reverse(f.2.len()));
+
+	let mut sub_palettes: Vec<BTreeSet<VeraPaletteEntry>> = vec![];
+	let mut placements = vec![];
+	for (i, j, colors) in frames.iter() {
+		let best = sub_palettes
+			.iter()
+			.enumerate()
+			.map(|(s, sub)| (s, colors.difference(sub).count(), sub.len()))
+			.filter(|(_, added, len)| len + added <= capacity)
+			.min_by_key(|(_, added, _)| *added);
+		let s = match best {
+			Some((s, _, _)) => s,
+			None => {
+				sub_palettes.push(BTreeSet::new());
+				sub_palettes.len() - 1
+			}
+		};
+		sub_palettes[s].extend(colors.iter().cloned());
+		placements.push((*i, *j, s));
+	}
+	if sub_palettes.len() > MAX_SUB_PALETTES {
+		return Err(ErrorKind::SubPaletteOverflow(sub_palettes.len()).into());
+	}
+	info!(
+		"Packed {} frames into {} sub-palettes",
+		frames.len(),
+		sub_palettes.len()
+	);
+
+	let mut palette = VeraPalette::blank(id);
+	for (s, colors) in sub_palettes.iter().enumerate() {
+		let mut sub_palette = VeraPalette::blank(id);
+		if transparent {
+			sub_palette.add_entry(true, 0, 0, 0)?;
+		}
+		for c in colors.iter() {
+			let (r, g, b) = c.to_rgb8();
+			sub_palette.add_entry(true, r, g, b)?;
+		}
+		palette.import_at(s * 16, &sub_palette, false)?;
+	}
+
+	for (s, set) in imagesets.iter_mut().enumerate() {
+		let offsets: Vec<usize> = placements.iter().filter(|(i, _, _)| *i == s).fold(
+			vec![0; set.frame_data.len()],
+			|mut acc, (_, j, sub)| {
+				acc[*j] = sub * 16;
+				acc
+			},
+		);
+		set.format_indices_at_offsets(&palette, depth, &offsets)?;
+	}
+	Ok(palette)
+}
@@ -17,7 +17,7 @@
 use crate::png_to_frames;
 use crate::{Assemblable, AssembledPrimitive};
 use crate::{Error, ErrorKind};
-use crate::{VeraImageSet, VeraPalette, VeraPixelDepth};
+use crate::{VeraImageSet, VeraImageSetLoadConfig, VeraPalette, VeraPixelDepth};
 
 use std::collections::BTreeMap;
 use std::fmt;
@@ -264,6 +264,10 @@ pub struct VeraTileMap {
 	/// Also going to keep a map of tile hashes to indices/pal offset/hflip/vflip when initialized
 	/// from an imageset
 	imageset_entries: BTreeMap<u64, (usize, u8, u8, u8)>,
+
+	/// Whether the imageset's palette offsets have changed in a way
+	/// the tile entries couldn't follow
+	stale: bool,
 }
 
 impl fmt::Display for VeraTileMap {
@@ -321,18 +325,54 @@ impl VeraTileMap {
 			tiles: vec![],
 			imageset_entries: BTreeMap::new(),
 			imageset_id: imageset.id.clone(),
+			stale: false,
 		};
-		// Tile Indices init here
+		// Tile Indices init here, preferring the first unflipped match
 		for (i, f) in imageset.frame_data.iter().enumerate() {
 			res.imageset_entries
-				.insert(f.calc_hash(), (i, f.pal_offset, 0, 0));
-			// And possible h/v flip iterations as well
-			res.imageset_entries
-				.insert(f.flip_hashes[0], (i, f.pal_offset, 1, 0));
-			res.imageset_entries
-				.insert(f.flip_hashes[1], (i, f.pal_offset, 0, 1));
-			res.imageset_entries
-				.insert(f.flip_hashes[2], (i, f.pal_offset, 1, 1));
+				.entry(f.calc_hash())
+				.or_insert((i, f.pal_offset, 0, 0));
+		}
+		// And possible h/v flip iterations as well, which text modes can't show
+		let text_mode =
+			mode == VeraTileMapMode::TextBPP1_16 || mode == VeraTileMapMode::TextBPP1_256;
+		if !text_mode {
+			for (i, f) in imageset.frame_data.iter().enumerate() {
+				for (hash, h_flip, v_flip) in [
+					(f.flip_hashes[0], 1, 0),
+					(f.flip_hashes[1], 0, 1),
+					(f.flip_hashes[2], 1, 1),
+				]
+				.iter()
+				{
+					res.imageset_entries.entry(*hash).or_insert((
+						i,
+						f.pal_offset,
+						*h_flip,
+						*v_flip,
+					));
+				}
+			}
+			// Then frames culled as recoloured copies, drawn at their own offset
+			for r in imageset.recoloured_frames.iter() {
+				let f = &r.frame;
+				let pal_offset = f.pal_offset.unwrap_or(0);
+				for (hash, h_flip, v_flip) in [
+					(r.hashes[0], f.h_flip, f.v_flip),
+					(r.hashes[1], !f.h_flip, f.v_flip),
+					(r.hashes[2], f.h_flip, !f.v_flip),
+					(r.hashes[3], !f.h_flip, !f.v_flip),
+				]
+				.iter()
+				{
+					res.imageset_entries.entry(*hash).or_insert((
+						f.frame,
+						pal_offset,
+						*h_flip as u8,
+						*v_flip as u8,
+					));
+				}
+			}
 		}
 		Ok(res)
 	}
@@ -398,6 +438,30 @@ impl VeraTileMap {
 		pane_start_y: u32,
 		clear_index: u8,
 	) -> Result<(), Error> {
+		self.load_from_png_with_config(
+			png_data,
+			palette,
+			pane_start_x,
+			pane_start_y,
+			clear_index,
+			&VeraImageSetLoadConfig::default(),
+		)
+	}
+
+	/// As above, but with tiles sliced using the layout in `config`,
+	/// whose frame limit must leave whole rows of tiles
+	pub fn load_from_png_with_config(
+		&mut self,
+		png_data: Vec<u8>,
+		palette: Option<&VeraPalette>,
+		pane_start_x: u32,
+		pane_start_y: u32,
+		clear_index: u8,
+		config: &VeraImageSetLoadConfig,
+	) -> Result<(), Error> {
+		if self.stale {
+			return Err(ErrorKind::TileMapStale(self.id.clone()).into());
+		}
 		self.tiles = vec![];
 		// load as we do for an imageset
 		let mut frames = vec![];
@@ -406,8 +470,12 @@ impl VeraTileMap {
 			self.tile_width.val_as_u32(),
 			self.tile_height.val_as_u32(),
 			png_data,
+			config,
 			&mut frames,
 		)?;
+		if frames.len() != (frames_per_row * frames_per_col) as usize {
+			return Err(ErrorKind::TileMapPartialRow(frames.len(), frames_per_row).into());
+		}
 		if frames_per_row > self.map_width.val_as_u32()
 			|| frames_per_col > self.map_height.val_as_u32()
 		{
@@ -432,12 +500,12 @@ impl VeraTileMap {
 		self.pane_start_x = pane_start_x;
 		self.pane_start_y = pane_start_y;
 
-		for (i, mut f) in frames.iter_mut().enumerate() {
+		for (i, f) in frames.iter_mut().enumerate() {
 			if self.mode == VeraTileMapMode::TextBPP1_16
 				|| self.mode == VeraTileMapMode::TextBPP1_256
 			{
 				f.depth = VeraPixelDepth::BPP1;
-				for mut p in f.data.iter_mut() {
+				for p in f.data.iter_mut() {
 					// As with imagesets, remove colour data, just use
 					// on/off
 					p.is_1bpp = true;
@@ -517,6 +585,46 @@ impl VeraTileMap {
 	pub fn get_tiles(&self) -> &Vec<VeraTileMapEntry> {
 		&self.tiles
 	}
+
+	/// Whether the map must be initialized and loaded again before
+	/// it can be assembled
+	pub fn is_stale(&self) -> bool {
+		self.stale
+	}
+
+	/// Mark the map as no longer matching its imageset's palette offsets
+	pub fn mark_stale(&mut self) {
+		self.stale = true;
+	}
+
+	/// Follow the palette offsets of the imageset's frames after it's
+	/// been reindexed against an edited palette. If a tile refers to a
+	/// frame the imageset no longer has, or to a culled recoloured copy,
+	/// nothing is changed, the map is marked stale and false is returned
+	pub fn update_pal_offsets(&mut self, imageset: &VeraImageSet) -> bool {
+		let frame_count = imageset.frame_data.len();
+		let missing_entry = self
+			.imageset_entries
+			.values()
+			.any(|(i, _, _, _)| *i >= frame_count);
+		let missing_tile = self.tiles.iter().any(|t| match t {
+			VeraTileMapEntry::Tile234(i, _, _, _) => *i as usize >= frame_count,
+			_ => false,
+		});
+		if !imageset.recoloured_frames.is_empty() || missing_entry || missing_tile {
+			self.stale = true;
+			return false;
+		}
+		for (i, pal_offset, _, _) in self.imageset_entries.values_mut() {
+			*pal_offset = imageset.frame_data[*i].pal_offset;
+		}
+		for t in self.tiles.iter_mut() {
+			if let VeraTileMapEntry::Tile234(i, pal_offset, _, _) = t {
+				*pal_offset = imageset.frame_data[*i as usize].pal_offset;
+			}
+		}
+		true
+	}
 }
 
 impl Assemblable for VeraTileMap {
@@ -533,6 +641,9 @@ impl Assemblable for VeraTileMap {
 	}
 
 	fn assemble(&self) -> Result<AssembledPrimitive, Error> {
+		if self.stale {
+			return Err(ErrorKind::TileMapStale(self.id.clone()).into());
+		}
 		if self.tiles.is_empty() {
 			warn!("tilemap is empty: {}", self.id);
 		}
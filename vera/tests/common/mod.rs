@@ -0,0 +1,69 @@
+// Copyright 2020 Revcore Technologies Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Common functions for integration tests
+use aloevera_vera::Error;
+
+/// Encode a png, packing samples below 8 bits most significant first.
+/// 16 bit samples are given as big endian byte pairs
+pub fn encode_test_png(
+	width: u32,
+	height: u32,
+	color: png::ColorType,
+	depth: u8,
+	samples: &[u8],
+	chunks: &[([u8; 4], Vec<u8>)],
+) -> Result<Vec<u8>, Error> {
+	let per_row = samples.len() / height as usize;
+	let mut data = vec![];
+	for row in samples.chunks(per_row) {
+		match depth {
+			8 | 16 => data.extend_from_slice(row),
+			_ => {
+				let per_byte = 8 / depth as usize;
+				for byte in row.chunks(per_byte) {
+					let mut b = 0u8;
+					for (i, s) in byte.iter().enumerate() {
+						b |= s << (8 - depth as usize * (i + 1));
+					}
+					data.push(b);
+				}
+			}
+		}
+	}
+	let mut png_data = vec![];
+	{
+		let mut encoder = png::Encoder::new(&mut png_data, width, height);
+		encoder.set_color(color);
+		encoder.set_depth(png::BitDepth::from_u8(depth).unwrap());
+		let mut writer = encoder.write_header()?;
+		for (name, chunk) in chunks.iter() {
+			writer.write_chunk(*name, chunk)?;
+		}
+		writer.write_image_data(&data)?;
+	}
+	Ok(png_data)
+}
+
+/// Encode RGBA pixels, given row by row, as an 8 bit png
+pub fn rgba_test_png(width: u32, height: u32, pixels: &[[u8; 4]]) -> Result<Vec<u8>, Error> {
+	encode_test_png(
+		width,
+		height,
+		png::ColorType::RGBA,
+		8,
+		&pixels.concat(),
+		&[],
+	)
+}
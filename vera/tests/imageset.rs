@@ -14,11 +14,18 @@
 
 use aloevera_util::init_test_logger;
 use aloevera_vera::Error;
+use std::borrow::Cow;
 
-use aloevera_vera::{AsmFormat, Assemblable};
 use aloevera_vera::{
-	VeraImageSet, VeraImageSetLoadConfig, VeraPalette, VeraPaletteLoadConfig, VeraPixelDepth,
+	pack_sub_palettes, png_to_frames, VeraAnimation, VeraAnimationDirection, VeraAnimationFrame,
+	VeraAnimationLoop, VeraAnimationTable, VeraColorConversion, VeraFrameRef, VeraImageSet,
+	VeraImageSetFormatConfig, VeraImageSetLoadConfig, VeraImageSetTag, VeraPalette,
+	VeraPaletteEntry, VeraPaletteLoadConfig, VeraPixelDepth, VeraTransparency,
 };
+use aloevera_vera::{AsmFormat, Assemblable};
+
+mod common;
+use common::{encode_test_png, rgba_test_png};
 
 #[test]
 fn imageset_failures() -> Result<(), Error> {
@@ -384,3 +391,1206 @@ fn image_flip() -> Result<(), Error> {
 
 	Ok(())
 }
+
+#[test]
+fn imageset_remap_nearest() -> Result<(), Error> {
+	init_test_logger();
+	let test_png = include_bytes!("data/imageset/rgba-16-1-x-1.png");
+	// red in the image is #b10, palette only has shades either side of it
+	let mut palette = VeraPalette::blank("pal");
+	for i in 0..32 {
+		let (r, g, b) = match i {
+			3 => (0xA0, 0x10, 0x00),
+			16 => (0x00, 0x00, 0x00),
+			17 => (0x40, 0x60, 0x20),
+			18 => (0x90, 0x10, 0x00),
+			_ => (0x80, 0x80, 0x80),
+		};
+		palette.add_entry(true, r, g, b)?;
+	}
+
+	let mut set = VeraImageSet::new("set_1", 16, 16);
+	let config = VeraImageSetLoadConfig::default();
+	set.load_from_png(test_png.to_vec(), &config)?;
+	assert!(set.format_indices(&palette, VeraPixelDepth::BPP8).is_err());
+
+	// too far away
+	let format_config = VeraImageSetFormatConfig {
+		remap_max_distance: Some(0.5),
+	};
+	assert!(set
+		.format_indices_with_config(&palette, VeraPixelDepth::BPP8, &format_config)
+		.is_err());
+
+	// 8bpp, so the closest entry in the whole palette
+	let format_config = VeraImageSetFormatConfig {
+		remap_max_distance: Some(20.0),
+	};
+	let reports = set.format_indices_with_config(&palette, VeraPixelDepth::BPP8, &format_config)?;
+	assert_eq!(reports.len(), 1);
+	assert_eq!(reports[0].frame_id, "set_1_0");
+	assert!(reports[0].pixels > 0);
+	let frame = set.frame_at(0)?;
+	assert_eq!(frame.pixel_at_coord(0, 0)?.pal_index, Some(17));
+	assert_eq!(frame.pixel_at_coord(15, 0)?.pal_index, Some(16));
+	assert_eq!(frame.pixel_at_coord(7, 7)?.pal_index, Some(3));
+
+	// 4bpp, remapping has to stay within the frame's range
+	let reports = set.format_indices_with_config(&palette, VeraPixelDepth::BPP4, &format_config)?;
+	assert_eq!(reports.len(), 1);
+	let frame = set.frame_at(0)?;
+	assert_eq!(frame.pal_offset, 16);
+	assert_eq!(frame.pixel_at_coord(0, 0)?.pal_index, Some(1));
+	assert_eq!(frame.pixel_at_coord(15, 0)?.pal_index, Some(0));
+	assert_eq!(frame.pixel_at_coord(7, 7)?.pal_index, Some(2));
+
+	// nothing to remap, nothing to report
+	palette.add_entry(true, 0xB0, 0x10, 0x00)?;
+	let reports = set.format_indices_with_config(&palette, VeraPixelDepth::BPP8, &format_config)?;
+	assert!(reports.is_empty());
+
+	Ok(())
+}
+
+#[test]
+fn imageset_color_conversion() -> Result<(), Error> {
+	init_test_logger();
+	// flat #4e4e4e, which sits a little over halfway between #444 and #555
+	let test_png = include_bytes!("data/imageset/rgb-16-flat-4e.png");
+	let mut palette = VeraPalette::blank("pal");
+	palette.add_entry(true, 0x40, 0x40, 0x40)?;
+	palette.add_entry(true, 0x50, 0x50, 0x50)?;
+
+	let load = |conversion| -> Result<VeraImageSet, Error> {
+		let mut set = VeraImageSet::new("set_1", 16, 16);
+		let config = VeraImageSetLoadConfig {
+			conversion,
+			..VeraImageSetLoadConfig::default()
+		};
+		set.load_from_png(test_png.to_vec(), &config)?;
+		set.format_indices(&palette, VeraPixelDepth::BPP8)?;
+		Ok(set)
+	};
+	let count_upper = |set: &VeraImageSet| -> Result<usize, Error> {
+		Ok(set
+			.frame_at(0)?
+			.data
+			.iter()
+			.filter(|p| p.pal_index == Some(1))
+			.count())
+	};
+
+	// truncation leaves raw values alone
+	let set = load(VeraColorConversion::Truncate)?;
+	assert_eq!(set.frame_at(0)?.pixel_at_coord(0, 0)?.r, 0x4e);
+	assert_eq!(count_upper(&set)?, 0);
+
+	let set = load(VeraColorConversion::Round)?;
+	assert_eq!(set.frame_at(0)?.pixel_at_coord(0, 0)?.r, 0x55);
+	assert_eq!(count_upper(&set)?, 256);
+
+	// dithers should mix the two levels in roughly the right proportion
+	let set = load(VeraColorConversion::Bayer)?;
+	assert_eq!(count_upper(&set)?, 144);
+	let frame = set.frame_at(0)?;
+	assert_ne!(
+		frame.pixel_at_coord(0, 0)?.pal_index,
+		frame.pixel_at_coord(1, 0)?.pal_index
+	);
+
+	let set = load(VeraColorConversion::FloydSteinberg)?;
+	let upper = count_upper(&set)?;
+	assert!(upper > 140 && upper < 165);
+
+	// dithering runs across the whole sheet, so sliced frames match it
+	for conversion in [
+		VeraColorConversion::Bayer,
+		VeraColorConversion::FloydSteinberg,
+	]
+	.iter()
+	{
+		let whole = load(*conversion)?;
+		let mut sliced = VeraImageSet::new("set_2", 4, 4);
+		let config = VeraImageSetLoadConfig {
+			conversion: *conversion,
+			cull_duplicates: false,
+			..VeraImageSetLoadConfig::default()
+		};
+		sliced.load_from_png(test_png.to_vec(), &config)?;
+		for y in 0..16 {
+			for x in 0..16 {
+				let frame = sliced.frame_at_coord(x / 4, y / 4)?;
+				assert_eq!(
+					frame.pixel_at_coord(x % 4, y % 4)?.r,
+					whole.frame_at(0)?.pixel_at_coord(x, y)?.r
+				);
+			}
+		}
+	}
+
+	// transparency is decided before conversion. Left half is the #eee key,
+	// right half #f0f0f0, which rounds onto the key
+	let halves = |left: u8, right: u8| {
+		let data: Vec<[u8; 4]> = (0..64)
+			.map(|i| match i % 8 < 4 {
+				true => [left, left, left, 0xff],
+				false => [right, right, right, 0xff],
+			})
+			.collect();
+		rgba_test_png(8, 8, &data)
+	};
+	let load_keyed = |png_data, conversion| -> Result<VeraImageSet, Error> {
+		let mut set = VeraImageSet::new("set_3", 8, 8);
+		let config = VeraImageSetLoadConfig {
+			conversion,
+			transparency: VeraTransparency::Key(0xee, 0xee, 0xee),
+			..VeraImageSetLoadConfig::default()
+		};
+		set.load_from_png(png_data, &config)?;
+		Ok(set)
+	};
+	for conversion in [VeraColorConversion::Truncate, VeraColorConversion::Round].iter() {
+		let set = load_keyed(halves(0xee, 0xf0)?, *conversion)?;
+		let frame = set.frame_at(0)?;
+		assert_eq!(frame.data.iter().filter(|p| p.is_transparent).count(), 32);
+	}
+
+	// hidden pixels don't spread dithering error, so the visible half
+	// dithers just as it would on its own. #e8e8e8 is keyed out
+	let set = load_keyed(halves(0xe8, 0xf8)?, VeraColorConversion::FloydSteinberg)?;
+	let frame = set.frame_at(0)?;
+	assert_eq!(frame.data.iter().filter(|p| p.is_transparent).count(), 32);
+	let mut alone = VeraImageSet::new("set_4", 4, 8);
+	let config = VeraImageSetLoadConfig {
+		conversion: VeraColorConversion::FloydSteinberg,
+		..VeraImageSetLoadConfig::default()
+	};
+	alone.load_from_png(
+		rgba_test_png(4, 8, &[[0xf8, 0xf8, 0xf8, 0xff]; 32])?,
+		&config,
+	)?;
+	for y in 0..8 {
+		for x in 0..4 {
+			assert_eq!(
+				frame.pixel_at_coord(x + 4, y)?.r,
+				alone.frame_at(0)?.pixel_at_coord(x, y)?.r
+			);
+		}
+	}
+
+	Ok(())
+}
+
+#[test]
+fn imageset_pack_sub_palettes() -> Result<(), Error> {
+	init_test_logger();
+	// 4 frames, the first two sharing enough colors to fit in a single
+	// sub-palette, the last using a subset of the third's colors
+	let test_png = include_bytes!("data/imageset/rgb-8-4-x-1-subpalettes.png");
+	let config = VeraImageSetLoadConfig::default();
+	let mut sets = vec![
+		VeraImageSet::new("set_1", 8, 8),
+		VeraImageSet::new("set_2", 8, 8),
+	];
+	for s in sets.iter_mut() {
+		s.load_from_png(test_png.to_vec(), &config)?;
+	}
+
+	let palette = pack_sub_palettes("pal", &mut sets, VeraPixelDepth::BPP4)?;
+	println!("{}", palette);
+	assert_eq!(palette.len(), 30);
+	assert!(palette.is_slot_occupied(11));
+	assert!(!palette.is_slot_occupied(12));
+	assert!(palette.is_slot_occupied(16));
+	assert!(palette.is_slot_occupied(29));
+
+	for s in sets.iter() {
+		assert!(s.formatted);
+		assert_eq!(s.depth, Some(VeraPixelDepth::BPP4));
+		assert_eq!(s.frame_at(0)?.pal_offset, 16);
+		assert_eq!(s.frame_at(1)?.pal_offset, 16);
+		assert_eq!(s.frame_at(2)?.pal_offset, 0);
+		assert_eq!(s.frame_at(3)?.pal_offset, 0);
+		// each pixel should point at its own color
+		for f in s.frame_data.iter() {
+			for p in f.data.iter() {
+				let index = p.pal_index.unwrap() as usize + f.pal_offset as usize;
+				let (_, indices) = palette.all_indices_of_rgb(p.r, p.g, p.b);
+				assert!(indices.contains(&index));
+			}
+		}
+		s.assemble()?;
+	}
+
+	// 10 colors won't fit in a 2bpp sub-palette
+	assert!(pack_sub_palettes("pal", &mut sets, VeraPixelDepth::BPP2).is_err());
+	assert!(pack_sub_palettes("pal", &mut sets, VeraPixelDepth::BPP8).is_err());
+
+	Ok(())
+}
+
+#[test]
+fn imageset_format_worst_case_palette() -> Result<(), Error> {
+	init_test_logger();
+	// 16 colors, each of the first 15 repeated in every palette row but the
+	// last color only found in the final row, so every combination of the
+	// repeated colors has to be ruled out before the answer is reached
+	let test_png = include_bytes!("data/imageset/rgb-4-16-colors.png");
+	let color = |k: u8| (k * 17, 255 - k * 17, 0x33);
+	let mut palette = VeraPalette::blank("pal");
+	for row in 0..16 {
+		for k in 0..16 {
+			let (r, g, b) = match k == 15 && row != 15 {
+				true => (0, 0, 0),
+				false => color(k),
+			};
+			palette.add_entry(true, r, g, b)?;
+		}
+	}
+
+	let mut set = VeraImageSet::new("set_1", 4, 4);
+	let config = VeraImageSetLoadConfig::default();
+	set.load_from_png(test_png.to_vec(), &config)?;
+	set.format_indices(&palette, VeraPixelDepth::BPP4)?;
+
+	let frame = set.frame_at(0)?;
+	assert_eq!(frame.pal_offset, 240);
+	for (i, p) in frame.data.iter().enumerate() {
+		assert_eq!(p.pal_index, Some(i as u8));
+	}
+
+	Ok(())
+}
+
+#[test]
+fn imageset_palette_edits() -> Result<(), Error> {
+	init_test_logger();
+	let test_png = include_bytes!("data/imageset/indexed-8-1-x-8-4bpp.png");
+	let pal_config = VeraPaletteLoadConfig {
+		direct_load: true,
+		include_defaults: false,
+		sort: false,
+		..VeraPaletteLoadConfig::default()
+	};
+	let mut palette = VeraPalette::derive_from_png("pal", test_png.to_vec(), &pal_config)?;
+	let mut set = VeraImageSet::new("set_1", 8, 8);
+	let config = VeraImageSetLoadConfig::default();
+	set.load_from_png(test_png.to_vec(), &config)?;
+	set.format_indices(&palette, VeraPixelDepth::BPP4)?;
+	assert_eq!(set.palette_id, Some("pal".to_owned()));
+
+	// every pixel should still display its own colour
+	let check = |set: &VeraImageSet, palette: &VeraPalette| -> Result<(), Error> {
+		for f in set.frame_data.iter() {
+			for p in f.data.iter() {
+				let index = f.pal_offset as usize + p.pal_index.unwrap() as usize;
+				assert_eq!(
+					palette.value_at_index(index)?,
+					VeraPaletteEntry::new(p.r, p.g, p.b)
+				);
+			}
+		}
+		Ok(())
+	};
+
+	let map = palette.swap_entries(1, 4)?;
+	assert!(set.reindex(&map));
+	check(&set, &palette)?;
+	assert_eq!(set.frame_at(0)?.pixel_at_coord(3, 3)?.pal_index, Some(1));
+
+	// inserting at the start shifts the second frame's colours across
+	// a sub-palette boundary
+	let map = palette.insert_entry(0, 0xff, 0xff, 0xff)?;
+	assert!(!set.reindex(&map));
+	assert!(!set.formatted);
+
+	let mut palette = VeraPalette::derive_from_png("pal", test_png.to_vec(), &pal_config)?;
+	set.format_indices(&palette, VeraPixelDepth::BPP4)?;
+	let map = palette.move_entry(2, 0)?;
+	assert!(set.reindex(&map));
+	check(&set, &palette)?;
+
+	// removing a colour in use can't be followed
+	let used = set.frame_at(0)?.pal_offset as usize
+		+ set.frame_at(0)?.pixel_at_coord(3, 3)?.pal_index.unwrap() as usize;
+	let map = palette.remove_entry(used)?;
+	assert!(!set.reindex(&map));
+	assert!(!set.formatted);
+	assert!(palette.remove_entry(palette.len()).is_err());
+	Ok(())
+}
+
+/// Two 8x8 RGBA frames side by side. The left half of the first is fully
+/// transparent over junk colours, the rest of it red. The second is all
+/// transparent over green
+fn transparent_test_png() -> Result<Vec<u8>, Error> {
+	let mut data = vec![];
+	for y in 0..8u8 {
+		for x in 0..16u8 {
+			let px = match x {
+				0..=3 => [x * 40, y * 30, 0x80, 0],
+				4..=7 => [0xff, 0, 0, 0xff],
+				_ => [0, 0xff, 0, 0],
+			};
+			data.push(px);
+		}
+	}
+	rgba_test_png(16, 8, &data)
+}
+
+#[test]
+fn imageset_transparency() -> Result<(), Error> {
+	init_test_logger();
+	let test_png = transparent_test_png()?;
+	let mut palette = VeraPalette::blank("pal");
+	palette.add_entry(true, 0xff, 0, 0)?;
+	palette.add_entry(true, 0, 0xff, 0)?;
+	palette.add_entry(true, 0xff, 0, 0)?;
+
+	let mut set = VeraImageSet::new("set", 8, 8);
+	let config = VeraImageSetLoadConfig {
+		transparency: VeraTransparency::Alpha,
+		..VeraImageSetLoadConfig::default()
+	};
+	set.load_from_png(test_png.clone(), &config)?;
+	set.format_indices(&palette, VeraPixelDepth::BPP4)?;
+	assert_eq!(set.frame_data.len(), 2);
+	let frame = set.frame_at(0)?;
+	assert!(frame.data[0].is_transparent);
+	assert_eq!(frame.data[0].pal_index, Some(0));
+	// red at index 0 would be drawn transparent, so the second red is used
+	assert_eq!(frame.data[4].pal_index, Some(2));
+	assert!(set.frame_at(1)?.data.iter().all(|p| p.pal_index == Some(0)));
+
+	// nothing to remap into once index 0 is reserved for transparency
+	let format_config = VeraImageSetFormatConfig {
+		remap_max_distance: Some(20.0),
+	};
+	let mut empty_set = set.clone();
+	assert!(empty_set
+		.format_indices_with_config(
+			&VeraPalette::blank("empty"),
+			VeraPixelDepth::BPP8,
+			&format_config
+		)
+		.is_err());
+
+	// transparent pixels take no space in packed sub-palettes
+	let mut sets = vec![set.clone()];
+	let packed = pack_sub_palettes("packed", &mut sets, VeraPixelDepth::BPP4)?;
+	assert_eq!(packed.len(), 2);
+	assert_eq!(packed.value_at_index(1)?, VeraPaletteEntry::new(0xff, 0, 0));
+	assert_eq!(sets[0].frame_at(0)?.data[4].pal_index, Some(1));
+
+	// key colour only, alpha ignored
+	let mut set = VeraImageSet::new("set", 8, 8);
+	let config = VeraImageSetLoadConfig {
+		transparency: VeraTransparency::Key(0, 0xff, 0),
+		..VeraImageSetLoadConfig::default()
+	};
+	set.load_from_png(test_png, &config)?;
+	assert!(!set.frame_at(0)?.has_transparency());
+	assert!(set.frame_at(1)?.data.iter().all(|p| p.is_transparent));
+	Ok(())
+}
+
+/// Load a png as a single frame, returning the RGB of each pixel and
+/// whether it was loaded as transparent
+fn decode_test_png(png_data: Vec<u8>) -> Result<Vec<(u8, u8, u8, bool)>, Error> {
+	let config = VeraImageSetLoadConfig {
+		transparency: VeraTransparency::Alpha,
+		..VeraImageSetLoadConfig::default()
+	};
+	let mut frames = vec![];
+	png_to_frames("t", 4, 2, png_data, &config, &mut frames)?;
+	Ok(frames[0]
+		.data
+		.iter()
+		.map(|p| (p.r, p.g, p.b, p.is_transparent))
+		.collect())
+}
+
+#[test]
+fn imageset_png_color_types() -> Result<(), Error> {
+	init_test_logger();
+	for depth in [1u8, 2, 4, 8].iter() {
+		let max = (1u16 << depth) - 1;
+		let values: Vec<u8> = (0..8u16).map(|i| (i * 37 % (max + 1)) as u8).collect();
+
+		// grayscale, scaled up to 8 bits
+		let png_data = encode_test_png(4, 2, png::ColorType::Grayscale, *depth, &values, &[])?;
+		let expected: Vec<(u8, u8, u8, bool)> = values
+			.iter()
+			.map(|v| {
+				let g = (*v as u16 * 255 / max) as u8;
+				(g, g, g, false)
+			})
+			.collect();
+		assert_eq!(decode_test_png(png_data)?, expected, "{} bit gray", depth);
+
+		// indexed, expanded through the PLTE, with index 0 transparent
+		let plte: Vec<u8> = (0..=max)
+			.flat_map(|k| vec![k as u8, 255 - k as u8, (k / 2) as u8])
+			.collect();
+		let chunks = [(*b"PLTE", plte), (*b"tRNS", vec![0])];
+		let png_data = encode_test_png(4, 2, png::ColorType::Indexed, *depth, &values, &chunks)?;
+		let expected: Vec<(u8, u8, u8, bool)> = values
+			.iter()
+			.map(|v| match v {
+				0 => (0, 0, 0, true),
+				_ => (*v, 255 - *v, *v / 2, false),
+			})
+			.collect();
+		assert_eq!(
+			decode_test_png(png_data)?,
+			expected,
+			"{} bit indexed",
+			depth
+		);
+	}
+
+	// grayscale with alpha
+	let samples: Vec<u8> = (0..8u8).flat_map(|i| vec![i * 30, i % 2 * 255]).collect();
+	let png_data = encode_test_png(4, 2, png::ColorType::GrayscaleAlpha, 8, &samples, &[])?;
+	let expected: Vec<(u8, u8, u8, bool)> = (0..8u8)
+		.map(|i| match i % 2 {
+			0 => (0, 0, 0, true),
+			_ => (i * 30, i * 30, i * 30, false),
+		})
+		.collect();
+	assert_eq!(decode_test_png(png_data)?, expected);
+
+	// rgb and rgba
+	let samples: Vec<u8> = (0..8u8).flat_map(|i| vec![i, i * 2, i * 3]).collect();
+	let png_data = encode_test_png(4, 2, png::ColorType::RGB, 8, &samples, &[])?;
+	let expected: Vec<(u8, u8, u8, bool)> = (0..8u8).map(|i| (i, i * 2, i * 3, false)).collect();
+	assert_eq!(decode_test_png(png_data)?, expected);
+	let samples: Vec<u8> = (0..8u8).flat_map(|i| vec![i, i * 2, i * 3, 255]).collect();
+	let png_data = encode_test_png(4, 2, png::ColorType::RGBA, 8, &samples, &[])?;
+	assert_eq!(decode_test_png(png_data)?, expected);
+	Ok(())
+}
+
+#[test]
+fn imageset_preserve_indices() -> Result<(), Error> {
+	init_test_logger();
+	// entries 1 and 2 are the same colour, the left frame uses 2,
+	// the right one 1
+	let plte = vec![0, 0, 0, 0xff, 0, 0, 0xff, 0, 0, 0, 0xff, 0];
+	let values = vec![0, 2, 3, 1, 2, 0, 1, 3];
+	let png_data = encode_test_png(
+		4,
+		2,
+		png::ColorType::Indexed,
+		4,
+		&values,
+		&[(*b"PLTE", plte)],
+	)?;
+	let mut palette = VeraPalette::blank("pal");
+	for i in 0..32 {
+		palette.add_entry(true, i * 8, i * 8, i * 8)?;
+	}
+	let mut set = VeraImageSet::new("set", 2, 2);
+	set.load_from_indexed_png(png_data.clone(), &VeraImageSetLoadConfig::default())?;
+	assert!(set.source_indices);
+	assert_eq!(set.frame_data.len(), 2);
+	assert!(set
+		.format_source_indices(&palette, VeraPixelDepth::BPP2, 8)
+		.is_err());
+	set.format_source_indices(&palette, VeraPixelDepth::BPP2, 16)?;
+	let indices = |set: &VeraImageSet, i| -> Result<Vec<Option<u8>>, Error> {
+		Ok(set.frame_at(i)?.data.iter().map(|p| p.pal_index).collect())
+	};
+	assert_eq!(indices(&set, 0)?, vec![Some(0), Some(2), Some(2), Some(0)]);
+	assert_eq!(indices(&set, 1)?, vec![Some(3), Some(1), Some(1), Some(3)]);
+	assert_eq!(set.frame_at(1)?.pal_offset, 16);
+	assert_eq!(set.frame_at(1)?.data[1].r, 0xff);
+
+	// the same colours but different indices aren't culled as duplicates
+	let plte = vec![0, 0, 0, 0xff, 0, 0, 0xff, 0, 0, 0, 0xff, 0];
+	let values = vec![1, 2, 1, 1, 1, 2, 2, 1];
+	let dup_data = encode_test_png(
+		4,
+		2,
+		png::ColorType::Indexed,
+		4,
+		&values,
+		&[(*b"PLTE", plte)],
+	)?;
+	set.load_from_indexed_png(dup_data, &VeraImageSetLoadConfig::default())?;
+	assert_eq!(set.frame_data.len(), 2);
+
+	// formatting by colour drops the source indices
+	let mut set = VeraImageSet::new("set", 2, 2);
+	set.load_from_indexed_png(png_data, &VeraImageSetLoadConfig::default())?;
+	let mut rgb_palette = VeraPalette::blank("rgb");
+	for (r, g, b) in [(0, 0, 0), (0xff, 0, 0), (0, 0xff, 0)].iter() {
+		rgb_palette.add_entry(true, *r, *g, *b)?;
+	}
+	set.format_indices(&rgb_palette, VeraPixelDepth::BPP2)?;
+	assert!(!set.source_indices);
+	assert!(set
+		.format_source_indices(&palette, VeraPixelDepth::BPP2, 0)
+		.is_err());
+
+	// indices past the depth fail
+	let mut set = VeraImageSet::new("set", 2, 2);
+	let mut plte = vec![];
+	for i in 0..16u8 {
+		plte.extend_from_slice(&[i * 16, 0, 0]);
+	}
+	let values = vec![0, 1, 2, 3, 4, 5, 6, 15];
+	let png_data = encode_test_png(
+		4,
+		2,
+		png::ColorType::Indexed,
+		4,
+		&values,
+		&[(*b"PLTE", plte)],
+	)?;
+	set.load_from_indexed_png(png_data, &VeraImageSetLoadConfig::default())?;
+	assert!(set
+		.format_source_indices(&palette, VeraPixelDepth::BPP2, 0)
+		.is_err());
+	set.format_source_indices(&palette, VeraPixelDepth::BPP4, 0)?;
+
+	// as do indices past the end of the palette at the offset
+	let mut short_palette = VeraPalette::blank("short");
+	for i in 0..20 {
+		short_palette.add_entry(true, i * 8, 0, 0)?;
+	}
+	assert!(set
+		.format_source_indices(&short_palette, VeraPixelDepth::BPP4, 16)
+		.is_err());
+	set.format_source_indices(&short_palette, VeraPixelDepth::BPP4, 0)?;
+	Ok(())
+}
+
+#[test]
+fn imageset_png_16_bit() -> Result<(), Error> {
+	init_test_logger();
+	// samples are rounded rather than truncated to 8 bits
+	let rgb: Vec<u8> = (0..8)
+		.flat_map(|i| match i % 2 {
+			0 => vec![0x12, 0xc0, 0x12, 0x34, 0xff, 0xff],
+			_ => vec![0xf0, 0xf0, 0x00, 0x00, 0x00, 0x00],
+		})
+		.collect();
+	let png_data = encode_test_png(4, 2, png::ColorType::RGB, 16, &rgb, &[])?;
+	let expected: Vec<(u8, u8, u8, bool)> = (0..8)
+		.map(|i| match i % 2 {
+			0 => (0x13, 0x12, 0xff, false),
+			_ => (0xf0, 0, 0, false),
+		})
+		.collect();
+	assert_eq!(decode_test_png(png_data.clone())?, expected);
+
+	let config = VeraPaletteLoadConfig {
+		include_defaults: false,
+		..VeraPaletteLoadConfig::default()
+	};
+	let palette = VeraPalette::derive_from_png("pal", png_data, &config)?;
+	assert_eq!(palette.len(), 2);
+	assert!(palette.index_of_rgb(0x13, 0x12, 0xff).is_some());
+
+	let gray: Vec<u8> = (0..8).flat_map(|_| vec![0x12, 0xc0]).collect();
+	let png_data = encode_test_png(4, 2, png::ColorType::Grayscale, 16, &gray, &[])?;
+	assert!(decode_test_png(png_data)?
+		.iter()
+		.all(|p| *p == (0x13, 0x13, 0x13, false)));
+
+	let rgba: Vec<u8> = (0..8u8)
+		.flat_map(|i| {
+			vec![
+				0x80,
+				0x00,
+				0x80,
+				0x00,
+				0x80,
+				0x00,
+				i % 2 * 0xff,
+				i % 2 * 0xff,
+			]
+		})
+		.collect();
+	let png_data = encode_test_png(4, 2, png::ColorType::RGBA, 16, &rgba, &[])?;
+	let transparent: Vec<bool> = decode_test_png(png_data)?.iter().map(|p| p.3).collect();
+	assert_eq!(
+		transparent,
+		(0..8).map(|i| i % 2 == 0).collect::<Vec<bool>>()
+	);
+	Ok(())
+}
+
+/// RGB of each pixel of an imageset frame, or None where transparent
+fn frame_rgb(set: &VeraImageSet, x: usize, y: usize) -> Result<Vec<Option<(u8, u8, u8)>>, Error> {
+	Ok(set
+		.frame_at_coord(x, y)?
+		.data
+		.iter()
+		.map(|p| match p.is_transparent {
+			true => None,
+			false => Some((p.r, p.g, p.b)),
+		})
+		.collect())
+}
+
+#[test]
+fn imageset_gif_bmp_pcx() -> Result<(), Error> {
+	init_test_logger();
+	let config = VeraImageSetLoadConfig {
+		transparency: VeraTransparency::Alpha,
+		cull_duplicates: false,
+		..VeraImageSetLoadConfig::default()
+	};
+	let (red, green, blue) = (Some((0xff, 0, 0)), Some((0, 0xff, 0)), Some((0, 0, 0xff)));
+
+	// red and green halves, then blue over the right half which is disposed
+	// of, then a fully transparent frame over the left
+	let mut gif_data = vec![];
+	{
+		let palette = [0, 0, 0, 0xff, 0, 0, 0, 0xff, 0, 0, 0, 0xff];
+		let mut encoder = gif::Encoder::new(&mut gif_data, 4, 2, &palette)?;
+		let frames = [
+			(
+				0,
+				4,
+				vec![1, 1, 2, 2, 1, 1, 2, 2],
+				gif::DisposalMethod::Keep,
+			),
+			(2, 2, vec![3; 4], gif::DisposalMethod::Background),
+			(0, 2, vec![0; 4], gif::DisposalMethod::Keep),
+		];
+		for (left, width, indices, dispose) in frames.iter() {
+			encoder.write_frame(&gif::Frame {
+				left: *left,
+				width: *width,
+				height: 2,
+				dispose: *dispose,
+				transparent: Some(0),
+				buffer: Cow::Borrowed(indices),
+				..gif::Frame::default()
+			})?;
+		}
+	}
+	let mut set = VeraImageSet::new("gif", 2, 2);
+	set.load_from_image(gif_data, &config)?;
+	assert_eq!(set.frame_data.len(), 6);
+	assert_eq!(set.frame_data[5].id, "gif_5");
+	assert_eq!(frame_rgb(&set, 0, 0)?, vec![red; 4]);
+	assert_eq!(frame_rgb(&set, 1, 0)?, vec![green; 4]);
+	assert_eq!(frame_rgb(&set, 0, 1)?, vec![red; 4]);
+	assert_eq!(frame_rgb(&set, 1, 1)?, vec![blue; 4]);
+	assert_eq!(frame_rgb(&set, 0, 2)?, vec![red; 4]);
+	assert_eq!(frame_rgb(&set, 1, 2)?, vec![None; 4]);
+
+	// 2x1 24 bit bmp, bottom up with rows padded to 4 bytes
+	let mut bmp_data = b"BM".to_vec();
+	bmp_data.extend(&[62, 0, 0, 0, 0, 0, 0, 0, 54, 0, 0, 0, 40, 0, 0, 0]);
+	bmp_data.extend(&[2, 0, 0, 0, 1, 0, 0, 0, 1, 0, 24, 0]);
+	bmp_data.extend(&[0; 24]);
+	bmp_data.extend(&[0xff, 0, 0, 0, 0xff, 0, 0, 0]);
+	let mut set = VeraImageSet::new("bmp", 1, 1);
+	set.load_from_image(bmp_data, &config)?;
+	assert_eq!(frame_rgb(&set, 0, 0)?, vec![blue]);
+	assert_eq!(frame_rgb(&set, 1, 0)?, vec![green]);
+
+	// 2x1 24 bit pcx, with run length encoded red, green and blue planes
+	let mut pcx_data = vec![0x0a, 5, 1, 8, 0, 0, 0, 0, 1, 0];
+	pcx_data.resize(65, 0);
+	pcx_data.extend(&[3, 2, 0]);
+	pcx_data.resize(128, 0);
+	pcx_data.extend(&[0xc1, 0xff, 0, 0, 0xc1, 0xff, 0xc2, 0]);
+	let mut set = VeraImageSet::new("pcx", 1, 1);
+	set.load_from_image(pcx_data, &config)?;
+	assert_eq!(frame_rgb(&set, 0, 0)?, vec![red]);
+	assert_eq!(frame_rgb(&set, 1, 0)?, vec![green]);
+
+	assert!(set
+		.load_from_image(b"not an image".to_vec(), &config)
+		.is_err());
+	Ok(())
+}
+
+#[test]
+fn imageset_aseprite() -> Result<(), Error> {
+	init_test_logger();
+	let test_ase = include_bytes!("data/imageset/sprite-rgba.aseprite");
+	let config = VeraImageSetLoadConfig {
+		transparency: VeraTransparency::Alpha,
+		cull_duplicates: false,
+		..VeraImageSetLoadConfig::default()
+	};
+	let (red, blue) = (Some((0xff, 0, 0)), Some((0, 0, 0xff)));
+
+	// one frame per animation frame, with visible layers flattened
+	let mut set = VeraImageSet::new("ase", 2, 2);
+	set.load_from_aseprite(test_ase.to_vec(), None, &config)?;
+	assert_eq!(set.frame_data.len(), 2);
+	assert_eq!(frame_rgb(&set, 0, 0)?, vec![red, red, red, blue]);
+	assert_eq!(frame_rgb(&set, 0, 1)?[1..], [red, red, red]);
+	assert_eq!(set.frame_durations, vec![100, 200]);
+	assert_eq!(
+		set.tags,
+		vec![VeraImageSetTag {
+			name: "walk".to_owned(),
+			from: 0,
+			to: 1,
+			direction: VeraAnimationDirection::PingPong,
+		}]
+	);
+
+	// durations and tags follow each animation frame's slices
+	let mut sliced = VeraImageSet::new("ase", 1, 2);
+	sliced.load_from_aseprite(test_ase.to_vec(), None, &config)?;
+	assert_eq!(sliced.frame_durations, vec![100, 100, 200, 200]);
+	assert_eq!((sliced.tags[0].from, sliced.tags[0].to), (0, 3));
+	let limited = VeraImageSetLoadConfig {
+		limit: Some(3),
+		..config
+	};
+	sliced.load_from_aseprite(test_ase.to_vec(), None, &limited)?;
+	assert_eq!(sliced.frame_durations, vec![100, 100, 200]);
+	assert_eq!((sliced.tags[0].from, sliced.tags[0].to), (0, 2));
+
+	// a single named layer
+	set.load_from_aseprite(test_ase.to_vec(), Some("top"), &config)?;
+	assert_eq!(frame_rgb(&set, 0, 0)?, vec![None, None, None, blue]);
+	assert!(set
+		.load_from_aseprite(test_ase.to_vec(), Some("nope"), &config)
+		.is_err());
+
+	let pal_config = VeraPaletteLoadConfig {
+		direct_load: true,
+		include_defaults: false,
+		sort: false,
+		..VeraPaletteLoadConfig::default()
+	};
+	let palette = VeraPalette::derive_from_aseprite("pal", test_ase.to_vec(), &pal_config)?;
+	assert_eq!(palette.len(), 4);
+	assert_eq!(
+		palette.value_at_index(3)?,
+		VeraPaletteEntry::new(0, 0, 0xff)
+	);
+	Ok(())
+}
+
+#[test]
+fn imageset_sheet_layout() -> Result<(), Error> {
+	init_test_logger();
+	// 2x2 grid of 2x2 frames, with a 1 pixel margin and spacing, and an
+	// extra column on the left, all in white around the frames
+	let (width, height) = (8, 7);
+	let mut data = vec![];
+	for y in 0..height {
+		for x in 0..width {
+			let (cx, cy) = (x as i32 - 2, y as i32 - 1);
+			let px = match (cx >= 0 && cx % 3 < 2, cy >= 0 && cy % 3 < 2) {
+				(true, true) if cx < 5 && cy < 5 => {
+					[(cy / 3 * 2 + cx / 3 + 1) as u8 * 0x10, 0, 0, 0xff]
+				}
+				_ => [0xff, 0xff, 0xff, 0xff],
+			};
+			data.push(px);
+		}
+	}
+	let png_data = rgba_test_png(width, height, &data)?;
+
+	let mut config = VeraImageSetLoadConfig {
+		cull_duplicates: false,
+		margin: 1,
+		spacing: 1,
+		offset_x: 1,
+		..VeraImageSetLoadConfig::default()
+	};
+	let mut set = VeraImageSet::new("sheet", 2, 2);
+	set.load_from_png(png_data.clone(), &config)?;
+	assert_eq!(set.frame_data.len(), 4);
+	for i in 0..4 {
+		let expected = Some(((i as u8 + 1) * 0x10, 0, 0));
+		assert_eq!(frame_rgb(&set, i % 2, i / 2)?, vec![expected; 4]);
+	}
+
+	config.limit = Some(3);
+	set.load_from_png(png_data.clone(), &config)?;
+	assert_eq!(set.frame_data.len(), 3);
+	assert!(set.frame_at_coord(1, 1).is_err());
+
+	// no room for a single frame
+	config.offset_y = 5;
+	assert!(set.load_from_png(png_data.clone(), &config).is_err());
+	// packed sheets must still be an exact multiple of the frame size
+	assert!(set
+		.load_from_png(png_data, &VeraImageSetLoadConfig::default())
+		.is_err());
+	Ok(())
+}
+
+#[test]
+fn imageset_atlas() -> Result<(), Error> {
+	init_test_logger();
+	// red 3x2 and green 2x2 rectangles side by side
+	let mut data = vec![];
+	for _ in 0..2 {
+		for x in 0..6 {
+			let px = match x {
+				0..=2 => [0xff, 0, 0, 0xff],
+				3..=4 => [0, 0xff, 0, 0xff],
+				_ => [0, 0, 0xff, 0xff],
+			};
+			data.push(px);
+		}
+	}
+	let png_data = rgba_test_png(6, 2, &data)?;
+	let config = VeraImageSetLoadConfig {
+		transparency: VeraTransparency::Alpha,
+		cull_duplicates: false,
+		..VeraImageSetLoadConfig::default()
+	};
+	let (red, green) = (Some((0xff, 0, 0)), Some((0, 0xff, 0)));
+
+	// hash layout, with the green rectangle trimmed from a 4x4 sprite
+	let atlas = br#"{
+		"frames": {
+			"walk 0.png": {
+				"frame": { "x": 0, "y": 0, "w": 3, "h": 2 },
+				"rotated": false,
+				"trimmed": false,
+				"spriteSourceSize": { "x": 0, "y": 0, "w": 3, "h": 2 },
+				"sourceSize": { "w": 3, "h": 2 },
+				"duration": 100
+			},
+			"idle 0.png": {
+				"frame": { "x": 3, "y": 0, "w": 2, "h": 2 },
+				"rotated": false,
+				"trimmed": true,
+				"spriteSourceSize": { "x": 1, "y": 1, "w": 2, "h": 2 },
+				"sourceSize": { "w": 4, "h": 4 },
+				"duration": 50
+			}
+		},
+		"meta": {
+			"frameTags": [{ "name": "all", "from": 0, "to": 1, "direction": "pingpong" }]
+		}
+	}"#;
+	let mut set = VeraImageSet::new("atlas", 4, 4);
+	set.load_from_atlas(png_data.clone(), atlas, &config)?;
+	assert_eq!(set.frame_data.len(), 2);
+	let walk = &set.frame_data[0];
+	assert_eq!(walk.name, Some("walk_0_png".to_owned()));
+	assert_eq!(walk.source_offset, Some((0, -1)));
+	let row = [red, red, red, None];
+	let blank = [None; 4];
+	assert_eq!(
+		frame_rgb(&set, 0, 0)?,
+		[&blank[..], &row[..], &row[..], &blank[..]].concat()
+	);
+	let idle = &set.frame_data[1];
+	assert_eq!(idle.name, Some("idle_0_png".to_owned()));
+	assert_eq!(idle.source_offset, Some((0, 0)));
+	let row = [None, green, green, None];
+	assert_eq!(
+		frame_rgb(&set, 1, 0)?,
+		[&blank[..], &row[..], &row[..], &blank[..]].concat()
+	);
+	assert_eq!(set.frame_durations, vec![100, 50]);
+	assert_eq!(set.tags[0].direction, VeraAnimationDirection::PingPong);
+
+	// duplicate rectangles keep their own names and offsets when culling
+	let dups = br#"{ "frames": {
+		"a": { "frame": { "x": 0, "y": 0, "w": 2, "h": 2 } },
+		"b": { "frame": { "x": 1, "y": 0, "w": 2, "h": 2 } },
+		"c": { "frame": { "x": 3, "y": 0, "w": 2, "h": 2 } }
+	},
+	"meta": { "frameTags": [
+		{ "name": "first", "from": 0, "to": 2 },
+		{ "name": "last", "from": 2, "to": 2 }
+	] } }"#;
+	let culling = VeraImageSetLoadConfig {
+		cull_duplicates: true,
+		..config
+	};
+	set.load_from_atlas(png_data.clone(), dups, &culling)?;
+	assert_eq!(set.frame_data.len(), 3);
+	assert_eq!(set.frame_index("b"), Some(1));
+
+	// tags past the frame limit are dropped, and the rest cut short
+	let limited = VeraImageSetLoadConfig {
+		limit: Some(2),
+		..config
+	};
+	set.load_from_atlas(png_data.clone(), dups, &limited)?;
+	assert_eq!(set.frame_data.len(), 2);
+	assert_eq!(set.tags.len(), 1);
+	assert_eq!((set.tags[0].from, set.tags[0].to), (0, 1));
+
+	// array layout, unnamed frames named by index
+	let atlas = br#"{ "frames": [{ "frame": { "x": 3, "y": 0, "w": 2, "h": 2 } }] }"#;
+	set.load_from_atlas(png_data.clone(), atlas, &config)?;
+	assert_eq!(set.frame_data[0].name, Some("0".to_owned()));
+	assert!(set.frame_durations.is_empty());
+
+	let too_large = br#"{ "frames": [{ "frame": { "x": 0, "y": 0, "w": 6, "h": 2 } }] }"#;
+	assert!(set
+		.load_from_atlas(png_data.clone(), too_large, &config)
+		.is_err());
+	let outside = br#"{ "frames": [{ "frame": { "x": 4, "y": 0, "w": 3, "h": 2 } }] }"#;
+	assert!(set
+		.load_from_atlas(png_data.clone(), outside, &config)
+		.is_err());
+	let rotated =
+		br#"{ "frames": [{ "frame": { "x": 0, "y": 0, "w": 2, "h": 2 }, "rotated": true }] }"#;
+	assert!(set
+		.load_from_atlas(png_data.clone(), rotated, &config)
+		.is_err());
+	// positions and sizes from the file mustn't overflow past the bounds check
+	let overflow = br#"{ "frames": [{ "frame": { "x": 4294967295, "y": 0, "w": 2, "h": 2 } }] }"#;
+	assert!(set
+		.load_from_atlas(png_data.clone(), overflow, &config)
+		.is_err());
+	// names that become the same constant
+	let clash = br#"{ "frames": {
+		"walk 0.png": { "frame": { "x": 0, "y": 0, "w": 2, "h": 2 } },
+		"walk_0.png": { "frame": { "x": 3, "y": 0, "w": 2, "h": 2 } }
+	} }"#;
+	assert!(set
+		.load_from_atlas(png_data.clone(), clash, &config)
+		.is_err());
+	// and names that can't be one
+	let unnamed = br#"{ "frames": { "": { "frame": { "x": 0, "y": 0, "w": 2, "h": 2 } } } }"#;
+	assert!(set
+		.load_from_atlas(png_data.clone(), unnamed, &config)
+		.is_err());
+	assert!(set.load_from_atlas(png_data, b"{}", &config).is_err());
+	Ok(())
+}
+
+#[test]
+fn imageset_append() -> Result<(), Error> {
+	init_test_logger();
+	// two solid 2x2 frames side by side
+	let sheet = |left: [u8; 3], right: [u8; 3]| -> Result<Vec<u8>, Error> {
+		let row = [left, left, right, right].concat();
+		encode_test_png(
+			4,
+			2,
+			png::ColorType::RGB,
+			8,
+			&[&row[..], &row[..]].concat(),
+			&[],
+		)
+	};
+	let (red, green, blue) = ([0xff, 0, 0], [0, 0xff, 0], [0, 0, 0xff]);
+	let solid = |c: [u8; 3]| vec![Some((c[0], c[1], c[2])); 4];
+	let config = VeraImageSetLoadConfig {
+		cull_duplicates: false,
+		..VeraImageSetLoadConfig::default()
+	};
+
+	// same frames per row, so added as a further row
+	let mut set = VeraImageSet::new("append", 2, 2);
+	set.load_from_png(sheet(red, green)?, &config)?;
+	set.append_from_image(sheet(green, blue)?, &config)?;
+	assert_eq!(set.frame_data.len(), 4);
+	assert_eq!(frame_rgb(&set, 0, 1)?, solid(green));
+	assert_eq!(frame_rgb(&set, 1, 1)?, solid(blue));
+
+	// duplicates culled across the combined set, keeping the first of each
+	set.append_from_image(sheet(blue, red)?, &VeraImageSetLoadConfig::default())?;
+	assert!(set.culled);
+	let ids: Vec<&str> = set.frame_data.iter().map(|f| f.id.as_str()).collect();
+	assert_eq!(ids, ["append_0", "append_1", "append_3"]);
+
+	// appending drops formatting
+	let palette =
+		VeraPalette::derive_from_png("pal", sheet(red, green)?, &VeraPaletteLoadConfig::default())?;
+	let mut set = VeraImageSet::new("append", 2, 2);
+	set.load_from_png(sheet(red, green)?, &config)?;
+	set.format_indices(&palette, VeraPixelDepth::BPP4)?;
+	set.append_from_image(sheet(green, red)?, &VeraImageSetLoadConfig::default())?;
+	assert!(!set.formatted);
+	assert_eq!(set.palette_id, None);
+	assert_eq!(set.frame_data.len(), 2);
+	assert!(set.frame_data[0].data.iter().all(|p| p.pal_index.is_none()));
+
+	// can't mix in frames without source indices
+	let indexed = include_bytes!("data/imageset/indexed-8-1-x-8-4bpp.png");
+	let mut set = VeraImageSet::new("append", 8, 8);
+	set.load_from_indexed_png(indexed.to_vec(), &config)?;
+	assert!(set.append_from_image(indexed.to_vec(), &config).is_err());
+	Ok(())
+}
+
+#[test]
+fn imageset_animations() -> Result<(), Error> {
+	init_test_logger();
+	let sheet = |left: [u8; 3], right: [u8; 3]| -> Result<Vec<u8>, Error> {
+		let row = [left, left, right, right].concat();
+		encode_test_png(
+			4,
+			2,
+			png::ColorType::RGB,
+			8,
+			&[&row[..], &row[..]].concat(),
+			&[],
+		)
+	};
+	let (red, green, blue) = ([0xff, 0, 0], [0, 0xff, 0], [0, 0, 0xff]);
+	let config = VeraImageSetLoadConfig {
+		cull_duplicates: false,
+		..VeraImageSetLoadConfig::default()
+	};
+	// red, green, red, blue
+	let mut set = VeraImageSet::new("set", 2, 2);
+	set.load_from_png(sheet(red, green)?, &config)?;
+	set.append_from_image(sheet(red, blue)?, &config)?;
+
+	set.set_frame_name(1, Some("green"))?;
+	set.set_frame_name(3, Some("blue"))?;
+	assert_eq!(set.frame_index("blue"), Some(3));
+	assert!(set.set_frame_name(0, Some("blue")).is_err());
+	assert!(set.set_frame_name(0, Some("not a constant")).is_err());
+	assert!(set.set_frame_name(4, Some("missing")).is_err());
+	set.set_frame_name(1, None)?;
+	assert_eq!(set.frame_index("green"), None);
+	set.set_frame_name(1, Some("green"))?;
+
+	let frame = |frame, duration| VeraAnimationFrame {
+		frame: VeraFrameRef::new(frame),
+		duration,
+	};
+	let mut walk = VeraAnimation {
+		name: "walk".to_owned(),
+		frames: vec![frame(2, 8), frame(3, 4)],
+		loop_mode: VeraAnimationLoop::Loop,
+	};
+	set.set_animation(walk.clone())?;
+	// replaces the animation of the same name
+	walk.frames.insert(0, frame(1, 4));
+	walk.loop_mode = VeraAnimationLoop::PingPong;
+	set.set_animation(walk.clone())?;
+	assert_eq!(set.animations().len(), 1);
+
+	let mut bad = walk.clone();
+	bad.frames.push(frame(4, 4));
+	assert!(set.set_animation(bad).is_err());
+	let mut bad = walk.clone();
+	bad.frames[0].duration = 0;
+	assert!(set.set_animation(bad).is_err());
+	let mut bad = walk.clone();
+	bad.frames.clear();
+	assert!(set.set_animation(bad).is_err());
+
+	// culled frames are shown by their kept copy
+	set.remove_duplicate_frames()?;
+	assert_eq!(set.frame_data.len(), 3);
+	let frames: Vec<usize> = set.animations()[0]
+		.frames
+		.iter()
+		.map(|f| f.frame.frame)
+		.collect();
+	assert_eq!(frames, vec![1, 0, 2]);
+	assert_eq!(set.frame_index("blue"), Some(2));
+
+	let mut palette = VeraPalette::blank("pal");
+	for (r, g, b) in [(0, 0, 0), (0xff, 0, 0), (0, 0xff, 0), (0, 0, 0xff)].iter() {
+		palette.add_entry(true, *r, *g, *b)?;
+	}
+	let table = VeraAnimationTable::from_imageset(&set).unwrap();
+	assert_eq!(table.id, "set_anims");
+	// not formatted yet
+	assert!(table.assemble().is_err());
+
+	set.format_indices(&palette, VeraPixelDepth::BPP4)?;
+	let table = VeraAnimationTable::from_imageset(&set).unwrap();
+	let code = table.assemble()?;
+	assert_eq!(code.data_raw(), &vec![1, 0, 0, 4, 0, 0, 0, 8, 2, 0, 0, 4]);
+	let meta = code
+		.assemble_meta(AsmFormat::Ca65, false)?
+		.to_string(None)?;
+	assert!(meta.contains("SET_ANIMS_FRAME_SIZE = 2\n"));
+	assert!(meta.contains("SET_ANIMS_WALK_LEN = 3\n"));
+	assert!(meta.contains("SET_ANIMS_WALK_LOOP = 2\n"));
+	// output names are usable as constants whatever the imageset id
+	let mut named = table.clone();
+	named.id = "my-set_anims".to_owned();
+	let meta = named
+		.assemble()?
+		.assemble_meta(AsmFormat::Ca65, false)?
+		.to_string(None)?;
+	assert!(meta.contains("MY_SET_ANIMS_WALK_LEN = 3\n"));
+	let meta = set
+		.assemble()?
+		.assemble_meta(AsmFormat::Ca65, false)?
+		.to_string(None)?;
+	assert!(meta.contains("SET_GREEN = 1\n"));
+	assert!(meta.contains("SET_BLUE = 2\n"));
+
+	set.remove_animation("walk")?;
+	assert!(set.remove_animation("walk").is_err());
+	assert!(VeraAnimationTable::from_imageset(&set).is_none());
+	Ok(())
+}
+
+#[test]
+fn imageset_cull_flipped() -> Result<(), Error> {
+	init_test_logger();
+	// 2x2 frames: red in one corner of green, its flips, then blue
+	let (r, g, b) = ([0xff, 0, 0, 0xff], [0, 0xff, 0, 0xff], [0, 0, 0xff, 0xff]);
+	let top = [r, g, g, r, g, g, g, g, b, b];
+	let bottom = [g, g, g, g, r, g, g, r, b, b];
+	let png_data = rgba_test_png(10, 2, &[top, bottom].concat())?;
+	let flipped = |frame, h_flip, v_flip| VeraFrameRef {
+		frame,
+		h_flip,
+		v_flip,
+		pal_offset: None,
+	};
+
+	// only exact duplicates by default
+	let mut set = VeraImageSet::new("set", 2, 2);
+	set.load_from_png(png_data.clone(), &VeraImageSetLoadConfig::default())?;
+	assert_eq!(set.frame_data.len(), 5);
+
+	let config = VeraImageSetLoadConfig {
+		cull_flipped: true,
+		..VeraImageSetLoadConfig::default()
+	};
+	set.load_from_png(png_data.clone(), &config)?;
+	assert_eq!(set.frame_data.len(), 2);
+	assert_eq!(
+		set.source_frames,
+		vec![
+			VeraFrameRef::new(0),
+			flipped(0, true, false),
+			flipped(0, false, true),
+			flipped(0, true, true),
+			VeraFrameRef::new(1),
+		]
+	);
+
+	// animations show the kept frame, flipped as needed
+	let config = VeraImageSetLoadConfig {
+		cull_duplicates: false,
+		..VeraImageSetLoadConfig::default()
+	};
+	set.load_from_png(png_data, &config)?;
+	set.set_animation(VeraAnimation {
+		name: "spin".to_owned(),
+		frames: vec![
+			VeraAnimationFrame {
+				frame: flipped(2, true, false),
+				duration: 4,
+			},
+			VeraAnimationFrame {
+				frame: VeraFrameRef::new(4),
+				duration: 4,
+			},
+		],
+		loop_mode: VeraAnimationLoop::Loop,
+	})?;
+	set.remove_duplicate_frames_with_flips(true)?;
+	let frames: Vec<VeraFrameRef> = set.animations()[0].frames.iter().map(|f| f.frame).collect();
+	assert_eq!(frames, vec![flipped(0, true, true), VeraFrameRef::new(1)]);
+	Ok(())
+}
@@ -15,7 +15,12 @@
 use aloevera_util::init_test_logger;
 use aloevera_vera::Error;
 
-use aloevera_vera::{VeraPalette, VeraPaletteEntry, VeraPaletteLoadConfig};
+use aloevera_vera::{AsmFormat, Assemblable};
+use aloevera_vera::{
+	VeraPalette, VeraPaletteCycle, VeraPaletteCycleDirection, VeraPaletteCycleTable,
+	VeraPaletteEntry, VeraPaletteFade, VeraPaletteFadeTarget, VeraPaletteLoadConfig,
+	VeraPalettePatch, VeraPixelDepth,
+};
 
 #[test]
 fn palette_8bpp_rgb() -> Result<(), Error> {
@@ -25,6 +30,7 @@ fn palette_8bpp_rgb() -> Result<(), Error> {
 		direct_load: true,
 		include_defaults: false,
 		sort: false,
+		..VeraPaletteLoadConfig::default()
 	};
 	let palette = VeraPalette::derive_from_png("pal", test_png.to_vec(), &pal_config)?;
 
@@ -58,6 +64,7 @@ fn palette_8bpp_indexed() -> Result<(), Error> {
 		direct_load: true,
 		include_defaults: false,
 		sort: false,
+		..VeraPaletteLoadConfig::default()
 	};
 	let palette = VeraPalette::derive_from_png("pal", test_png.to_vec(), &pal_config)?;
 
@@ -91,6 +98,7 @@ fn palette_gpl() -> Result<(), Error> {
 		direct_load: true,
 		include_defaults: false,
 		sort: false,
+		..VeraPaletteLoadConfig::default()
 	};
 	let palette = VeraPalette::derive_from_gpl("pal", test_gpl.to_vec(), &pal_config)?;
 	assert_eq!(
@@ -119,3 +127,369 @@ fn palette_gpl() -> Result<(), Error> {
 
 	Ok(())
 }
+
+#[test]
+fn palette_other_formats() -> Result<(), Error> {
+	init_test_logger();
+	let pal_config = VeraPaletteLoadConfig {
+		direct_load: true,
+		include_defaults: false,
+		sort: false,
+		..VeraPaletteLoadConfig::default()
+	};
+	// all fixtures hold the same colors as the gimp palette
+	let test_gpl = include_bytes!("data/palette/palette-gimp.gpl");
+	let gpl = VeraPalette::derive_from_gpl("pal", test_gpl.to_vec(), &pal_config)?;
+
+	let test_jasc = include_bytes!("data/palette/palette-jasc.pal");
+	let test_act = include_bytes!("data/palette/palette-adobe.act");
+	let test_aco = include_bytes!("data/palette/palette-adobe.aco");
+	let test_hex = include_bytes!("data/palette/palette-lospec.hex");
+	let palettes = [
+		VeraPalette::derive_from_jasc("pal", test_jasc.to_vec(), &pal_config)?,
+		VeraPalette::derive_from_act("pal", test_act.to_vec(), &pal_config)?,
+		VeraPalette::derive_from_aco("pal", test_aco.to_vec(), &pal_config)?,
+		VeraPalette::derive_from_hex("pal", test_hex.to_vec(), &pal_config)?,
+	];
+	for palette in palettes.iter() {
+		assert_eq!(palette.len(), 16);
+		for i in 0..gpl.len() {
+			assert_eq!(palette.value_at_index(i)?, gpl.value_at_index(i)?);
+		}
+	}
+
+	// malformed files
+	assert!(VeraPalette::derive_from_jasc("pal", test_gpl.to_vec(), &pal_config).is_err());
+	assert!(VeraPalette::derive_from_act("pal", test_gpl.to_vec(), &pal_config).is_err());
+	assert!(VeraPalette::derive_from_aco("pal", test_gpl.to_vec(), &pal_config).is_err());
+	assert!(VeraPalette::derive_from_hex("pal", test_gpl.to_vec(), &pal_config).is_err());
+
+	Ok(())
+}
+
+#[test]
+fn palette_export() -> Result<(), Error> {
+	init_test_logger();
+	let pal_config = VeraPaletteLoadConfig {
+		direct_load: true,
+		include_defaults: false,
+		sort: false,
+		..VeraPaletteLoadConfig::default()
+	};
+	let test_gpl = include_bytes!("data/palette/palette-gimp.gpl");
+	let palette = VeraPalette::derive_from_gpl("pal", test_gpl.to_vec(), &pal_config)?;
+	// 12 bit values expanded back out, e.g. 136 102 17 -> #861
+	assert_eq!(palette.to_rgb8()[4], (0x88, 0x66, 0x11));
+
+	// each export should load back in to the same palette
+	let exports = [
+		VeraPalette::derive_from_gpl("pal", palette.to_gpl(), &pal_config)?,
+		VeraPalette::derive_from_jasc("pal", palette.to_jasc(), &pal_config)?,
+		VeraPalette::derive_from_png("pal", palette.to_png_swatch(4)?, &pal_config)?,
+	];
+	for p in exports.iter() {
+		assert_eq!(p.len(), palette.len());
+		for i in 0..palette.len() {
+			assert_eq!(p.value_at_index(i)?, palette.value_at_index(i)?);
+		}
+	}
+
+	// swatch is 16 entries per row, 4 pixels per entry here
+	let swatch = palette.to_png_swatch(4)?;
+	let decoder = png::Decoder::new(&swatch[..]);
+	let (info, _) = decoder.read_info()?;
+	assert_eq!((info.width, info.height), (64, 4));
+
+	assert!(VeraPalette::blank("empty").to_png_swatch(4).is_err());
+	Ok(())
+}
+
+#[test]
+fn palette_fade() -> Result<(), Error> {
+	init_test_logger();
+	let mut palette = VeraPalette::blank("pal");
+	palette.add_entry(true, 0xf0, 0x80, 0x10)?;
+	palette.add_entry(true, 0x00, 0x00, 0x00)?;
+
+	let black = VeraPaletteFadeTarget::Color(VeraPaletteEntry::new(0, 0, 0));
+	let fade = VeraPaletteFade::from_palette("fade", &palette, black, 4)?;
+	assert_eq!(fade.step_count(), 4);
+	assert_eq!(fade.size(), 4 * 2 * 2);
+	assert_eq!(fade.step_at(0)?[0], VeraPaletteEntry { r: 11, g: 6, b: 1 });
+	assert_eq!(fade.step_at(1)?[0], VeraPaletteEntry { r: 8, g: 4, b: 1 });
+	assert_eq!(fade.step_at(3)?[0], VeraPaletteEntry { r: 0, g: 0, b: 0 });
+	assert!(fade
+		.step_at(4)
+		.unwrap_err()
+		.to_string()
+		.starts_with("Palette fade has no step at index 4"));
+
+	// cross-fade, each entry heading to its counterpart
+	let mut other = VeraPalette::blank("other");
+	other.add_entry(true, 0x00, 0x80, 0xf0)?;
+	other.add_entry(true, 0xf0, 0xf0, 0xf0)?;
+	let target = VeraPaletteFadeTarget::Palette(&other);
+	let fade = VeraPaletteFade::from_palette("fade", &palette, target, 2)?;
+	assert_eq!(fade.step_at(0)?[1], VeraPaletteEntry { r: 8, g: 8, b: 8 });
+	assert_eq!(
+		fade.step_at(1)?,
+		&[other.value_at_index(0)?, other.value_at_index(1)?]
+	);
+
+	let code = fade.assemble()?;
+	assert_eq!(
+		code.data_raw(),
+		&vec![0x88, 0x08, 0x88, 0x08, 0x8f, 0x00, 0xff, 0x0f]
+	);
+
+	other.add_entry(true, 0x10, 0x10, 0x10)?;
+	let target = VeraPaletteFadeTarget::Palette(&other);
+	assert!(VeraPaletteFade::from_palette("fade", &palette, target, 2).is_err());
+	assert!(VeraPaletteFade::from_palette("fade", &palette, black, 0).is_err());
+	Ok(())
+}
+
+#[test]
+fn palette_cycles() -> Result<(), Error> {
+	init_test_logger();
+	let mut palette = VeraPalette::blank("pal");
+	for i in 0..8u8 {
+		palette.add_entry(true, i << 4, 0, 0)?;
+	}
+	let mut cycle = VeraPaletteCycle {
+		name: "water".to_owned(),
+		start: 2,
+		len: 4,
+		direction: VeraPaletteCycleDirection::Forward,
+		frames_per_step: 4,
+	};
+	// after one step, each colour has moved up one index
+	assert_eq!(cycle.source_index(3, 1), 2);
+	assert_eq!(cycle.source_index(2, 1), 5);
+	assert_eq!(cycle.source_index(2, 4), 2);
+	assert_eq!(cycle.source_index(7, 1), 7);
+	palette.set_cycle(cycle.clone())?;
+
+	cycle.direction = VeraPaletteCycleDirection::Backward;
+	assert_eq!(cycle.source_index(5, 1), 2);
+	// replaces the cycle of the same name
+	palette.set_cycle(cycle.clone())?;
+	assert_eq!(palette.cycles().len(), 1);
+
+	let mut bad = cycle.clone();
+	bad.start = 6;
+	assert!(palette.set_cycle(bad.clone()).is_err());
+	bad.start = 0;
+	bad.name = "not a constant".to_owned();
+	assert!(palette.set_cycle(bad).is_err());
+
+	// one row per step
+	let preview = palette.to_png_cycle_preview("water", 2)?;
+	let decoder = png::Decoder::new(&preview[..]);
+	let (info, mut reader) = decoder.read_info()?;
+	assert_eq!((info.width, info.height), (8, 8));
+	let mut buf = vec![0; info.buffer_size()];
+	reader.next_frame(&mut buf)?;
+	// decoded as rgb, the second step of the backward cycle is 3, 4, 5, 2
+	let row = &buf[2 * 8 * 3..3 * 8 * 3];
+	let reds: Vec<u8> = row.iter().step_by(6).copied().collect();
+	assert_eq!(reds, vec![0x33, 0x44, 0x55, 0x22]);
+
+	let table = VeraPaletteCycleTable::from_palette(&palette).unwrap();
+	assert_eq!(table.id, "pal_cycles");
+	let code = table.assemble()?;
+	assert_eq!(code.data_raw(), &vec![2, 4, 1, 4]);
+	let meta = code
+		.assemble_meta(AsmFormat::Ca65, false)?
+		.to_string(None)?;
+	assert!(meta.contains("PAL_CYCLES_WATER_START = 2\n"));
+
+	// output names are usable as constants whatever the palette id
+	let mut named = palette.clone();
+	named.id = "my-pal".to_owned();
+	let meta = VeraPaletteCycleTable::from_palette(&named)
+		.unwrap()
+		.assemble()?
+		.assemble_meta(AsmFormat::Ca65, false)?
+		.to_string(None)?;
+	assert!(meta.contains("MY_PAL_CYCLES_COUNT = 1\n"));
+
+	// cycles follow their entries through edits
+	let mut edited = palette.clone();
+	edited.swap_entries(0, 7)?;
+	assert_eq!(edited.cycles()[0].start, 2);
+	edited.move_entry(0, 7)?;
+	assert_eq!(edited.cycles()[0].start, 1);
+	// and are dropped once their run is broken up
+	edited.swap_entries(1, 3)?;
+	assert!(edited.cycles().is_empty());
+	let mut edited = palette.clone();
+	edited.remove_entry(7)?;
+	edited.remove_entry(6)?;
+	edited.remove_entry(5)?;
+	assert!(edited.cycles().is_empty());
+
+	// and a table is checked against the palette again when assembled
+	let mut table = VeraPaletteCycleTable::from_palette(&palette).unwrap();
+	table.palette_len = 3;
+	assert!(table.assemble().is_err());
+
+	palette.remove_cycle("water")?;
+	assert!(palette.remove_cycle("water").is_err());
+	assert!(VeraPaletteCycleTable::from_palette(&palette).is_none());
+	Ok(())
+}
+
+#[test]
+fn palette_patch() -> Result<(), Error> {
+	init_test_logger();
+	let base = VeraPalette::default();
+	let mut palette = VeraPalette::default();
+	palette.id = "pal".to_owned();
+	palette.set_entry(3, 0x10, 0x20, 0x30)?;
+	palette.set_entry(4, 0x40, 0x50, 0x60)?;
+	palette.set_entry(10, 0xf0, 0xf0, 0xf0)?;
+	// same as the default, so not part of a run
+	let e = base.value_at_index(5)?;
+	palette.set_entry(5, e.r << 4, e.g << 4, e.b << 4)?;
+
+	let patch = VeraPalettePatch::from_palettes(&palette, &base)?;
+	assert_eq!(patch.id, "pal_patch");
+	assert_eq!(patch.changed_count(), 3);
+	let starts: Vec<usize> = patch.runs().iter().map(|r| r.0).collect();
+	assert_eq!(starts, vec![3, 10]);
+	let code = patch.assemble()?;
+	assert_eq!(
+		code.data_raw(),
+		&vec![3, 2, 0x23, 0x01, 0x56, 0x04, 10, 1, 0xff, 0x0f, 0, 0]
+	);
+	assert_eq!(patch.size(), code.data_raw().len());
+
+	// output names are usable as constants whatever the palette id
+	palette.id = "my-pal".to_owned();
+	let meta = VeraPalettePatch::from_palettes(&palette, &base)?
+		.assemble()?
+		.assemble_meta(AsmFormat::Ca65, false)?
+		.to_string(None)?;
+	assert!(meta.contains("MY_PAL_PATCH_RECORDS = 2\n"));
+
+	// runs are split so counts fit in a byte
+	let patch = VeraPalettePatch::from_palettes(&base, &VeraPalette::blank("empty"))?;
+	let lens: Vec<usize> = patch.runs().iter().map(|r| r.1.len()).collect();
+	assert_eq!(lens, vec![255, 1]);
+
+	// nothing changed
+	let patch = VeraPalettePatch::from_palettes(&base, &base)?;
+	assert_eq!(patch.assemble()?.data_raw(), &vec![0, 0]);
+	Ok(())
+}
+
+#[test]
+fn palette_import_at() -> Result<(), Error> {
+	init_test_logger();
+	let pal_config = VeraPaletteLoadConfig {
+		direct_load: true,
+		include_defaults: false,
+		sort: false,
+		..VeraPaletteLoadConfig::default()
+	};
+	let test_gpl = include_bytes!("data/palette/palette-gimp.gpl");
+	let sub_palette = VeraPalette::derive_from_gpl("sub", test_gpl.to_vec(), &pal_config)?;
+
+	// into the third sub-palette of an empty palette
+	let mut palette = VeraPalette::blank("pal");
+	palette.import_at(32, &sub_palette, false)?;
+	assert_eq!(palette.len(), 48);
+	assert!(!palette.is_slot_occupied(0));
+	assert!(!palette.is_slot_occupied(31));
+	assert!(palette.is_slot_occupied(32));
+	assert!(palette.is_slot_occupied(47));
+	assert!(!palette.is_slot_occupied(48));
+	assert_eq!(
+		palette.value_at_index(36)?,
+		VeraPaletteEntry { r: 8, g: 6, b: 1 }
+	);
+	// empty slots aren't matched against
+	assert_eq!(palette.index_of_rgb(0, 0, 0), Some(32));
+	assert_eq!(palette.all_indices_of_rgb(0, 0, 0).1, vec![32]);
+
+	// then the first
+	palette.import_at(0, &sub_palette, false)?;
+	assert!(palette.is_slot_occupied(0));
+	assert!(!palette.is_slot_occupied(16));
+	assert_eq!(palette.index_of_rgb(0, 0, 0), Some(0));
+
+	// conflicts
+	assert!(palette.import_at(40, &sub_palette, false).is_err());
+	palette.import_at(40, &sub_palette, true)?;
+	assert_eq!(palette.len(), 56);
+	assert_eq!(
+		palette.value_at_index(44)?,
+		VeraPaletteEntry { r: 8, g: 6, b: 1 }
+	);
+
+	// out of range
+	assert!(palette.import_at(250, &sub_palette, true).is_err());
+
+	// sorting moves the empty slots to the end along with their entries
+	palette.sort();
+	assert_eq!(palette.len(), 56);
+	assert!((0..40).all(|i| palette.is_slot_occupied(i)));
+	assert!((40..56).all(|i| !palette.is_slot_occupied(i)));
+	assert_eq!(palette.index_of_rgb(0, 0, 0), Some(0));
+
+	Ok(())
+}
+
+#[test]
+fn palette_quantize_truecolor() -> Result<(), Error> {
+	init_test_logger();
+	let test_png = include_bytes!("data/palette/palette-truecolor-gradient.png");
+	// too many colours to load directly
+	let pal_config = VeraPaletteLoadConfig::default();
+	assert!(VeraPalette::derive_from_png("pal", test_png.to_vec(), &pal_config).is_err());
+
+	let pal_config = VeraPaletteLoadConfig {
+		include_defaults: false,
+		quantize: Some(VeraPixelDepth::BPP4),
+		..VeraPaletteLoadConfig::default()
+	};
+	let (palette_16, report_16) =
+		VeraPalette::quantize_from_png("pal", test_png.to_vec(), &pal_config)?;
+	println!("{}", palette_16);
+	println!("{}", report_16);
+	assert_eq!(palette_16.len(), 16);
+	assert_eq!(report_16.palette_colors, 16);
+	assert_eq!(report_16.source_colors, 64 * 64);
+
+	let pal_config = VeraPaletteLoadConfig {
+		quantize: Some(VeraPixelDepth::BPP8),
+		..VeraPaletteLoadConfig::default()
+	};
+	let (palette_256, report_256) =
+		VeraPalette::quantize_from_png("pal", test_png.to_vec(), &pal_config)?;
+	println!("{}", report_256);
+	assert!(palette_256.len() > 16 && palette_256.len() <= 256);
+	// defaults retained
+	assert_eq!(palette_256.index_of_rgb(0, 0, 0), Some(0));
+	assert!(report_256.mean_error < report_16.mean_error);
+
+	// 2bpp, with black and white taking up half the palette
+	let pal_config = VeraPaletteLoadConfig {
+		sort: false,
+		quantize: Some(VeraPixelDepth::BPP2),
+		..VeraPaletteLoadConfig::default()
+	};
+	let palette = VeraPalette::derive_from_png("pal", test_png.to_vec(), &pal_config)?;
+	assert_eq!(palette.len(), 4);
+	assert_eq!(
+		palette.value_at_index(1)?,
+		VeraPaletteEntry {
+			r: 15,
+			g: 15,
+			b: 15
+		}
+	);
+
+	Ok(())
+}
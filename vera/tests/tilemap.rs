@@ -17,10 +17,14 @@ use aloevera_vera::Error;
 
 use aloevera_vera::{AsmFormat, Assemblable};
 use aloevera_vera::{
+	VeraAnimation, VeraAnimationFrame, VeraAnimationLoop, VeraAnimationTable, VeraFrameRef,
 	VeraImageSet, VeraImageSetLoadConfig, VeraPalette, VeraPaletteLoadConfig, VeraPixelDepth,
-	VeraTileMap, VeraTileMapDim, VeraTileMapEntry, VeraTileMapMode,
+	VeraTileMap, VeraTileMapDim, VeraTileMapEntry, VeraTileMapMode, VeraTransparency,
 };
 
+mod common;
+use common::rgba_test_png;
+
 #[test]
 fn tilemap_32_x_32_x_16_8bpp() -> Result<(), Error> {
 	init_test_logger();
@@ -279,3 +283,369 @@ fn tilemap_64_x_32_x_8_4bpp() -> Result<(), Error> {
 
 	Ok(())
 }
+
+/// Encode 8x8 tiles side by side as a png. Each tile is the `ground`
+/// colour, with a `marker` coloured mark along its top or bottom edge,
+/// at its left or right, or blue for `None`
+fn flipped_tiles_png(
+	tiles: &[Option<(bool, bool)>],
+	marker: [u8; 3],
+	ground: [u8; 3],
+) -> Result<Vec<u8>, Error> {
+	let tiles: Vec<_> = tiles.iter().map(|t| (*t, marker, ground)).collect();
+	recoloured_tiles_png(&tiles)
+}
+
+/// A tile as above, with its marker and ground colours
+type RecolouredTile = (Option<(bool, bool)>, [u8; 3], [u8; 3]);
+
+/// As above, with the marker and ground colours given per tile
+fn recoloured_tiles_png(tiles: &[RecolouredTile]) -> Result<Vec<u8>, Error> {
+	let width = tiles.len() * 8;
+	let mut data = vec![];
+	for y in 0..8 {
+		for (t, marker, ground) in tiles.iter() {
+			for x in 0..8 {
+				let [r, g, b] = match t {
+					None => [0, 0, 0xff],
+					Some((h, v)) => {
+						let mx = if *h { x >= 6 } else { x < 2 };
+						let my = if *v { y == 7 } else { y == 0 };
+						match mx && my {
+							true => *marker,
+							false => *ground,
+						}
+					}
+				};
+				data.push([r, g, b, 0xff]);
+			}
+		}
+	}
+	rgba_test_png(width as u32, 8, &data)
+}
+
+#[test]
+fn tilemap_cull_flipped() -> Result<(), Error> {
+	init_test_logger();
+	let (tile, h, v, blank) = (
+		Some((false, false)),
+		Some((true, false)),
+		Some((false, true)),
+		None,
+	);
+	let (red, green) = ([0xff, 0, 0], [0, 0xff, 0]);
+	let setdata = flipped_tiles_png(&[tile, h, v, blank], red, green)?;
+	let mut palette = VeraPalette::blank("pal");
+	let entries = [
+		(0, 0, 0),
+		(0xff, 0, 0),
+		(0, 0xff, 0),
+		(0, 0, 0xff),
+		(0xff, 0xff, 0xff),
+	];
+	for (r, g, b) in entries.iter() {
+		palette.add_entry(true, *r, *g, *b)?;
+	}
+
+	let config = VeraImageSetLoadConfig {
+		cull_flipped: true,
+		..VeraImageSetLoadConfig::default()
+	};
+	let mut set = VeraImageSet::new("tileset", 8, 8);
+	set.load_from_png(setdata, &config)?;
+	assert_eq!(set.frame_data.len(), 2);
+	set.format_indices(&palette, VeraPixelDepth::BPP4)?;
+
+	let mut tilemap = VeraTileMap::init_from_imageset(
+		"tilemap",
+		VeraTileMapMode::Tile4BPP,
+		VeraTileMapDim::Dim32,
+		VeraTileMapDim::Dim32,
+		&set,
+	)?;
+	let hv = Some((true, true));
+	let mapdata = flipped_tiles_png(&[v, blank, h, tile, hv], red, green)?;
+	tilemap.load_from_png(mapdata, None, 0, 0, 0)?;
+	assert_eq!(
+		tilemap.get_tiles(),
+		&vec![
+			VeraTileMapEntry::Tile234(0, 0, 0, 1),
+			VeraTileMapEntry::Tile234(1, 0, 0, 0),
+			VeraTileMapEntry::Tile234(0, 0, 1, 0),
+			VeraTileMapEntry::Tile234(0, 0, 0, 0),
+			VeraTileMapEntry::Tile234(0, 0, 1, 1),
+		]
+	);
+
+	// text modes can't flip tiles
+	let mut set = VeraImageSet::new("tileset", 8, 8);
+	let (white, black) = ([0xff, 0xff, 0xff], [0, 0, 0]);
+	set.load_from_png(flipped_tiles_png(&[tile], white, black)?, &config)?;
+	set.format_indices(&palette, VeraPixelDepth::BPP1)?;
+	let mut tilemap = VeraTileMap::init_from_imageset(
+		"tilemap",
+		VeraTileMapMode::TextBPP1_256,
+		VeraTileMapDim::Dim32,
+		VeraTileMapDim::Dim32,
+		&set,
+	)?;
+	let mapdata = flipped_tiles_png(&[tile], white, black)?;
+	tilemap.load_from_png_with_config(mapdata, Some(&palette), 0, 0, 0, &config)?;
+	let mapdata = flipped_tiles_png(&[h], white, black)?;
+	assert!(tilemap
+		.load_from_png_with_config(mapdata, Some(&palette), 0, 0, 0, &config)
+		.is_err());
+	Ok(())
+}
+
+#[test]
+fn tilemap_cull_recoloured() -> Result<(), Error> {
+	init_test_logger();
+	let (tile, h, v) = (
+		Some((false, false)),
+		Some((true, false)),
+		Some((false, true)),
+	);
+	let (red, green) = ([0xff, 0, 0], [0, 0xff, 0]);
+	let (blue, white) = ([0, 0, 0xff], [0xff, 0xff, 0xff]);
+	// same shape drawn in the first and second 16 colour rows
+	let mut palette = VeraPalette::blank("pal");
+	for row in [(red, green), (blue, white)].iter() {
+		palette.add_entry(true, 0, 0, 0)?;
+		for c in [row.0, row.1].iter() {
+			palette.add_entry(true, c[0], c[1], c[2])?;
+		}
+		for _ in 3..16 {
+			palette.add_entry(true, 0, 0, 0)?;
+		}
+	}
+	let setdata =
+		recoloured_tiles_png(&[(tile, red, green), (tile, blue, white), (h, blue, white)])?;
+	let mut set = VeraImageSet::new("tileset", 8, 8);
+	set.load_from_png(setdata, &VeraImageSetLoadConfig::default())?;
+	assert!(set.remove_recoloured_frames(false).is_err());
+	assert_eq!(set.frame_data.len(), 3);
+	set.format_indices(&palette, VeraPixelDepth::BPP4)?;
+	assert_eq!(set.frame_data[1].pal_offset, 16);
+
+	// without flips, only the unflipped copy goes
+	let mut unflipped = set.clone();
+	unflipped.remove_recoloured_frames(false)?;
+	assert_eq!(unflipped.frame_data.len(), 2);
+
+	let steps: Vec<_> = (0..3)
+		.map(|i| VeraAnimationFrame {
+			frame: VeraFrameRef::new(i),
+			duration: 4,
+		})
+		.collect();
+	set.set_animation(VeraAnimation {
+		name: "glow".to_owned(),
+		frames: steps,
+		loop_mode: VeraAnimationLoop::Loop,
+	})?;
+	set.remove_recoloured_frames(true)?;
+	assert_eq!(set.frame_data.len(), 1);
+	// palette offset / 16 in the top of each step's flip byte
+	let code = VeraAnimationTable::from_imageset(&set)
+		.unwrap()
+		.assemble()?;
+	assert_eq!(
+		code.data_raw(),
+		&vec![0, 0, 0, 4, 0, 0, 0x10, 4, 0, 0, 0x11, 4]
+	);
+	let recoloured = VeraFrameRef {
+		frame: 0,
+		h_flip: false,
+		v_flip: false,
+		pal_offset: Some(16),
+	};
+	assert_eq!(
+		set.source_frames,
+		vec![
+			VeraFrameRef::new(0),
+			recoloured,
+			VeraFrameRef {
+				h_flip: true,
+				..recoloured
+			},
+		]
+	);
+
+	let mut tilemap = VeraTileMap::init_from_imageset(
+		"tilemap",
+		VeraTileMapMode::Tile4BPP,
+		VeraTileMapDim::Dim32,
+		VeraTileMapDim::Dim32,
+		&set,
+	)?;
+	let mapdata =
+		recoloured_tiles_png(&[(tile, blue, white), (tile, red, green), (v, blue, white)])?;
+	tilemap.load_from_png(mapdata, None, 0, 0, 0)?;
+	assert_eq!(
+		tilemap.get_tiles(),
+		&vec![
+			VeraTileMapEntry::Tile234(0, 16, 0, 0),
+			VeraTileMapEntry::Tile234(0, 0, 0, 0),
+			VeraTileMapEntry::Tile234(0, 16, 0, 1),
+		]
+	);
+
+	// the culled frames only match the kept one as formatted, so the
+	// set can't be formatted again, and palette edits unformat it
+	assert!(set
+		.clone()
+		.format_indices(&palette, VeraPixelDepth::BPP4)
+		.is_err());
+	let index_map: Vec<_> = (0..palette.len()).map(Some).collect();
+	assert!(!set.reindex(&index_map));
+	assert!(!set.formatted);
+	assert!(set.format_indices(&palette, VeraPixelDepth::BPP4).is_err());
+
+	// nor appended to, keeping its frames as they were
+	let frames = set.frame_data.clone();
+	let more = recoloured_tiles_png(&[(tile, red, green)])?;
+	assert!(set
+		.append_from_image(more, &VeraImageSetLoadConfig::default())
+		.is_err());
+	assert_eq!(set.frame_data, frames);
+	Ok(())
+}
+
+#[test]
+fn tilemap_transparent_tiles() -> Result<(), Error> {
+	init_test_logger();
+	// a red corner over transparency, then solid green, with whatever
+	// colour the transparent pixels happen to hold in each image
+	let tiles_png = |tiles: &[usize], hidden: [u8; 4]| {
+		let mut data = vec![];
+		for y in 0..8 {
+			for t in tiles.iter() {
+				for x in 0..8 {
+					data.push(match (t, x < 2 && y < 2) {
+						(0, true) => [0xff, 0, 0, 0xff],
+						(0, false) => hidden,
+						_ => [0, 0xff, 0, 0xff],
+					});
+				}
+			}
+		}
+		rgba_test_png(tiles.len() as u32 * 8, 8, &data)
+	};
+	let mut palette = VeraPalette::blank("pal");
+	palette.add_entry(true, 0, 0, 0)?;
+	palette.add_entry(true, 0xff, 0, 0)?;
+	palette.add_entry(true, 0, 0xff, 0)?;
+
+	let config = VeraImageSetLoadConfig {
+		transparency: VeraTransparency::Alpha,
+		..VeraImageSetLoadConfig::default()
+	};
+	let mut set = VeraImageSet::new("tileset", 8, 8);
+	set.load_from_png(tiles_png(&[0, 1], [0, 0, 0, 0])?, &config)?;
+	set.format_indices(&palette, VeraPixelDepth::BPP4)?;
+	let mut tilemap = VeraTileMap::init_from_imageset(
+		"tilemap",
+		VeraTileMapMode::Tile4BPP,
+		VeraTileMapDim::Dim32,
+		VeraTileMapDim::Dim32,
+		&set,
+	)?;
+
+	// transparent pixels only match when loaded the same way
+	let mapdata = tiles_png(&[1, 0], [0xff, 0xff, 0xff, 0])?;
+	assert!(tilemap
+		.load_from_png(mapdata.clone(), None, 0, 0, 0)
+		.is_err());
+	tilemap.load_from_png_with_config(mapdata, None, 0, 0, 0, &config)?;
+	assert_eq!(
+		tilemap.get_tiles(),
+		&vec![
+			VeraTileMapEntry::Tile234(1, 0, 0, 0),
+			VeraTileMapEntry::Tile234(0, 0, 0, 0),
+		]
+	);
+	Ok(())
+}
+
+#[test]
+fn tilemap_palette_edit() -> Result<(), Error> {
+	init_test_logger();
+	// solid red and solid green tiles
+	let tiles_png = |tiles: &[usize]| {
+		let mut data = vec![];
+		for _ in 0..8 {
+			for t in tiles.iter() {
+				let c = match t {
+					0 => [0xff, 0, 0, 0xff],
+					_ => [0, 0xff, 0, 0xff],
+				};
+				data.extend_from_slice(&[c; 8]);
+			}
+		}
+		rgba_test_png(tiles.len() as u32 * 8, 8, &data)
+	};
+	let mut palette = VeraPalette::blank("pal");
+	palette.add_entry(true, 0, 0, 0)?;
+	palette.add_entry(true, 0xff, 0, 0)?;
+	palette.add_entry(true, 0, 0xff, 0)?;
+	for i in 3..32 {
+		palette.add_entry(true, 0, 0, i * 8)?;
+	}
+	let mut set = VeraImageSet::new("tileset", 8, 8);
+	set.load_from_png(tiles_png(&[0, 1])?, &VeraImageSetLoadConfig::default())?;
+	set.format_indices(&palette, VeraPixelDepth::BPP4)?;
+	let mut tilemap = VeraTileMap::init_from_imageset(
+		"tilemap",
+		VeraTileMapMode::Tile4BPP,
+		VeraTileMapDim::Dim32,
+		VeraTileMapDim::Dim32,
+		&set,
+	)?;
+	tilemap.load_from_png(tiles_png(&[1, 0])?, None, 0, 0, 0)?;
+
+	// moving green into the next 16 entries carries its tiles along
+	let index_map = palette.move_entry(2, 20)?;
+	assert!(set.reindex(&index_map));
+	assert_eq!(set.frame_at(1)?.pal_offset, 16);
+	assert!(tilemap.update_pal_offsets(&set));
+	assert_eq!(
+		tilemap.get_tiles(),
+		&vec![
+			VeraTileMapEntry::Tile234(1, 16, 0, 0),
+			VeraTileMapEntry::Tile234(0, 0, 0, 0),
+		]
+	);
+	let code = tilemap.assemble()?;
+	assert_eq!(code.data_raw()[..4], [1, 0x10, 0, 0]);
+	tilemap.load_from_png(tiles_png(&[1, 1])?, None, 0, 0, 0)?;
+	assert_eq!(
+		tilemap.get_tiles(),
+		&vec![
+			VeraTileMapEntry::Tile234(1, 16, 0, 0),
+			VeraTileMapEntry::Tile234(1, 16, 0, 0),
+		]
+	);
+
+	// removing red leaves the map stale until initialized again
+	let index_map = palette.remove_entry(1)?;
+	assert!(!set.reindex(&index_map));
+	tilemap.mark_stale();
+	assert!(tilemap.is_stale());
+	assert!(tilemap.assemble().is_err());
+	assert!(tilemap
+		.load_from_png(tiles_png(&[1, 0])?, None, 0, 0, 0)
+		.is_err());
+	palette.add_entry(true, 0xff, 0, 0)?;
+	set.format_indices(&palette, VeraPixelDepth::BPP4)?;
+	let mut tilemap = VeraTileMap::init_from_imageset(
+		"tilemap",
+		VeraTileMapMode::Tile4BPP,
+		VeraTileMapDim::Dim32,
+		VeraTileMapDim::Dim32,
+		&set,
+	)?;
+	tilemap.load_from_png(tiles_png(&[1, 0])?, None, 0, 0, 0)?;
+	tilemap.assemble()?;
+	Ok(())
+}